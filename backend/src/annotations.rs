@@ -0,0 +1,273 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::history;
+use crate::models::{
+    Annotation, AnnotationQuery, AnnotationSource, NewAnnotation, Reboot, ShutdownKind,
+};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Utc};
+use log::info;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const ANNOTATIONS_FILE: &str = "annotations.json";
+const MAX_TEXT_BYTES: usize = 1024;
+const MAX_TAGS: usize = 16;
+const MAX_TAG_BYTES: usize = 64;
+const MAX_ANNOTATIONS: usize = 10_000; // the oldest are dropped beyond this
+
+#[derive(Default, Serialize, Deserialize)]
+struct AnnotationStoreInner {
+    next_id: u64,
+    /// Ordered by start.
+    annotations: Vec<Annotation>,
+}
+
+/// Notes on the history timeline, from users or from events pi-dash noticed
+/// itself, kept in `/data/annotations.json`.
+#[derive(Clone)]
+pub struct AnnotationStore {
+    inner: Arc<RwLock<AnnotationStoreInner>>,
+}
+
+impl AnnotationStore {
+    pub fn new() -> Self {
+        AnnotationStore {
+            inner: Arc::new(RwLock::new(Self::load_from_disk().unwrap_or_default())),
+        }
+    }
+
+    fn load_from_disk() -> Option<AnnotationStoreInner> {
+        let path = config::data_path(ANNOTATIONS_FILE);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(inner) => {
+                println!("Loaded annotations from {}", path.display());
+                Some(inner)
+            }
+            Err(e) => {
+                eprintln!("Failed to parse annotations {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Annotations change rarely, so every change is written through. The
+    /// file is replaced atomically so a power cut cannot leave it half-written.
+    fn save_to_disk(inner: &AnnotationStoreInner) {
+        let path = config::data_path(ANNOTATIONS_FILE);
+        match serde_json::to_vec(inner) {
+            Ok(json) => {
+                if let Err(e) = writes::replace(&path, &json) {
+                    eprintln!("Failed to write annotations to {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize annotations: {}", e),
+        }
+    }
+
+    /// Mark this start of pi-dash at `at`, and the downtime of the reboot
+    /// before it when there was one.
+    pub fn started(&self, at: DateTime<Utc>, reboot: Option<&Reboot>) {
+        if let Some(reboot) = reboot {
+            let (text, tags) = match reboot.shutdown {
+                ShutdownKind::Clean => ("Host rebooted", vec!["reboot".to_string()]),
+                ShutdownKind::Crash => (
+                    "Host rebooted after a crash or power loss",
+                    vec!["reboot".to_string(), "crash".to_string()],
+                ),
+            };
+            self.add(
+                reboot.last_seen,
+                Some(reboot.booted),
+                text.to_string(),
+                tags,
+                AnnotationSource::Auto,
+            );
+        }
+        self.add(
+            at,
+            None,
+            "pi-dash started".to_string(),
+            vec!["restart".to_string()],
+            AnnotationSource::Auto,
+        );
+    }
+
+    /// Add an annotation for something pi-dash noticed itself.
+    pub fn note(&self, at: DateTime<Utc>, text: String, tags: &[&str]) {
+        let tags = tags.iter().map(|t| t.to_string()).collect();
+        self.add(at, None, text, tags, AnnotationSource::Auto);
+    }
+
+    fn add(
+        &self,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        text: String,
+        tags: Vec<String>,
+        source: AnnotationSource,
+    ) -> Annotation {
+        let mut inner = self.inner.write();
+        inner.next_id += 1;
+        let annotation = Annotation {
+            id: inner.next_id,
+            start,
+            end,
+            text,
+            tags,
+            source,
+        };
+        let pos = inner.annotations.partition_point(|a| a.start <= start);
+        inner.annotations.insert(pos, annotation.clone());
+        if inner.annotations.len() > MAX_ANNOTATIONS {
+            let excess = inner.annotations.len() - MAX_ANNOTATIONS;
+            inner.annotations.drain(..excess);
+        }
+        Self::save_to_disk(&inner);
+        info!("Added annotation {} at {}: {}", annotation.id, start, annotation.text);
+        annotation
+    }
+
+    /// Validate and add an annotation sent through the API.
+    pub fn create(&self, new: NewAnnotation) -> Result<Annotation, String> {
+        let start = match new.start.as_deref() {
+            Some(s) => history::parse_time(s).ok_or("Invalid 'start' time")?,
+            None => Utc::now(),
+        };
+        let end = match new.end.as_deref() {
+            Some(s) => Some(history::parse_time(s).ok_or("Invalid 'end' time")?),
+            None => None,
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err("'end' must not be before 'start'".to_string());
+        }
+        let text = new.text.trim();
+        if text.is_empty() {
+            return Err("'text' must not be empty".to_string());
+        }
+        if text.len() > MAX_TEXT_BYTES {
+            return Err(format!("'text' is longer than {} bytes", MAX_TEXT_BYTES));
+        }
+        let mut tags: Vec<String> = Vec::new();
+        for tag in new.tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            if tag.len() > MAX_TAG_BYTES {
+                return Err(format!("Tag '{}' is longer than {} bytes", tag, MAX_TAG_BYTES));
+            }
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(format!("At most {} tags are allowed", MAX_TAGS));
+        }
+        Ok(self.add(start, end, text.to_string(), tags, AnnotationSource::User))
+    }
+
+    /// Returns whether the annotation existed.
+    pub fn delete(&self, id: u64) -> bool {
+        let mut inner = self.inner.write();
+        let before = inner.annotations.len();
+        inner.annotations.retain(|a| a.id != id);
+        let found = inner.annotations.len() < before;
+        if found {
+            Self::save_to_disk(&inner);
+        }
+        found
+    }
+
+    /// Annotations overlapping `from..=to`, optionally only those tagged `tag`.
+    pub fn list(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        tag: Option<&str>,
+    ) -> Vec<Annotation> {
+        self.inner
+            .read()
+            .annotations
+            .iter()
+            .filter(|a| to.is_none_or(|to| a.start <= to))
+            .filter(|a| from.is_none_or(|from| a.end.unwrap_or(a.start) >= from))
+            .filter(|a| tag.is_none_or(|tag| a.tags.iter().any(|t| t == tag)))
+            .cloned()
+            .collect()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+pub async fn api_annotations(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    annotations: web::Data<AnnotationStore>,
+    query: web::Query<AnnotationQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let mut bounds = Vec::new();
+    for (name, value) in [("from", &query.from), ("to", &query.to)] {
+        match value.as_deref().map(history::parse_time) {
+            None => bounds.push(None),
+            Some(Some(t)) => bounds.push(Some(t)),
+            Some(None) => {
+                return HttpResponse::BadRequest()
+                    .json(serde_json::json!({"error": format!("Invalid '{}' time", name)}));
+            }
+        }
+    }
+    let (from, to) = (bounds[0], bounds[1]);
+    encoding::respond(&req, &annotations.list(from, to, query.tag.as_deref()))
+}
+
+pub async fn api_create_annotation(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    annotations: web::Data<AnnotationStore>,
+    body: web::Json<NewAnnotation>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    match annotations.create(body.into_inner()) {
+        Ok(annotation) => HttpResponse::Created().json(annotation),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    }
+}
+
+pub async fn api_delete_annotation(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    annotations: web::Data<AnnotationStore>,
+    id: web::Path<u64>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    if annotations.delete(id.into_inner()) {
+        HttpResponse::Ok().json(serde_json::json!({"ok": true}))
+    } else {
+        HttpResponse::NotFound().json(serde_json::json!({"error": "Unknown annotation"}))
+    }
+}
@@ -13,12 +13,12 @@ fn percent_decode(s: &str) -> String {
     let mut i = 0;
     while i < bytes.len() {
         if bytes[i] == b'%' && i + 2 < bytes.len() {
-            if let Ok(hex) = std::str::from_utf8(&bytes[i + 1..i + 3]) {
-                if let Ok(byte) = u8::from_str_radix(hex, 16) {
-                    out.push(byte as char);
-                    i += 3;
-                    continue;
-                }
+            if let Ok(hex) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                && let Ok(byte) = u8::from_str_radix(hex, 16)
+            {
+                out.push(byte as char);
+                i += 3;
+                continue;
             }
         } else if bytes[i] == b'+' {
             out.push(' ');
@@ -145,12 +145,11 @@ impl AuthConfig {
 /// Extract the access token from Authorization header or query param.
 pub fn extract_token(req: &HttpRequest) -> Option<String> {
     // Check Authorization header first
-    if let Some(auth) = req.headers().get("Authorization") {
-        if let Ok(val) = auth.to_str() {
-            if let Some(token) = val.strip_prefix("Bearer ") {
-                return Some(token.to_string());
-            }
-        }
+    if let Some(auth) = req.headers().get("Authorization")
+        && let Ok(val) = auth.to_str()
+        && let Some(token) = val.strip_prefix("Bearer ")
+    {
+        return Some(token.to_string());
     }
     // Check query param (for WebSocket) — URL-decode the value since browsers
     // percent-encode `:` as `%3A` in query strings.
@@ -203,10 +202,10 @@ pub async fn logout() -> HttpResponse {
 }
 
 pub async fn check_auth(auth: web::Data<AuthConfig>, req: HttpRequest) -> HttpResponse {
-    if let Some(token) = extract_token(&req) {
-        if auth.validate_access_token(&token) {
-            return HttpResponse::Ok().json(serde_json::json!({"authenticated": true}));
-        }
+    if let Some(token) = extract_token(&req)
+        && auth.validate_access_token(&token)
+    {
+        return HttpResponse::Ok().json(serde_json::json!({"authenticated": true}));
     }
     HttpResponse::Unauthorized().json(serde_json::json!({"authenticated": false}))
 }
@@ -0,0 +1,848 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::history::HistoryStore;
+use crate::http;
+use crate::metrics::{self, Sample, SeriesKey};
+use crate::models::{CheckResult, CheckState, CheckStatus, MetricKind};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore};
+
+const RESULT_RETENTION_SECS: i64 = 86400; // 24 hours of results per check
+const CHECKS_FILE: &str = "checks.json.zst";
+const CHECK_LATENCY_METRIC: &str = "check_latency_ms";
+const CHECK_UP_METRIC: &str = "check_up";
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// One entry of the `checks` array in the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckConfig {
+    pub name: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(flatten)]
+    pub probe: Probe,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Probe {
+    /// HTTP(S) GET. Without `expected_status`, any 2xx/3xx counts as up.
+    Http {
+        url: String,
+        #[serde(default)]
+        expected_status: Option<u16>,
+        #[serde(default)]
+        body_contains: Option<String>,
+        /// Skip certificate verification (self-signed LAN services).
+        #[serde(default)]
+        insecure: bool,
+    },
+    Tcp {
+        host: String,
+        port: u16,
+    },
+    /// Resolve `host` against `resolver` ("ip" or "ip:port").
+    Dns {
+        host: String,
+        resolver: String,
+        #[serde(default)]
+        record_type: DnsRecordType,
+        /// Require this address to be among the answers.
+        #[serde(default)]
+        expected: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum DnsRecordType {
+    #[default]
+    A,
+    #[serde(rename = "AAAA")]
+    Aaaa,
+}
+
+impl Probe {
+    fn kind(&self) -> &'static str {
+        match self {
+            Probe::Http { .. } => "http",
+            Probe::Tcp { .. } => "tcp",
+            Probe::Dns { .. } => "dns",
+        }
+    }
+
+    fn target(&self) -> String {
+        match self {
+            Probe::Http { url, .. } => url.clone(),
+            Probe::Tcp { host, port } => format!("{}:{}", host, port),
+            Probe::Dns { host, resolver, .. } => format!("{} @ {}", host, resolver),
+        }
+    }
+}
+
+struct CheckStoreInner {
+    configs: Vec<CheckConfig>,
+    results: HashMap<String, VecDeque<CheckResult>>,
+}
+
+/// Runs the configured checks and keeps a rolling window of their results.
+#[derive(Clone)]
+pub struct CheckStore {
+    inner: Arc<RwLock<CheckStoreInner>>,
+    history: HistoryStore,
+}
+
+impl CheckStore {
+    pub fn new(configs: Vec<CheckConfig>, history: HistoryStore) -> Self {
+        let mut results = Self::load_from_disk().unwrap_or_default();
+        results.retain(|name, _| configs.iter().any(|c| &c.name == name));
+
+        let registry = history.registry();
+        let _ = registry.register(metrics::desc(
+            CHECK_LATENCY_METRIC,
+            "ms",
+            MetricKind::Gauge,
+            &["check"],
+            "Check response time",
+        ));
+        let _ = registry.register(metrics::desc(
+            CHECK_UP_METRIC,
+            "bool",
+            MetricKind::Gauge,
+            &["check"],
+            "1 when the check succeeded, 0 when it failed",
+        ));
+
+        CheckStore {
+            inner: Arc::new(RwLock::new(CheckStoreInner { configs, results })),
+            history,
+        }
+    }
+
+    fn load_from_disk() -> Option<HashMap<String, VecDeque<CheckResult>>> {
+        let path = config::data_path(CHECKS_FILE);
+        let content = std::fs::read(&path).ok()?;
+        let decompressed = zstd::decode_all(content.as_slice()).ok()?;
+        let results = serde_json::from_slice(&decompressed).ok()?;
+        println!("Loaded check results from {}", path.display());
+        Some(results)
+    }
+
+    pub fn save_to_disk(&self) {
+        let inner = self.inner.read();
+        if inner.configs.is_empty() {
+            return;
+        }
+        let path = config::data_path(CHECKS_FILE);
+        match serde_json::to_vec(&inner.results) {
+            Ok(json) => match zstd::encode_all(json.as_slice(), 3) {
+                Ok(compressed) => {
+                    if let Err(e) = writes::replace(&path, &compressed) {
+                        eprintln!("Failed to write check results to {}: {}", path.display(), e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to compress check results: {}", e),
+            },
+            Err(e) => eprintln!("Failed to serialize check results: {}", e),
+        }
+    }
+
+    /// Spawn one scheduling task per configured check.
+    pub fn spawn(&self) {
+        for check in self.inner.read().configs.clone() {
+            let store = self.clone();
+            tokio::spawn(async move {
+                let period = Duration::from_secs(check.interval_secs.max(1));
+                let mut interval = tokio::time::interval(period);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let timeout = Duration::from_secs(check.timeout_secs.max(1));
+                    let result = run_probe(&check.probe, timeout).await;
+                    if !result.up {
+                        warn!(
+                            "Check '{}' is down: {}",
+                            check.name,
+                            result.error.as_deref().unwrap_or("unknown error")
+                        );
+                    }
+                    store.record(&check.name, result);
+                }
+            });
+        }
+    }
+
+    fn record(&self, name: &str, result: CheckResult) {
+        let key = |metric: &str| SeriesKey::new(metric).with_label("check", name);
+        let up = if result.up { 1.0 } else { 0.0 };
+        let mut samples = vec![Sample::new(key(CHECK_UP_METRIC), up)];
+        if let Some(ms) = result.response_ms {
+            samples.push(Sample::new(key(CHECK_LATENCY_METRIC), ms));
+        }
+        self.history.record(result.timestamp, samples);
+
+        let mut inner = self.inner.write();
+        let cutoff = result.timestamp - TimeDelta::seconds(RESULT_RETENTION_SECS);
+        let results = inner.results.entry(name.to_string()).or_default();
+        results.push_back(result);
+        while let Some(front) = results.front() {
+            if front.timestamp < cutoff {
+                results.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<CheckStatus> {
+        let inner = self.inner.read();
+        inner
+            .configs
+            .iter()
+            .map(|c| Self::status(c, inner.results.get(&c.name), false))
+            .collect()
+    }
+
+    pub fn status_with_results(&self, name: &str) -> Option<CheckStatus> {
+        let inner = self.inner.read();
+        let config = inner.configs.iter().find(|c| c.name == name)?;
+        Some(Self::status(config, inner.results.get(name), true))
+    }
+
+    fn status(
+        config: &CheckConfig,
+        results: Option<&VecDeque<CheckResult>>,
+        with_results: bool,
+    ) -> CheckStatus {
+        let empty = VecDeque::new();
+        let results = results.unwrap_or(&empty);
+        let last = results.back().cloned();
+
+        let state = match &last {
+            None => CheckState::Pending,
+            Some(r) if r.up => CheckState::Up,
+            Some(_) => CheckState::Down,
+        };
+
+        // Walk back to the first result of the current streak
+        let since = last.as_ref().and_then(|l| {
+            results
+                .iter()
+                .rev()
+                .take_while(|r| r.up == l.up)
+                .last()
+                .map(|r| r.timestamp)
+        });
+
+        let up_count = results.iter().filter(|r| r.up).count();
+        let uptime_percent = if results.is_empty() {
+            None
+        } else {
+            Some((up_count as f32 / results.len() as f32) * 100.0)
+        };
+
+        let latencies: Vec<f64> = results
+            .iter()
+            .filter(|r| r.up)
+            .filter_map(|r| r.response_ms)
+            .collect();
+        let avg_response_ms = if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().sum::<f64>() / latencies.len() as f64)
+        };
+
+        let tls_days_left = results
+            .iter()
+            .rev()
+            .find_map(|r| r.tls_expiry)
+            .map(|expiry| (expiry - Utc::now()).num_days());
+
+        CheckStatus {
+            name: config.name.clone(),
+            kind: config.probe.kind().to_string(),
+            target: config.probe.target(),
+            interval_secs: config.interval_secs,
+            state,
+            since,
+            last,
+            uptime_percent,
+            avg_response_ms,
+            tls_days_left,
+            results: with_results.then(|| results.iter().cloned().collect()),
+        }
+    }
+}
+
+/// Outcome of a probe before it is stamped into a `CheckResult`.
+#[derive(Default)]
+struct ProbeOutcome {
+    status_code: Option<u16>,
+    tls_expiry: Option<DateTime<Utc>>,
+}
+
+async fn run_probe(probe: &Probe, timeout: Duration) -> CheckResult {
+    let timestamp = Utc::now();
+    let start = Instant::now();
+
+    let outcome = match tokio::time::timeout(timeout, probe_once(probe)).await {
+        Ok(res) => res,
+        Err(_) => Err((
+            ProbeOutcome::default(),
+            format!("timed out after {}s", timeout.as_secs()),
+        )),
+    };
+    let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    match outcome {
+        Ok(o) => CheckResult {
+            timestamp,
+            up: true,
+            response_ms: Some(elapsed_ms),
+            status_code: o.status_code,
+            tls_expiry: o.tls_expiry,
+            error: None,
+        },
+        Err((o, error)) => CheckResult {
+            timestamp,
+            up: false,
+            response_ms: None,
+            status_code: o.status_code,
+            tls_expiry: o.tls_expiry,
+            error: Some(error),
+        },
+    }
+}
+
+type ProbeError = (ProbeOutcome, String);
+
+async fn probe_once(probe: &Probe) -> Result<ProbeOutcome, ProbeError> {
+    match probe {
+        Probe::Http {
+            url,
+            expected_status,
+            body_contains,
+            insecure,
+        } => probe_http(url, *expected_status, body_contains.as_deref(), *insecure).await,
+        Probe::Tcp { host, port } => TcpStream::connect((host.as_str(), *port))
+            .await
+            .map(|_| ProbeOutcome::default())
+            .map_err(|e| (ProbeOutcome::default(), e.to_string())),
+        Probe::Dns {
+            host,
+            resolver,
+            record_type,
+            expected,
+        } => probe_dns(host, resolver, *record_type, expected.as_deref())
+            .await
+            .map(|_| ProbeOutcome::default())
+            .map_err(|e| (ProbeOutcome::default(), e)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP
+// ---------------------------------------------------------------------------
+
+struct ParsedUrl {
+    tls: bool,
+    host: String,
+    port: u16,
+    /// `host[:port]` as it should appear in the Host header.
+    authority: String,
+    path: String,
+}
+
+fn parse_url(url: &str) -> Result<ParsedUrl, String> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(format!("unsupported URL scheme: {}", url));
+    };
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let default_port = if tls { 443 } else { 80 };
+
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        // IPv6 literal: [::1]:8080
+        let (host, after) = v6
+            .split_once(']')
+            .ok_or_else(|| format!("invalid IPv6 host in {}", url))?;
+        let port = match after.strip_prefix(':') {
+            Some(p) => p.parse().map_err(|_| format!("invalid port in {}", url))?,
+            None => default_port,
+        };
+        (host.to_string(), port)
+    } else {
+        match authority.rsplit_once(':') {
+            Some((h, p)) => (
+                h.to_string(),
+                p.parse().map_err(|_| format!("invalid port in {}", url))?,
+            ),
+            None => (authority.to_string(), default_port),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(format!("missing host in {}", url));
+    }
+
+    Ok(ParsedUrl {
+        tls,
+        host,
+        port,
+        authority: authority.to_string(),
+        path: path.to_string(),
+    })
+}
+
+async fn probe_http(
+    url: &str,
+    expected_status: Option<u16>,
+    body_contains: Option<&str>,
+    insecure: bool,
+) -> Result<ProbeOutcome, ProbeError> {
+    let url = parse_url(url).map_err(|e| (ProbeOutcome::default(), e))?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: pi-dash\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        url.path, url.authority
+    );
+
+    let tcp = TcpStream::connect((url.host.as_str(), url.port))
+        .await
+        .map_err(|e| (ProbeOutcome::default(), e.to_string()))?;
+
+    let mut outcome = ProbeOutcome::default();
+    let raw = if url.tls {
+        let server_name = ServerName::try_from(url.host.clone())
+            .map_err(|e| (ProbeOutcome::default(), e.to_string()))?;
+        let connector = TlsConnector::from(tls_config(insecure));
+        let mut stream = connector
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| (ProbeOutcome::default(), format!("TLS: {}", e)))?;
+        outcome.tls_expiry = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|leaf| certificate_expiry(leaf));
+        http::exchange(&mut stream, request.as_bytes()).await
+    } else {
+        let mut stream = tcp;
+        http::exchange(&mut stream, request.as_bytes()).await
+    };
+    let raw = match raw {
+        Ok(raw) => raw,
+        Err(e) => return Err((outcome, e.to_string())),
+    };
+
+    let (status, body) = match http::parse_response(&raw) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err((outcome, e)),
+    };
+    outcome.status_code = Some(status);
+
+    let status_ok = match expected_status {
+        Some(expected) => status == expected,
+        None => (200..400).contains(&status),
+    };
+    if !status_ok {
+        return Err((outcome, format!("unexpected status {}", status)));
+    }
+
+    if let Some(needle) = body_contains
+        && !String::from_utf8_lossy(&body).contains(needle)
+    {
+        return Err((outcome, format!("body does not contain {:?}", needle)));
+    }
+
+    Ok(outcome)
+}
+
+fn certificate_expiry(der: &CertificateDer<'_>) -> Option<DateTime<Utc>> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der.as_ref()).ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+}
+
+fn tls_config(insecure: bool) -> Arc<ClientConfig> {
+    static VERIFIED: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    static INSECURE: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+    let cell = if insecure { &INSECURE } else { &VERIFIED };
+    cell.get_or_init(|| {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .expect("ring provider supports the default TLS versions");
+
+        let config = if insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+                .with_no_client_auth()
+        } else {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            builder.with_root_certificates(roots).with_no_client_auth()
+        };
+        Arc::new(config)
+    })
+    .clone()
+}
+
+/// Accepts any certificate but still checks handshake signatures, so
+/// self-signed services can be monitored and their expiry reported.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// DNS
+// ---------------------------------------------------------------------------
+
+fn parse_resolver(resolver: &str) -> Result<SocketAddr, String> {
+    if let Ok(addr) = resolver.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    resolver
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("invalid resolver address: {}", resolver))
+}
+
+async fn probe_dns(
+    name: &str,
+    resolver: &str,
+    record_type: DnsRecordType,
+    expected: Option<&str>,
+) -> Result<Vec<IpAddr>, String> {
+    let resolver = parse_resolver(resolver)?;
+    let bind: SocketAddr = if resolver.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+    socket.connect(resolver).await.map_err(|e| e.to_string())?;
+
+    let id: u16 = rand::random();
+    let query = build_dns_query(id, name, record_type)?;
+    socket.send(&query).await.map_err(|e| e.to_string())?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let n = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+        // Ignore stray datagrams that do not answer our query
+        if n < 12 || u16::from_be_bytes([buf[0], buf[1]]) != id {
+            continue;
+        }
+        let addrs = parse_dns_response(&buf[..n], record_type)?;
+        if addrs.is_empty() {
+            let rtype = match record_type {
+                DnsRecordType::A => "A",
+                DnsRecordType::Aaaa => "AAAA",
+            };
+            return Err(format!("no {} records for {}", rtype, name));
+        }
+        if let Some(expected) = expected {
+            let expected: IpAddr = expected
+                .parse()
+                .map_err(|_| format!("invalid expected address: {}", expected))?;
+            if !addrs.contains(&expected) {
+                return Err(format!("{} did not resolve to {}", name, expected));
+            }
+        }
+        return Ok(addrs);
+    }
+}
+
+const DNS_CLASS_IN: u16 = 1;
+
+fn dns_type_code(record_type: DnsRecordType) -> u16 {
+    match record_type {
+        DnsRecordType::A => 1,
+        DnsRecordType::Aaaa => 28,
+    }
+}
+
+fn build_dns_query(id: u16, name: &str, record_type: DnsRecordType) -> Result<Vec<u8>, String> {
+    let qtype = dns_type_code(record_type);
+
+    let mut q = Vec::with_capacity(32 + name.len());
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&[0x01, 0x00]); // standard query, recursion desired
+    q.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // QDCOUNT = 1
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid DNS name: {}", name));
+        }
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    Ok(q)
+}
+
+/// Skip over a (possibly compressed) domain name, returning the offset after it.
+fn skip_dns_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xC0 == 0xC0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+/// Addresses of the queried type in the answer section. CNAMEs and any
+/// other records in the chain are skipped.
+fn parse_dns_response(buf: &[u8], record_type: DnsRecordType) -> Result<Vec<IpAddr>, String> {
+    let rcode = buf[3] & 0x0F;
+    if rcode != 0 {
+        let reason = match rcode {
+            2 => "SERVFAIL",
+            3 => "NXDOMAIN",
+            5 => "REFUSED",
+            _ => "error",
+        };
+        return Err(format!("resolver returned {} (rcode {})", reason, rcode));
+    }
+
+    let qdcount = u16::from_be_bytes([buf[4], buf[5]]);
+    let ancount = u16::from_be_bytes([buf[6], buf[7]]);
+    let truncated = || "truncated DNS response".to_string();
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_dns_name(buf, pos).ok_or_else(truncated)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..ancount {
+        pos = skip_dns_name(buf, pos).ok_or_else(truncated)?;
+        let header = buf.get(pos..pos + 10).ok_or_else(truncated)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let class = u16::from_be_bytes([header[2], header[3]]);
+        let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        let rdata = buf.get(pos..pos + rdlen).ok_or_else(truncated)?;
+        pos += rdlen;
+        if rtype != dns_type_code(record_type) || class != DNS_CLASS_IN {
+            continue;
+        }
+        match record_type {
+            DnsRecordType::A => {
+                let octets = <[u8; 4]>::try_from(rdata).map_err(|_| "malformed A record")?;
+                addrs.push(IpAddr::from(octets));
+            }
+            DnsRecordType::Aaaa => {
+                let octets = <[u8; 16]>::try_from(rdata).map_err(|_| "malformed AAAA record")?;
+                addrs.push(IpAddr::from(octets));
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+pub async fn api_checks(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    checks: web::Data<CheckStore>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    encoding::respond(&req, &checks.statuses())
+}
+
+pub async fn api_check(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    checks: web::Data<CheckStore>,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    match checks.status_with_results(&name) {
+        Some(status) => encoding::respond(&req, &status),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error": "Unknown check"})),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A response to `query` carrying `answers`, each a (type, rdata) pair
+    /// under a name pointing back at the question.
+    fn dns_response(query: &[u8], rcode: u8, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut buf = query.to_vec();
+        buf[2] = 0x81;
+        buf[3] = 0x80 | rcode;
+        buf[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        for (rtype, rdata) in answers {
+            buf.extend_from_slice(&[0xC0, 0x0C]);
+            buf.extend_from_slice(&rtype.to_be_bytes());
+            buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x01, 0x2C]); // class IN, TTL 300
+            buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            buf.extend_from_slice(rdata);
+        }
+        buf
+    }
+
+    #[test]
+    fn dns_query_encoding() {
+        let query = build_dns_query(0x1234, "pi.example.", DnsRecordType::Aaaa).unwrap();
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert_eq!(&query[12..24], b"\x02pi\x07example\x00");
+        assert_eq!(&query[24..], &[0x00, 28, 0x00, 0x01]);
+
+        assert!(build_dns_query(1, "a..b", DnsRecordType::A).is_err());
+        assert!(build_dns_query(1, &"x".repeat(64), DnsRecordType::A).is_err());
+    }
+
+    const CNAME: &[u8] = b"\x03web\xC0\x10";
+
+    #[test]
+    fn dns_response_addresses() {
+        let query = build_dns_query(7, "www.example.com", DnsRecordType::A).unwrap();
+        let response = dns_response(
+            &query,
+            0,
+            &[(5, CNAME), (1, &[192, 168, 1, 2]), (28, &[0; 16]), (1, &[10, 0, 0, 1])],
+        );
+        let addrs = parse_dns_response(&response, DnsRecordType::A).unwrap();
+        assert_eq!(
+            addrs,
+            vec![IpAddr::from([192, 168, 1, 2]), IpAddr::from([10, 0, 0, 1])]
+        );
+
+        let query = build_dns_query(7, "www.example.com", DnsRecordType::Aaaa).unwrap();
+        let response = dns_response(&query, 0, &[(1, &[192, 168, 1, 2]), (28, &[0; 16])]);
+        let addrs = parse_dns_response(&response, DnsRecordType::Aaaa).unwrap();
+        assert_eq!(addrs, vec![IpAddr::from([0u8; 16])]);
+    }
+
+    #[test]
+    fn dns_response_without_matching_answers() {
+        let query = build_dns_query(7, "alias.example", DnsRecordType::A).unwrap();
+        let response = dns_response(&query, 0, &[(5, CNAME)]);
+        assert!(parse_dns_response(&response, DnsRecordType::A).unwrap().is_empty());
+
+        // An A record outside class IN is not an address
+        let mut response = dns_response(&query, 0, &[(1, &[10, 0, 0, 1])]);
+        let class = response.len() - 4 - 2 - 4 - 2;
+        response[class..class + 2].copy_from_slice(&3u16.to_be_bytes());
+        assert!(parse_dns_response(&response, DnsRecordType::A).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dns_response_errors() {
+        let query = build_dns_query(7, "missing.example", DnsRecordType::A).unwrap();
+        let err = parse_dns_response(&dns_response(&query, 3, &[]), DnsRecordType::A).unwrap_err();
+        assert!(err.contains("NXDOMAIN"), "{}", err);
+
+        let mut response = dns_response(&query, 0, &[(1, &[10, 0, 0, 1])]);
+        response.truncate(response.len() - 2);
+        assert_eq!(
+            parse_dns_response(&response, DnsRecordType::A).unwrap_err(),
+            "truncated DNS response"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use nix::time::{ClockId, clock_gettime};
+
+const JUMP_THRESHOLD_SECS: i64 = 30; // smaller differences are scheduling noise or NTP slewing
+const MIN_PLAUSIBLE_SECS: i64 = 1_704_067_200; // 2024-01-01; anything earlier is an unset clock
+const TIMESYNC_FLAG: &str = "/run/systemd/timesync/synchronized"; // created by systemd-timesyncd once synced
+
+/// Whether the wall clock can possibly be right. Boards without an RTC start
+/// at the epoch (or a saved time) until NTP has synced.
+pub fn plausible(wall: DateTime<Utc>) -> bool {
+    wall.timestamp() >= MIN_PLAUSIBLE_SECS
+}
+
+/// Whether systemd-timesyncd reports the clock as synchronised. Other NTP
+/// clients leave no such marker, so `false` proves nothing.
+pub fn synced() -> bool {
+    std::path::Path::new(TIMESYNC_FLAG).exists()
+}
+
+/// Time since boot, suspend included. Unlike the wall clock it never jumps.
+pub fn uptime() -> TimeDelta {
+    clock_gettime(ClockId::CLOCK_BOOTTIME)
+        .ok()
+        .and_then(|ts| TimeDelta::new(ts.tv_sec(), ts.tv_nsec() as u32))
+        .unwrap_or_default()
+}
+
+/// A wall clock time paired with the boot clock at the moment it was taken.
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub wall: DateTime<Utc>,
+    boot: TimeDelta,
+}
+
+impl Reading {
+    pub fn now() -> Self {
+        Self::at(Utc::now())
+    }
+
+    /// `wall` as read just now.
+    pub fn at(wall: DateTime<Utc>) -> Self {
+        Reading {
+            wall,
+            boot: uptime(),
+        }
+    }
+
+    /// Boot clock time elapsed since `earlier`.
+    pub fn since(&self, earlier: &Reading) -> TimeDelta {
+        self.boot - earlier.boot
+    }
+
+    /// Wall time of `earlier` corrected to this reading's clock.
+    pub fn restamp(&self, earlier: &Reading) -> DateTime<Utc> {
+        self.wall - self.since(earlier)
+    }
+}
+
+/// Follows the wall clock against the boot clock to catch it being stepped.
+pub struct Clock {
+    last: Option<Reading>,
+    /// Reading that retention is measured from; see [`Clock::retention_now`].
+    anchor: Reading,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            last: None,
+            anchor: Reading::now(),
+        }
+    }
+
+    /// Note a new reading. Returns how far the wall clock was stepped since
+    /// the previous one (positive when forward), if it was.
+    pub fn observe(&mut self, reading: Reading) -> Option<TimeDelta> {
+        let jump = self.last.map(|last| {
+            let expected = last.wall + reading.since(&last);
+            reading.wall - expected
+        });
+        self.last = Some(reading);
+        jump.filter(|j| j.num_seconds().abs() > JUMP_THRESHOLD_SECS)
+    }
+
+    /// Current time for deciding what is past retention. It advances with
+    /// the boot clock, so stepping the wall clock forward cannot expire data
+    /// early; stepping it back is followed since that only keeps data longer.
+    pub fn retention_now(&mut self) -> DateTime<Utc> {
+        let now = Reading::now();
+        let steady = self.anchor.wall + now.since(&self.anchor);
+        if now.wall < steady {
+            self.anchor = now;
+            return now.wall;
+        }
+        steady
+    }
+
+    /// Accept the wall clock as correct from `reading` on, typically once it
+    /// has been synced.
+    pub fn reanchor(&mut self, reading: Reading) {
+        self.anchor = reading;
+        self.last = Some(reading);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reading of `wall` seconds past a plausible time, taken `boot`
+    /// seconds after boot.
+    fn reading(wall: i64, boot: i64) -> Reading {
+        Reading {
+            wall: DateTime::from_timestamp(MIN_PLAUSIBLE_SECS + wall, 0).unwrap(),
+            boot: TimeDelta::seconds(boot),
+        }
+    }
+
+    #[test]
+    fn plausible_times() {
+        assert!(!plausible(DateTime::UNIX_EPOCH));
+        assert!(plausible(reading(0, 0).wall));
+    }
+
+    #[test]
+    fn restamp_uses_boot_clock() {
+        let earlier = reading(-1_000_000, 100);
+        let now = reading(500, 160);
+        assert_eq!(now.since(&earlier), TimeDelta::seconds(60));
+        assert_eq!(now.restamp(&earlier), reading(440, 0).wall);
+    }
+
+    #[test]
+    fn observe_detects_steps() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.observe(reading(0, 10)), None);
+        // Drift within the threshold is not a step
+        assert_eq!(clock.observe(reading(5 + JUMP_THRESHOLD_SECS, 15)), None);
+        let forward = clock.observe(reading(3600 + 40, 20));
+        assert!(forward.is_some_and(|j| j.num_seconds() > 3500));
+        let back = clock.observe(reading(0, 25));
+        assert!(back.is_some_and(|j| j.num_seconds() < -3500));
+    }
+
+    #[test]
+    fn reanchor_resets_last_reading() {
+        let mut clock = Clock::new();
+        clock.observe(reading(0, 10));
+        clock.reanchor(reading(7200, 20));
+        assert_eq!(clock.observe(reading(7210, 30)), None);
+    }
+}
@@ -2,9 +2,11 @@ use std::fs;
 use std::path::Path;
 use std::collections::HashMap;
 use std::time::Instant;
-use sysinfo::{System, Disks, Components};
+use sysinfo::{System, Disks, DiskRefreshKind, Components};
 use crate::models::{CpuStats, MemoryStats, DiskStats, SystemStats, TemperatureSensor, TempGroup, NetworkStats, DiskIoStats};
 use chrono::Utc;
+use crate::storage::StorageMonitor;
+use crate::mounts::{self, MountMonitor};
 
 struct ProcStats {
     timestamp: Instant,
@@ -18,6 +20,8 @@ pub struct Collector {
     sys: System,
     disks: Disks,
     components: Components,
+    storage: StorageMonitor,
+    mounts: MountMonitor,
     last_proc_stats: Option<ProcStats>,
     last_stats: Option<SystemStats>,
     last_collection_time: Option<Instant>,
@@ -27,12 +31,17 @@ impl Collector {
     pub fn new() -> Self {
         let mut sys = System::new_all();
         sys.refresh_all();
-        let disks = Disks::new_with_refreshed_list();
+        // List mounts without touching them; network filesystems are only
+        // ever probed by the MountMonitor thread.
+        let mut disks = Disks::new_with_refreshed_list_specifics(DiskRefreshKind::nothing());
+        refresh_local_disks(&mut disks);
         let components = Components::new_with_refreshed_list();
         Collector { 
             sys, 
             disks, 
             components, 
+            storage: StorageMonitor::start(),
+            mounts: MountMonitor::start(),
             last_proc_stats: None,
             last_stats: None,
             last_collection_time: None,
@@ -45,13 +54,14 @@ impl Collector {
         // If we collected very recently (e.g. within 500ms), return cached stats.
         // This prevents on-demand API calls from messing up the deltas for the 
         // background collector loop (stolen delta problem).
-        if let (Some(last_s), Some(last_t)) = (&self.last_stats, self.last_collection_time) {
-            if now.duration_since(last_t).as_millis() < 500 {
-                return last_s.clone();
-            }
+        if let (Some(last_s), Some(last_t)) = (&self.last_stats, self.last_collection_time)
+            && now.duration_since(last_t).as_millis() < 500
+        {
+            return last_s.clone();
         }
         self.sys.refresh_all();
-        self.disks.refresh(true);
+        self.disks.refresh_specifics(true, DiskRefreshKind::nothing());
+        refresh_local_disks(&mut self.disks);
         self.components.refresh(true);
 
         let cpu = self.collect_cpu();
@@ -61,6 +71,8 @@ impl Collector {
         let temperatures = group_temperatures(raw_temps);
 
         let (network, disk_io) = self.collect_proc_stats();
+        let storage_arrays = self.storage.arrays();
+        let network_mounts = self.mounts.mounts();
 
         let stats = SystemStats {
             timestamp: Utc::now(),
@@ -70,6 +82,8 @@ impl Collector {
             network,
             disk_io,
             temperatures,
+            storage_arrays,
+            network_mounts,
         };
 
         self.last_stats = Some(stats.clone());
@@ -98,7 +112,7 @@ impl Collector {
                 label.contains("cpu") || label.contains("coretemp") || label.contains("k10temp") || label.contains("soc")
             })
             .and_then(|c| c.temperature())
-            .map(|t| round1(t));
+            .map(round1);
 
         CpuStats {
             usage_percent: round1(usage_percent),
@@ -111,18 +125,22 @@ impl Collector {
         let total = self.sys.total_memory();
         let used = self.sys.used_memory();
         let free = self.sys.free_memory();
+        let swap_total = self.sys.total_swap();
 
         let usage_percent = if total > 0 {
             (used as f32 / total as f32) * 100.0
         } else {
             0.0
         };
+        let swap_percent = (swap_total > 0)
+            .then(|| round1(self.sys.used_swap() as f32 / swap_total as f32 * 100.0));
 
         MemoryStats {
             usage_percent: round1(usage_percent),
             total_bytes: total,
             free_bytes: free,
             used_bytes: used,
+            swap_percent,
         }
     }
 
@@ -130,7 +148,7 @@ impl Collector {
         let mut total: u64 = 0;
         let mut available: u64 = 0;
 
-        for disk in self.disks.iter() {
+        for disk in self.disks.iter().filter(|d| !is_network_disk(d)) {
             // Only count real filesystems
             let mount = disk.mount_point().to_string_lossy();
             if mount == "/" || mount.starts_with("/home") || mount.starts_with("/mnt") || mount.starts_with("/media") {
@@ -141,7 +159,7 @@ impl Collector {
 
         // If we didn't find any specific mounts, use all disks
         if total == 0 {
-            for disk in self.disks.iter() {
+            for disk in self.disks.iter().filter(|d| !is_network_disk(d)) {
                 total += disk.total_space();
                 available += disk.available_space();
             }
@@ -212,17 +230,17 @@ impl Collector {
                                 .trim()
                                 .to_string();
 
-                            if let Ok(val_str) = fs::read_to_string(file.path()) {
-                                if let Ok(val) = val_str.trim().parse::<f32>() {
-                                    // hwmon reports in millidegrees
-                                    let temp = val / 1000.0;
-                                    if temp > -40.0 && temp < 150.0 {
-                                        sensors.push(TemperatureSensor {
-                                            label,
-                                            temperature: temp,
-                                            sensor_type: name.clone(),
-                                        });
-                                    }
+                            if let Ok(val_str) = fs::read_to_string(file.path())
+                                && let Ok(val) = val_str.trim().parse::<f32>()
+                            {
+                                // hwmon reports in millidegrees
+                                let temp = val / 1000.0;
+                                if temp > -40.0 && temp < 150.0 {
+                                    sensors.push(TemperatureSensor {
+                                        label,
+                                        temperature: temp,
+                                        sensor_type: name.clone(),
+                                    });
                                 }
                             }
                         }
@@ -293,7 +311,7 @@ impl Collector {
                     
                     let is_whole_disk = if dev_name.starts_with("sd") || dev_name.starts_with("hd") || dev_name.starts_with("vd") {
                         // sdX, hdX, vdX - whole disk if it doesn't end with a digit
-                        !dev_name.chars().last().map_or(false, |c| c.is_ascii_digit())
+                        !dev_name.chars().last().is_some_and(|c| c.is_ascii_digit())
                     } else if dev_name.starts_with("nvme") || dev_name.starts_with("mmcblk") {
                         // nvmeXn1, mmcblk0 - whole disk if it doesn't contain 'p' followed by a digit
                         !dev_name.contains('p')
@@ -352,9 +370,24 @@ impl Collector {
     }
 }
 
+fn is_network_disk(disk: &sysinfo::Disk) -> bool {
+    mounts::is_network_fs(&disk.file_system().to_string_lossy())
+}
+
+/// Refresh capacity of local filesystems only. `statvfs` on a hung network
+/// mount blocks indefinitely, so those are skipped here.
+fn refresh_local_disks(disks: &mut Disks) {
+    for disk in disks.list_mut() {
+        if !is_network_disk(disk) {
+            disk.refresh_specifics(DiskRefreshKind::nothing().with_storage());
+        }
+    }
+}
+
 /// Group raw temperature sensors using the same logic as the old TempCard.tsx:
 /// - Sensors whose label contains "npu", "core", "gpu", "soc", or "center" → "SOC" group
 /// - All others → grouped by sensor_type
+///
 /// Each group emits a single TempGroup with the maximum temperature (rounded to 1 dp).
 pub fn group_temperatures(sensors: Vec<TemperatureSensor>) -> Vec<TempGroup> {
     let mut groups: HashMap<String, f32> = HashMap::new();
@@ -402,6 +435,6 @@ pub fn group_temperatures(sensors: Vec<TemperatureSensor>) -> Vec<TempGroup> {
 
 /// Round a float to 1 decimal place.
 #[inline]
-fn round1(v: f32) -> f32 {
+pub fn round1(v: f32) -> f32 {
     (v * 10.0).round() / 10.0
 }
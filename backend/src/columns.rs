@@ -0,0 +1,214 @@
+use crate::metrics::SeriesKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+
+/// Series keys interned to small ids, so metric names and label strings are
+/// stored once however many tiers and buckets refer to them. Ids are handed
+/// out in first-seen order; [`Interner::retain`] renumbers them when series
+/// are dropped, so they are only valid while the store stays locked.
+#[derive(Default, Clone)]
+pub struct Interner {
+    keys: Vec<SeriesKey>,
+    ids: HashMap<SeriesKey, u32>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, key: &SeriesKey) -> u32 {
+        if let Some(&id) = self.ids.get(key) {
+            return id;
+        }
+        let id = self.keys.len() as u32;
+        self.keys.push(key.clone());
+        self.ids.insert(key.clone(), id);
+        id
+    }
+
+    pub fn id(&self, key: &SeriesKey) -> Option<u32> {
+        self.ids.get(key).copied()
+    }
+
+    pub fn key(&self, id: u32) -> Option<&SeriesKey> {
+        self.keys.get(id as usize)
+    }
+
+    /// Every key with its id, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &SeriesKey)> {
+        self.keys.iter().enumerate().map(|(id, key)| (id as u32, key))
+    }
+
+    /// Keep the ids `keep` accepts and renumber them in order. Returns the
+    /// new id of every old one, `None` for those dropped.
+    pub fn retain(&mut self, keep: impl Fn(u32) -> bool) -> Vec<Option<u32>> {
+        let mut remap = Vec::with_capacity(self.keys.len());
+        let mut kept = Vec::new();
+        for (id, key) in std::mem::take(&mut self.keys).into_iter().enumerate() {
+            if keep(id as u32) {
+                remap.push(Some(kept.len() as u32));
+                kept.push(key);
+            } else {
+                remap.push(None);
+            }
+        }
+        self.ids = kept
+            .iter()
+            .enumerate()
+            .map(|(id, key)| (key.clone(), id as u32))
+            .collect();
+        self.keys = kept;
+        remap
+    }
+}
+
+impl HeapSize for Interner {
+    // Every key is held twice: in the id list and as a map key
+    fn heap_bytes(&self) -> usize {
+        let strings: usize = self.keys.iter().map(HeapSize::heap_bytes).sum();
+        self.keys.capacity() * size_of::<SeriesKey>()
+            + self.ids.capacity() * (size_of::<SeriesKey>() + size_of::<u32>() + 1)
+            + 2 * strings
+    }
+}
+
+impl HeapSize for SeriesKey {
+    fn heap_bytes(&self) -> usize {
+        let labels: usize = self
+            .labels
+            .iter()
+            .map(|(name, value)| size_of::<(String, String)>() + name.capacity() + value.capacity())
+            .sum();
+        self.metric.capacity() + labels
+    }
+}
+
+impl Serialize for Interner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.keys.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keys = Vec::<SeriesKey>::deserialize(deserializer)?;
+        let ids = keys
+            .iter()
+            .enumerate()
+            .map(|(id, key)| (key.clone(), id as u32))
+            .collect();
+        Ok(Interner { keys, ids })
+    }
+}
+
+/// Heap memory owned by a value, on top of its inline size.
+pub trait HeapSize {
+    fn heap_bytes(&self) -> usize;
+}
+
+impl HeapSize for f64 {
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// Time-ordered ring buffer of one series, kept as two parallel columns so
+/// timestamps are plain integers rather than one `DateTime` per value.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Column<T> {
+    times: VecDeque<i64>,
+    values: VecDeque<T>,
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Column {
+            times: VecDeque::new(),
+            values: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Column<T> {
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn first_time(&self) -> Option<i64> {
+        self.times.front().copied()
+    }
+
+    /// Insert `value` at time `t`, keeping the column ordered. Values almost
+    /// always arrive in order, so this is normally a plain push.
+    pub fn push(&mut self, t: i64, value: T) {
+        let pos = match self.times.back() {
+            Some(&last) if last > t => self.times.partition_point(|&x| x <= t),
+            _ => self.times.len(),
+        };
+        self.times.insert(pos, t);
+        self.values.insert(pos, value);
+    }
+
+    /// Value at exactly time `t`, created with the default when missing.
+    pub fn slot_mut(&mut self, t: i64) -> &mut T
+    where
+        T: Default,
+    {
+        let pos = match self.times.back() {
+            Some(&last) if last == t => self.times.len() - 1,
+            Some(&last) if last > t => match self.times.binary_search(&t) {
+                Ok(pos) => pos,
+                Err(pos) => {
+                    self.times.insert(pos, t);
+                    self.values.insert(pos, T::default());
+                    pos
+                }
+            },
+            _ => {
+                self.times.push_back(t);
+                self.values.push_back(T::default());
+                self.times.len() - 1
+            }
+        };
+        &mut self.values[pos]
+    }
+
+    pub fn contains(&self, t: i64) -> bool {
+        self.times.binary_search(&t).is_ok()
+    }
+
+    pub fn pop_front(&mut self) -> Option<(i64, T)> {
+        Some((self.times.pop_front()?, self.values.pop_front()?))
+    }
+
+    /// Drop every value older than `cutoff`.
+    pub fn prune(&mut self, cutoff: i64) {
+        while self.times.front().is_some_and(|&t| t < cutoff) {
+            self.times.pop_front();
+            self.values.pop_front();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i64, &T)> {
+        self.times.iter().copied().zip(self.values.iter())
+    }
+
+    /// Values with `from <= t < to`, found by binary search.
+    pub fn range(&self, from: i64, to: i64) -> impl Iterator<Item = (i64, &T)> {
+        let start = self.times.partition_point(|&t| t < from);
+        let end = self.times.partition_point(|&t| t < to).max(start);
+        self.times
+            .range(start..end)
+            .copied()
+            .zip(self.values.range(start..end))
+    }
+}
+
+impl<T: HeapSize> Column<T> {
+    /// Memory taken by one entry.
+    pub fn entry_bytes(value: &T) -> usize {
+        size_of::<i64>() + size_of::<T>() + value.heap_bytes()
+    }
+
+    /// Approximate memory used by the column's entries.
+    pub fn memory_bytes(&self) -> usize {
+        self.values.iter().map(Self::entry_bytes).sum()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::checks::CheckConfig;
+use crate::forecast::ForecastConfig;
+use crate::heartbeat::HeartbeatConfig;
+use crate::history::HistoryConfig;
+use crate::paths::PathMonitorConfig;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Directory where pi-dash keeps its persistent state.
+pub const DATA_DIR: &str = "/data";
+
+/// Build a path to a file inside the data directory.
+pub fn data_path(name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(name)
+}
+
+/// Optional JSON configuration for features that need more than a handful of
+/// environment variables. Read from `PI_DASH_CONFIG`, falling back to
+/// `/data/config.json`. A missing file yields the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Synthetic service checks (HTTP, TCP, DNS).
+    pub checks: Vec<CheckConfig>,
+    /// Heartbeat endpoints for cron jobs and backups.
+    pub heartbeats: Vec<HeartbeatConfig>,
+    /// File and directory freshness / size monitors.
+    pub paths: Vec<PathMonitorConfig>,
+    /// Raw retention and aggregation tiers of the metrics history.
+    pub history: HistoryConfig,
+    /// Trend fitting and time-to-full warnings.
+    pub forecast: ForecastConfig,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = Self::path();
+        match Self::read(&path) {
+            Ok(Some(config)) => {
+                println!("Loaded config from {}", path.display());
+                config
+            }
+            Ok(None) => Config::default(),
+            Err(e) => {
+                eprintln!("Failed to parse config {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        std::env::var("PI_DASH_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| data_path("config.json"))
+    }
+
+    /// Parse the config at `path`; `None` if there is no such file.
+    pub fn read(path: &Path) -> Result<Option<Self>, serde_json::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::models::HistoryPoint;
+
+/// Indices of the points to keep when reducing a chart to `threshold`
+/// points with Largest-Triangle-Three-Buckets, which keeps peaks and dips
+/// that plain averaging or striding would flatten.
+///
+/// `xs` are the point times; `ys` has one row per point with a value per
+/// series, `None` where a series has no value. Each series is scaled to its
+/// own range before triangle areas are summed, so a byte rate in the
+/// millions does not drown out a percentage. The first and last points are
+/// always kept.
+pub fn lttb(xs: &[f64], ys: &[Vec<Option<f64>>], threshold: usize) -> Vec<usize> {
+    let n = xs.len();
+    if threshold >= n || threshold < 3 {
+        return (0..n).collect();
+    }
+
+    let series = ys.iter().map(Vec::len).max().unwrap_or(0);
+    let scale = |values: &mut dyn Iterator<Item = f64>| {
+        let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        });
+        if max > min { 1.0 / (max - min) } else { 0.0 }
+    };
+    let x_scale = scale(&mut xs.iter().copied());
+    let y_scales: Vec<f64> = (0..series)
+        .map(|s| scale(&mut ys.iter().filter_map(|row| row.get(s).copied().flatten())))
+        .collect();
+    let y = |i: usize, s: usize| ys[i].get(s).copied().flatten().map(|v| v * y_scales[s]);
+
+    let every = (n - 2) as f64 / (threshold - 2) as f64;
+    let mut kept = Vec::with_capacity(threshold);
+    kept.push(0);
+    let mut a = 0;
+    for bucket in 0..threshold - 2 {
+        // Average of the next bucket, the third corner of every triangle
+        let next_start = ((bucket + 1) as f64 * every) as usize + 1;
+        let next_end = (((bucket + 2) as f64 * every) as usize + 1).min(n);
+        let next = next_start..next_end;
+        let avg_x = next.clone().map(|i| xs[i] * x_scale).sum::<f64>() / next.len() as f64;
+        let avg_y: Vec<Option<f64>> = (0..series)
+            .map(|s| {
+                let values: Vec<f64> = next.clone().filter_map(|i| y(i, s)).collect();
+                (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+            })
+            .collect();
+
+        let start = (bucket as f64 * every) as usize + 1;
+        let end = ((bucket + 1) as f64 * every) as usize + 1;
+        let ax = xs[a] * x_scale;
+        let mut best = start;
+        let mut best_area = -1.0;
+        for (i, &x) in xs.iter().enumerate().take(end.min(n - 1)).skip(start) {
+            let bx = x * x_scale;
+            let area: f64 = (0..series)
+                .filter_map(|s| {
+                    let (ay, by, cy) = (y(a, s)?, y(i, s)?, avg_y[s]?);
+                    Some(((ax - avg_x) * (by - ay) - (ax - bx) * (cy - ay)).abs())
+                })
+                .sum();
+            if area > best_area {
+                best_area = area;
+                best = i;
+            }
+        }
+        kept.push(best);
+        a = best;
+    }
+    kept.push(n - 1);
+    kept
+}
+
+/// Reduce history points to at most `max`, judging shape by the fields that
+/// will be returned (all when `fields` is `None`).
+pub fn history_points(points: Vec<HistoryPoint>, fields: Option<&[&str]>, max: usize) -> Vec<HistoryPoint> {
+    let wanted = |name: &str| fields.is_none_or(|f| f.contains(&name));
+    let scalars: Vec<fn(&HistoryPoint) -> Option<f64>> = [
+        ("cpu_percent", (|p| Some(p.cpu_percent as f64)) as fn(&HistoryPoint) -> Option<f64>),
+        ("cpu_freq", |p| Some(p.cpu_freq as f64)),
+        ("cpu_temp", |p| p.cpu_temp.map(f64::from)),
+        ("mem_percent", |p| Some(p.mem_percent as f64)),
+        ("disk_percent", |p| Some(p.disk_percent as f64)),
+        ("network_rx_bytes_sec", |p| Some(p.network_rx_bytes_sec as f64)),
+        ("network_tx_bytes_sec", |p| Some(p.network_tx_bytes_sec as f64)),
+        ("disk_read_bytes_sec", |p| Some(p.disk_read_bytes_sec as f64)),
+        ("disk_write_bytes_sec", |p| Some(p.disk_write_bytes_sec as f64)),
+        ("coverage", |p| p.coverage.map(f64::from)),
+    ]
+    .into_iter()
+    .filter(|(name, _)| wanted(name))
+    .map(|(_, get)| get)
+    .collect();
+    let mut labels: Vec<&str> = Vec::new();
+    if wanted("temperatures") {
+        for group in points.iter().flat_map(|p| &p.temperatures) {
+            if !labels.contains(&group.label.as_str()) {
+                labels.push(&group.label);
+            }
+        }
+    }
+
+    let xs: Vec<f64> = points.iter().map(|p| p.timestamp.timestamp() as f64).collect();
+    let ys: Vec<Vec<Option<f64>>> = points
+        .iter()
+        .map(|p| {
+            scalars
+                .iter()
+                .map(|get| get(p))
+                .chain(labels.iter().map(|label| {
+                    p.temperatures
+                        .iter()
+                        .find(|g| g.label == *label)
+                        .map(|g| g.temperature as f64)
+                }))
+                .collect()
+        })
+        .collect();
+
+    let keep = lttb(&xs, &ys, max);
+    let mut keep = keep.into_iter().peekable();
+    points
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, p)| keep.next_if_eq(&i).map(|_| p))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(values: &[f64]) -> (Vec<f64>, Vec<Vec<Option<f64>>>) {
+        let xs = (0..values.len()).map(|i| i as f64).collect();
+        let ys = values.iter().map(|&v| vec![Some(v)]).collect();
+        (xs, ys)
+    }
+
+    fn assert_well_formed(kept: &[usize], n: usize, threshold: usize) {
+        assert_eq!(kept.len(), threshold);
+        assert_eq!(kept.first(), Some(&0));
+        assert_eq!(kept.last(), Some(&(n - 1)));
+        assert!(kept.windows(2).all(|w| w[0] < w[1]), "{:?}", kept);
+    }
+
+    #[test]
+    fn short_input_kept_whole() {
+        let (xs, ys) = single(&[1.0, 2.0, 3.0]);
+        assert_eq!(lttb(&xs, &ys, 3), vec![0, 1, 2]);
+        assert_eq!(lttb(&xs, &ys, 10), vec![0, 1, 2]);
+        assert_eq!(lttb(&xs, &ys, 2), vec![0, 1, 2]);
+        assert!(lttb(&[], &[], 5).is_empty());
+    }
+
+    #[test]
+    fn keeps_spikes() {
+        let mut values = vec![10.0; 100];
+        values[37] = 90.0;
+        values[71] = -50.0;
+        let (xs, ys) = single(&values);
+        let kept = lttb(&xs, &ys, 10);
+        assert_well_formed(&kept, 100, 10);
+        assert!(kept.contains(&37));
+        assert!(kept.contains(&71));
+    }
+
+    #[test]
+    fn flat_and_missing_values() {
+        let (xs, ys) = single(&[5.0; 50]);
+        assert_well_formed(&lttb(&xs, &ys, 7), 50, 7);
+
+        let ys: Vec<Vec<Option<f64>>> = (0..50)
+            .map(|i| vec![(i % 4 != 0).then_some(i as f64)])
+            .collect();
+        assert_well_formed(&lttb(&xs, &ys, 7), 50, 7);
+        let empty: Vec<Vec<Option<f64>>> = vec![vec![None]; 50];
+        assert_well_formed(&lttb(&xs, &empty, 7), 50, 7);
+    }
+
+    #[test]
+    fn series_are_scaled_separately() {
+        // A small dip in a percentage must survive next to a large byte rate
+        let xs: Vec<f64> = (0..60).map(|i| i as f64 * 5.0).collect();
+        let ys: Vec<Vec<Option<f64>>> = (0..60)
+            .map(|i| {
+                let percent = if i == 23 { 1.0 } else { 50.0 };
+                vec![Some(percent), Some(1e9 + (i % 2) as f64 * 1e6)]
+            })
+            .collect();
+        let kept = lttb(&xs, &ys, 8);
+        assert_well_formed(&kept, 60, 8);
+        assert!(kept.contains(&23));
+    }
+}
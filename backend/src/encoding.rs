@@ -0,0 +1,251 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Wire format of an API response or WebSocket frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl Encoding {
+    /// The format the `Accept` header prefers, JSON when it names none we
+    /// speak. Ties in quality go to the type listed first.
+    pub fn from_accept(req: &HttpRequest) -> Self {
+        let Some(accept) = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Encoding::Json;
+        };
+        let mut best = (Encoding::Json, 0.0);
+        for range in accept.split(',') {
+            let mut parts = range.split(';');
+            let Some(encoding) = parts.next().and_then(|m| Self::from_media_type(m.trim())) else {
+                continue;
+            };
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q > best.1 {
+                best = (encoding, q);
+            }
+        }
+        best.0
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Encoding::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Encoding::MsgPack)
+            }
+            "application/cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+
+    /// The format named by a WebSocket subprotocol.
+    pub fn from_subprotocol(protocol: &str) -> Option<Self> {
+        match protocol {
+            "pi-dash.json" => Some(Encoding::Json),
+            "pi-dash.msgpack" => Some(Encoding::MsgPack),
+            "pi-dash.cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::MsgPack => "application/msgpack",
+            Encoding::Cbor => "application/cbor",
+        }
+    }
+
+    /// Binary formats keep field names as map keys, like JSON, so any
+    /// MessagePack or CBOR decoder can read a response on its own.
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Encoding::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Encoding::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf).map_err(|e| e.to_string())?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// A 200 response carrying `value` in the format the request asked for.
+pub fn respond<T: Serialize + ?Sized>(req: &HttpRequest, value: &T) -> HttpResponse {
+    let encoding = Encoding::from_accept(req);
+    match encoding.encode(value) {
+        Ok(body) => HttpResponse::Ok()
+            .insert_header((header::VARY, "Accept"))
+            .content_type(encoding.content_type())
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e})),
+    }
+}
+
+/// Field names sent over binary WebSockets, shared by every client. Names
+/// are only ever appended, so an index keeps its meaning for the life of
+/// the process and each client only needs the names it has not seen yet.
+///
+/// REST responses keep named keys (see [`Encoding::encode`]): each one is
+/// read on its own, with no earlier message to have carried the names.
+#[derive(Default)]
+pub struct Dictionary {
+    names: Arc<Vec<String>>,
+    index: HashMap<String, u32>,
+}
+
+impl Dictionary {
+    /// Replace every map key in `value` with its index, adding new names.
+    pub fn pack(&mut self, value: Value) -> Packed {
+        match value {
+            Value::Null => Packed::Null,
+            Value::Bool(b) => Packed::Bool(b),
+            Value::Number(n) => Packed::Number(n),
+            Value::String(s) => Packed::String(s),
+            Value::Array(items) => Packed::Array(items.into_iter().map(|v| self.pack(v)).collect()),
+            Value::Object(map) => Packed::Map(
+                map.into_iter()
+                    .map(|(k, v)| (self.intern(k), self.pack(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn intern(&mut self, name: String) -> u32 {
+        if let Some(&i) = self.index.get(&name) {
+            return i;
+        }
+        let i = self.names.len() as u32;
+        Arc::make_mut(&mut self.names).push(name.clone());
+        self.index.insert(name, i);
+        i
+    }
+
+    /// Every name so far; index `i` is the key `i` in packed values.
+    pub fn names(&self) -> Arc<Vec<String>> {
+        self.names.clone()
+    }
+}
+
+/// A JSON value whose map keys are indexes into a [`Dictionary`].
+pub enum Packed {
+    Null,
+    Bool(bool),
+    Number(serde_json::Number),
+    String(String),
+    Array(Vec<Packed>),
+    Map(Vec<(u32, Packed)>),
+}
+
+impl Serialize for Packed {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Packed::Null => serializer.serialize_unit(),
+            Packed::Bool(b) => serializer.serialize_bool(*b),
+            // Most stats are f32; don't widen them to eight bytes on the wire
+            Packed::Number(n) => match n.as_f64() {
+                Some(f) if n.is_f64() && f as f32 as f64 == f => serializer.serialize_f32(f as f32),
+                _ => n.serialize(serializer),
+            },
+            Packed::String(s) => serializer.serialize_str(s),
+            Packed::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Packed::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use ciborium::Value as Cbor;
+
+    fn accepting(accept: &str) -> Encoding {
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT, accept))
+            .to_http_request();
+        Encoding::from_accept(&req)
+    }
+
+    #[test]
+    fn accept_negotiation() {
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(Encoding::from_accept(&req), Encoding::Json);
+        assert_eq!(accepting("text/html, */*"), Encoding::Json);
+        assert_eq!(accepting("application/msgpack"), Encoding::MsgPack);
+        assert_eq!(accepting("application/json, application/cbor"), Encoding::Json);
+        assert_eq!(accepting("application/json;q=0.5, Application/CBOR"), Encoding::Cbor);
+        assert_eq!(
+            accepting("application/x-msgpack;q=0.9, application/cbor;q=0.8"),
+            Encoding::MsgPack
+        );
+    }
+
+    #[test]
+    fn dictionary_keeps_indexes() {
+        let mut dictionary = Dictionary::default();
+        dictionary.pack(serde_json::json!({
+            "cpu": {"usage_percent": 1},
+            "memory": {"usage_percent": 2},
+        }));
+        let first = dictionary.names();
+        assert_eq!(*first, ["cpu", "usage_percent", "memory"]);
+
+        dictionary.pack(serde_json::json!({"memory": 3, "disk": 4}));
+        let second = dictionary.names();
+        assert_eq!(*second, ["cpu", "usage_percent", "memory", "disk"]);
+        // Names handed out earlier are untouched
+        assert_eq!(first.len(), 3);
+    }
+
+    #[test]
+    fn packed_wire_form() {
+        let mut dictionary = Dictionary::default();
+        let packed = dictionary.pack(serde_json::json!({
+            "a": [1.5, true, null],
+            "b": {"a": "x"},
+            "c": 0.1,
+        }));
+        let mut buf = Vec::new();
+        ciborium::into_writer(&packed, &mut buf).unwrap();
+        let decoded: Cbor = ciborium::from_reader(&buf[..]).unwrap();
+        let key = |i: u32| Cbor::Integer(i.into());
+        assert_eq!(
+            decoded,
+            Cbor::Map(vec![
+                (key(0), Cbor::Array(vec![Cbor::Float(1.5), Cbor::Bool(true), Cbor::Null])),
+                (key(1), Cbor::Map(vec![(key(0), Cbor::Text("x".to_string()))])),
+                (key(2), Cbor::Float(0.1)),
+            ])
+        );
+
+        // 1.5 fits an f32 and is sent in five bytes; 0.1 needs all nine
+        let single = rmp_serde::to_vec(&dictionary.pack(serde_json::json!(1.5))).unwrap();
+        let double = rmp_serde::to_vec(&dictionary.pack(serde_json::json!(0.1))).unwrap();
+        assert_eq!((single.len(), double.len()), (5, 9));
+    }
+}
@@ -0,0 +1,635 @@
+use crate::auth::{self, AuthConfig};
+use crate::config::{self, Config};
+use crate::history::{self, HistoryStore, Selection, StorageKind};
+use crate::metrics::SeriesKey;
+use crate::models::{Aggregate, ExportFormat, ExportQuery};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+const CHUNK_BYTES: usize = 64 * 1024; // response body is sent in pieces of about this size
+const ROW_GROUP_ROWS: usize = 64 * 1024; // rows buffered per Parquet row group
+const PARQUET_SCHEMA: &str = "
+    message history {
+        REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+        REQUIRED BINARY metric (STRING);
+        REQUIRED BINARY labels (STRING);
+        REQUIRED DOUBLE value;
+    }
+";
+const USAGE: &str = "\
+Usage: pi-dash export [options]
+
+Write metrics history saved on disk, one row per series value
+(timestamp, metric, labels, value). Works while pi-dash is running.
+
+Options:
+  --format csv|ndjson|parquet   Output format (default csv)
+  --range NAME                  raw or a tier name (default the finest tier)
+  --from TIME, --to TIME        Window, RFC 3339 or Unix seconds
+  --step SECS                   Re-aggregate the window to this step
+  --agg avg|min|max|last|p95|p99
+                                How tier buckets are reduced (default avg)
+  --fields METRIC,...           Only these metrics
+  --dir PATH                    History log directory or database (default
+                                /data/history, or /data/history.db with
+                                sqlite storage)
+  -o, --output FILE             Write here instead of stdout";
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Labels in the `name=value,...` form `/api/series` takes.
+fn format_labels(key: &SeriesKey) -> String {
+    key.labels
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quote a CSV field if it needs it.
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+#[derive(Serialize)]
+struct NdjsonRow<'a> {
+    timestamp: DateTime<Utc>,
+    metric: &'a str,
+    labels: &'a BTreeMap<String, String>,
+    value: f64,
+}
+
+/// Parquet output. Rows are buffered column by column and written out a
+/// row group at a time.
+struct ParquetEncoder<W: Write + Send> {
+    writer: SerializedFileWriter<W>,
+    times: Vec<i64>,
+    metrics: Vec<ByteArray>,
+    labels: Vec<ByteArray>,
+    values: Vec<f64>,
+}
+
+impl<W: Write + Send> ParquetEncoder<W> {
+    fn new(out: W) -> parquet::errors::Result<Self> {
+        let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        Ok(ParquetEncoder {
+            writer: SerializedFileWriter::new(out, schema, Arc::new(props))?,
+            times: Vec::new(),
+            metrics: Vec::new(),
+            labels: Vec::new(),
+            values: Vec::new(),
+        })
+    }
+
+    fn write_series(&mut self, key: &SeriesKey, values: &[(DateTime<Utc>, f64)]) -> parquet::errors::Result<()> {
+        let metric = ByteArray::from(key.metric.as_str());
+        let labels = ByteArray::from(format_labels(key).as_str());
+        for &(t, value) in values {
+            self.times.push(t.timestamp_millis());
+            self.metrics.push(metric.clone());
+            self.labels.push(labels.clone());
+            self.values.push(value);
+            if self.times.len() >= ROW_GROUP_ROWS {
+                self.write_row_group()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> parquet::errors::Result<()> {
+        if self.times.is_empty() {
+            return Ok(());
+        }
+        let mut row_group = self.writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => column.typed::<Int64Type>().write_batch(&self.times, None, None)?,
+                1 => column.typed::<ByteArrayType>().write_batch(&self.metrics, None, None)?,
+                2 => column.typed::<ByteArrayType>().write_batch(&self.labels, None, None)?,
+                _ => column.typed::<DoubleType>().write_batch(&self.values, None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        self.times.clear();
+        self.metrics.clear();
+        self.labels.clear();
+        self.values.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> parquet::errors::Result<W> {
+        self.write_row_group()?;
+        self.writer.into_inner()
+    }
+}
+
+/// Writes series values as rows of `timestamp, metric, labels, value`.
+enum Encoder<W: Write + Send> {
+    Csv(W),
+    Ndjson(W),
+    Parquet(Box<ParquetEncoder<W>>),
+}
+
+impl<W: Write + Send> Encoder<W> {
+    fn new(format: ExportFormat, mut out: W) -> io::Result<Self> {
+        Ok(match format {
+            ExportFormat::Csv => {
+                out.write_all(b"timestamp,metric,labels,value\n")?;
+                Encoder::Csv(out)
+            }
+            ExportFormat::Ndjson => Encoder::Ndjson(out),
+            ExportFormat::Parquet => {
+                Encoder::Parquet(Box::new(ParquetEncoder::new(out).map_err(io::Error::other)?))
+            }
+        })
+    }
+
+    fn write_series(&mut self, key: &SeriesKey, values: &[(DateTime<Utc>, f64)]) -> io::Result<()> {
+        match self {
+            Encoder::Csv(out) => {
+                let metric = csv_field(&key.metric);
+                let labels = format_labels(key);
+                let labels = csv_field(&labels);
+                for (t, value) in values {
+                    writeln!(
+                        out,
+                        "{},{},{},{}",
+                        t.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                        metric,
+                        labels,
+                        value
+                    )?;
+                }
+                Ok(())
+            }
+            Encoder::Ndjson(out) => {
+                for &(timestamp, value) in values {
+                    let row = NdjsonRow {
+                        timestamp,
+                        metric: &key.metric,
+                        labels: &key.labels,
+                        value,
+                    };
+                    serde_json::to_writer(&mut *out, &row)?;
+                    out.write_all(b"\n")?;
+                }
+                Ok(())
+            }
+            Encoder::Parquet(encoder) => encoder.write_series(key, values).map_err(io::Error::other),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut out = match self {
+            Encoder::Csv(out) | Encoder::Ndjson(out) => out,
+            Encoder::Parquet(encoder) => encoder.finish().map_err(io::Error::other)?,
+        };
+        out.flush()
+    }
+}
+
+/// Write the selected history to `out` in `format`.
+fn write<W: Write + Send>(
+    history: &HistoryStore,
+    format: ExportFormat,
+    metrics: Option<&[String]>,
+    selection: &Selection,
+    agg: Aggregate,
+    out: W,
+) -> io::Result<()> {
+    let mut encoder = Encoder::new(format, out)?;
+    history
+        .export(metrics, selection, agg, |key, values| encoder.write_series(key, values))
+        .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown range")))?;
+    encoder.finish()
+}
+
+/// Hands the response body to the HTTP stream in chunks. Fails with
+/// `BrokenPipe` once the client has gone away.
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+fn split_fields(fields: Option<&str>) -> Option<Vec<String>> {
+    fields.map(|f| {
+        f.split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// The first of `metrics` that is not registered.
+fn unknown_metric<'a>(history: &HistoryStore, metrics: Option<&'a [String]>) -> Option<&'a str> {
+    metrics
+        .into_iter()
+        .flatten()
+        .find(|m| history.registry().get(m).is_none())
+        .map(String::as_str)
+}
+
+/// Stream history as CSV, NDJSON or Parquet. Takes the same range or window
+/// as `/api/history`; `fields` lists metric names.
+pub async fn api_export(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let format = query.format.unwrap_or_default();
+    let agg = query.agg.unwrap_or_default();
+
+    let metrics = split_fields(query.fields.as_deref());
+    if let Some(unknown) = unknown_metric(&history, metrics.as_deref()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown metric '{}'", unknown),
+            "metrics": history.registry().list().into_iter().map(|m| m.name).collect::<Vec<_>>(),
+        }));
+    }
+
+    let windowed = query.from.is_some() || query.to.is_some() || query.step.is_some();
+    let selection = if windowed {
+        let to = match query.to.as_deref().map(history::parse_time) {
+            None => Utc::now(),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'to' time"}));
+            }
+        };
+        let from = match query.from.as_deref().map(history::parse_time) {
+            None => to - TimeDelta::days(1),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'from' time"}));
+            }
+        };
+        if from >= to || query.step.is_some_and(|s| s <= 0) {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid window"}));
+        }
+        Selection::Window {
+            from,
+            to,
+            step_secs: query.step,
+        }
+    } else {
+        let range = query
+            .range
+            .clone()
+            .unwrap_or_else(|| history.default_range());
+        if history.retention_secs(&range).is_none() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Unknown range",
+                "ranges": history.ranges(),
+            }));
+        }
+        Selection::Range(range)
+    };
+
+    // Encoding runs on the blocking pool and feeds the response through a
+    // small channel, so a slow client holds back the encoder, not memory
+    let (tx, rx) = mpsc::channel(4);
+    let store = history.get_ref().clone();
+    tokio::task::spawn_blocking(move || {
+        let out = ChannelWriter {
+            tx: tx.clone(),
+            buf: Vec::with_capacity(CHUNK_BYTES),
+        };
+        if let Err(e) = write(&store, format, metrics.as_deref(), &selection, agg, out)
+            && e.kind() != io::ErrorKind::BrokenPipe
+        {
+            eprintln!("History export failed: {}", e);
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((chunk, rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"pi-dash-history.{}\"", format.extension()),
+        ))
+        .streaming(body)
+}
+
+fn parse_arg<T: serde::de::DeserializeOwned>(flag: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("Invalid value '{}' for {}", value, flag))
+}
+
+fn parse_time_arg(flag: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    history::parse_time(value).ok_or_else(|| format!("Invalid time '{}' for {}", value, flag))
+}
+
+/// `pi-dash export`: write history straight from the log on disk, without
+/// going through a running server. Returns the process exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    match cli(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn cli(args: &[String]) -> Result<(), String> {
+    let mut format = ExportFormat::default();
+    let mut agg = Aggregate::default();
+    let mut range = None;
+    let mut from = None;
+    let mut to = None;
+    let mut step = None;
+    let mut metrics = None;
+    let mut dir = None;
+    let mut output: Option<PathBuf> = None;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        let Some(value) = args.next() else {
+            return Err(format!("Missing value for {}\n\n{}", flag, USAGE));
+        };
+        match flag.as_str() {
+            "--format" => format = parse_arg(flag, value)?,
+            "--agg" => agg = parse_arg(flag, value)?,
+            "--range" => range = Some(value.clone()),
+            "--from" => from = Some(parse_time_arg(flag, value)?),
+            "--to" => to = Some(parse_time_arg(flag, value)?),
+            "--step" => {
+                step = Some(
+                    value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|&s| s > 0)
+                        .ok_or_else(|| format!("Invalid value '{}' for {}", value, flag))?,
+                )
+            }
+            "--fields" => metrics = split_fields(Some(value)),
+            "--dir" => dir = Some(PathBuf::from(value)),
+            "-o" | "--output" => output = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown option {}\n\n{}", flag, USAGE)),
+        }
+    }
+
+    let config_path = Config::path();
+    let config = Config::read(&config_path)
+        .map_err(|e| format!("Failed to parse config {}: {}", config_path.display(), e))?
+        .unwrap_or_default();
+    let dir = dir.unwrap_or_else(|| {
+        config::data_path(match config.history.storage {
+            StorageKind::Log => history::WAL_DIR,
+            StorageKind::Sqlite => history::SQLITE_FILE,
+        })
+    });
+    let history = HistoryStore::open_read_only(&config.history, &dir)
+        .map_err(|e| format!("Failed to read history in {}: {}", dir.display(), e))?;
+
+    let selection = if from.is_some() || to.is_some() || step.is_some() {
+        let to = to.unwrap_or_else(Utc::now);
+        let from = from.unwrap_or(to - TimeDelta::days(1));
+        if from >= to {
+            return Err("--from must be before --to".to_string());
+        }
+        Selection::Window {
+            from,
+            to,
+            step_secs: step,
+        }
+    } else {
+        let range = range.unwrap_or_else(|| history.default_range());
+        if history.retention_secs(&range).is_none() {
+            return Err(format!(
+                "Unknown range '{}'; expected one of {}",
+                range,
+                history.ranges().join(", ")
+            ));
+        }
+        Selection::Range(range)
+    };
+
+    let out: Box<dyn Write + Send> = match &output {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    match write(
+        &history,
+        format,
+        metrics.as_deref(),
+        &selection,
+        agg,
+        io::BufWriter::new(out),
+    ) {
+        // Piped into `head` or similar
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(|e| format!("Export failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::HistoryConfig;
+    use crate::metrics::{self, Sample};
+    use crate::models::MetricKind;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    /// A store holding two readings of a labelled series and one of a plain
+    /// one, and the times they were taken at.
+    fn store() -> (HistoryStore, [DateTime<Utc>; 2]) {
+        let dir = tempfile::tempdir().unwrap();
+        let history = HistoryStore::open_read_only(&HistoryConfig::default(), dir.path()).unwrap();
+        history
+            .registry()
+            .register(metrics::desc("room_temp", "celsius", MetricKind::Gauge, &["room"], ""))
+            .unwrap();
+        // Whole seconds, as raw values are kept to the microsecond
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        let times = [now - TimeDelta::seconds(20), now - TimeDelta::seconds(10)];
+        let room = SeriesKey::new("room_temp").with_label("room", "attic, \"north\"");
+        history.record(
+            times[0],
+            vec![Sample::new(room.clone(), 21.5), Sample::new(SeriesKey::new(metrics::CPU_PERCENT), 3.0)],
+        );
+        history.record(times[1], vec![Sample::new(room, 22.0)]);
+        (history, times)
+    }
+
+    fn export(history: &HistoryStore, format: ExportFormat, metrics: &[&str]) -> Vec<u8> {
+        let metrics: Vec<String> = metrics.iter().map(|m| m.to_string()).collect();
+        let mut out = Vec::new();
+        let selection = Selection::Range("raw".to_string());
+        write(history, format, Some(&metrics), &selection, Aggregate::Avg, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn csv_quotes_labels() {
+        let (history, times) = store();
+        let csv = String::from_utf8(export(&history, ExportFormat::Csv, &["room_temp"])).unwrap();
+        let time = |t: DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        assert_eq!(
+            csv,
+            format!(
+                "timestamp,metric,labels,value\n\
+                 {},room_temp,\"room=attic, \"\"north\"\"\",21.5\n\
+                 {},room_temp,\"room=attic, \"\"north\"\"\",22\n",
+                time(times[0]),
+                time(times[1])
+            )
+        );
+    }
+
+    #[test]
+    fn ndjson_rows() {
+        let (history, times) = store();
+        let ndjson = export(&history, ExportFormat::Ndjson, &["room_temp", metrics::CPU_PERCENT]);
+        let rows: Vec<serde_json::Value> = ndjson
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                serde_json::json!({
+                    "timestamp": times[0], "metric": metrics::CPU_PERCENT, "labels": {}, "value": 3.0,
+                }),
+                serde_json::json!({
+                    "timestamp": times[0], "metric": "room_temp",
+                    "labels": {"room": "attic, \"north\""}, "value": 21.5,
+                }),
+                serde_json::json!({
+                    "timestamp": times[1], "metric": "room_temp",
+                    "labels": {"room": "attic, \"north\""}, "value": 22.0,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parquet_round_trip() {
+        let (history, times) = store();
+        let parquet = export(&history, ExportFormat::Parquet, &["room_temp"]);
+        let reader = SerializedFileReader::new(Bytes::from(parquet)).unwrap();
+
+        let expected = parse_message_type(PARQUET_SCHEMA).unwrap();
+        assert_eq!(reader.metadata().file_metadata().schema(), &expected);
+
+        let rows: Vec<(i64, String, String, f64)> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                (
+                    row.get_timestamp_millis(0).unwrap(),
+                    row.get_string(1).unwrap().clone(),
+                    row.get_string(2).unwrap().clone(),
+                    row.get_double(3).unwrap(),
+                )
+            })
+            .collect();
+        let labels = "room=attic, \"north\"".to_string();
+        assert_eq!(
+            rows,
+            [
+                (times[0].timestamp_millis(), "room_temp".to_string(), labels.clone(), 21.5),
+                (times[1].timestamp_millis(), "room_temp".to_string(), labels, 22.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_named() {
+        let (history, _) = store();
+        let fields = split_fields(Some("room_temp, cpu_percent,,fan_rpm"));
+        assert_eq!(unknown_metric(&history, fields.as_deref()), Some("fan_rpm"));
+        let fields = split_fields(Some("room_temp,cpu_percent"));
+        assert_eq!(unknown_metric(&history, fields.as_deref()), None);
+        assert_eq!(unknown_metric(&history, None), None);
+    }
+}
@@ -0,0 +1,442 @@
+use crate::annotations::AnnotationStore;
+use crate::auth::{self, AuthConfig};
+use crate::encoding;
+use crate::history::{HistoryStore, Selection};
+use crate::metrics::{self, Sample, SeriesKey};
+use crate::models::{
+    Aggregate, Forecast, ForecastConfidence, ForecastQuery, ForecastResponse, MetricKind,
+    SeriesPoint,
+};
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{TimeDelta, Utc};
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+const EVALUATE_INTERVAL_SECS: u64 = 900;
+const FORECAST_METRIC: &str = "forecast_days_to_full";
+const STEP_SECS: i64 = 3600; // trends are fitted to hourly averages
+const MIN_SAMPLES: usize = 6;
+const MAX_DAYS: f64 = 3650.0; // further out than this counts as never
+const RESET_FRACTION: f64 = 0.05; // a drop of this share of the limit starts a new trend
+const Z_95: f64 = 1.96;
+const ALERT_TEXT: &str = " expected to reach "; // follows the name in alert annotations
+const RESOLVED_TEXT: &str = " no longer expected to reach "; // and in resolved ones
+
+/// A series to forecast: every series of `metric` whose labels include
+/// `labels`, each expected to stop at `limit`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ForecastTarget {
+    pub name: String,
+    pub metric: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub limit: f64,
+}
+
+impl ForecastTarget {
+    fn new(name: &str, metric: &str, limit: f64) -> Self {
+        ForecastTarget {
+            name: name.to_string(),
+            metric: metric.to_string(),
+            labels: BTreeMap::new(),
+            limit,
+        }
+    }
+}
+
+/// `forecast` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ForecastConfig {
+    /// How much history trends are fitted to.
+    pub window_days: u32,
+    /// Alert when a target is expected to reach its limit within this many days.
+    pub warn_days: f64,
+    /// Forecast root disk, network mount, memory and swap usage.
+    pub builtin: bool,
+    /// Further series, e.g. a pushed process RSS metric or a path monitor size.
+    pub targets: Vec<ForecastTarget>,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        ForecastConfig {
+            window_days: 7,
+            warn_days: 7.0,
+            builtin: true,
+            targets: Vec::new(),
+        }
+    }
+}
+
+/// Least-squares line through the points, with time in days.
+struct Fit {
+    slope: f64,
+    slope_err: f64,
+    r_squared: Option<f64>,
+}
+
+fn fit(points: &[SeriesPoint]) -> Fit {
+    let t0 = points[0].timestamp;
+    let xs: Vec<f64> = points
+        .iter()
+        .map(|p| (p.timestamp - t0).num_seconds() as f64 / 86400.0)
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.value).sum::<f64>() / n;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (x, p) in xs.iter().zip(points) {
+        let (dx, dy) = (x - mean_x, p.value - mean_y);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    if sxx == 0.0 {
+        return Fit {
+            slope: 0.0,
+            slope_err: 0.0,
+            r_squared: None,
+        };
+    }
+    let slope = sxy / sxx;
+    let residual = (syy - slope * sxy).max(0.0);
+    Fit {
+        slope,
+        slope_err: (residual / (n - 2.0) / sxx).sqrt(),
+        r_squared: (syy > 0.0).then(|| 1.0 - residual / syy),
+    }
+}
+
+/// Index of the first point after the last drop of more than
+/// [`RESET_FRACTION`] of `limit`. Cleanups and log rotation start a new
+/// trend, so only what follows is fitted.
+fn trend_start(points: &[SeriesPoint], limit: f64) -> usize {
+    points
+        .windows(2)
+        .rposition(|w| w[0].value - w[1].value > limit * RESET_FRACTION)
+        .map_or(0, |i| i + 1)
+}
+
+/// Days until `remaining` is used up at `rate` per day, if within reach.
+fn days_until(remaining: f64, rate: f64) -> Option<f64> {
+    if remaining <= 0.0 {
+        return Some(0.0);
+    }
+    let days = remaining / rate;
+    (rate > 0.0 && days <= MAX_DAYS).then_some(days)
+}
+
+/// Runs forecasts over history and annotates the timeline when one comes
+/// within `warn_days`.
+#[derive(Clone)]
+pub struct ForecastStore {
+    config: Arc<ForecastConfig>,
+    history: HistoryStore,
+    annotations: AnnotationStore,
+    /// Names of the series currently expected to fill within `warn_days`.
+    alerting: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ForecastStore {
+    pub fn new(config: ForecastConfig, history: HistoryStore, annotations: AnnotationStore) -> Self {
+        let _ = history.registry().register(metrics::desc(
+            FORECAST_METRIC,
+            "days",
+            MetricKind::Gauge,
+            &["target"],
+            "Estimated days until the target reaches its limit",
+        ));
+        ForecastStore {
+            config: Arc::new(config),
+            history,
+            annotations: annotations.clone(),
+            alerting: Arc::new(Mutex::new(alerting_from(&annotations))),
+        }
+    }
+
+    fn targets(&self) -> Vec<ForecastTarget> {
+        let mut targets = Vec::new();
+        if self.config.builtin {
+            targets.push(ForecastTarget::new("disk", metrics::DISK_PERCENT, 100.0));
+            targets.push(ForecastTarget::new("mounts", "mount_usage_percent", 100.0));
+            targets.push(ForecastTarget::new("memory", metrics::MEM_PERCENT, 100.0));
+            targets.push(ForecastTarget::new("swap", metrics::SWAP_PERCENT, 100.0));
+        }
+        targets.extend(self.config.targets.iter().cloned());
+        targets
+    }
+
+    /// Forecasts over the last `window_days`, one per matching series with
+    /// enough history.
+    pub fn forecasts(&self, window_days: u32) -> ForecastResponse {
+        let to = Utc::now();
+        let from = to - TimeDelta::days(window_days as i64);
+        let selection = Selection::Window {
+            from,
+            to,
+            step_secs: Some(STEP_SECS),
+        };
+        let mut forecasts = Vec::new();
+        for target in self.targets() {
+            let Some((_, _, series)) =
+                self.history
+                    .series(&target.metric, &target.labels, &selection, Aggregate::Avg)
+            else {
+                continue;
+            };
+            for data in series {
+                if let Some(forecast) = self.forecast(&target, data.labels, &data.points) {
+                    forecasts.push(forecast);
+                }
+            }
+        }
+        ForecastResponse {
+            from,
+            to,
+            window_days,
+            warn_days: self.config.warn_days,
+            forecasts,
+        }
+    }
+
+    fn forecast(
+        &self,
+        target: &ForecastTarget,
+        labels: BTreeMap<String, String>,
+        points: &[SeriesPoint],
+    ) -> Option<Forecast> {
+        let points = &points[trend_start(points, target.limit)..];
+        if points.len() < MIN_SAMPLES {
+            return None;
+        }
+        let last = points.last()?;
+        let fit = fit(points);
+        let remaining = target.limit - last.value;
+        let days_to_full = days_until(remaining, fit.slope);
+        let spread = Z_95 * fit.slope_err;
+        let earliest = days_until(remaining, fit.slope + spread);
+        let latest = days_until(remaining, fit.slope - spread);
+
+        let r_squared = fit.r_squared.unwrap_or(0.0);
+        let confidence = if r_squared >= 0.9 && points.len() >= 24 && latest.is_some() {
+            ForecastConfidence::High
+        } else if r_squared >= 0.6 && points.len() >= 12 {
+            ForecastConfidence::Medium
+        } else {
+            ForecastConfidence::Low
+        };
+        let at = |days: Option<f64>| {
+            days.map(|d| last.timestamp + TimeDelta::seconds((d * 86400.0) as i64))
+        };
+        Some(Forecast {
+            target: target.name.clone(),
+            metric: target.metric.clone(),
+            labels,
+            limit: target.limit,
+            current: last.value,
+            samples: points.len(),
+            slope_per_day: fit.slope,
+            r_squared: fit.r_squared,
+            days_to_full,
+            full_at: at(days_to_full),
+            full_earliest: at(earliest),
+            full_latest: at(latest),
+            confidence,
+            alert: confidence != ForecastConfidence::Low
+                && days_to_full.is_some_and(|d| d <= self.config.warn_days),
+        })
+    }
+
+    /// Periodically record the estimates as a metric, and log and annotate
+    /// targets that come within, or drop out of, the warning horizon.
+    pub fn spawn(&self) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(EVALUATE_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                if store.history.holding() {
+                    continue;
+                }
+                store.evaluate();
+            }
+        });
+    }
+
+    fn evaluate(&self) {
+        let response = self.forecasts(self.config.window_days);
+        let mut samples = Vec::new();
+        let mut alerting = self.alerting.lock();
+        let mut now_alerting = HashSet::new();
+        for f in &response.forecasts {
+            let mut key = SeriesKey::new(FORECAST_METRIC).with_label("target", &f.target);
+            for (name, value) in &f.labels {
+                key = key.with_label(name, value);
+            }
+            if let Some(days) = f.days_to_full {
+                samples.push(Sample::new(key, days));
+            }
+            if !f.alert {
+                continue;
+            }
+            let name = format!("'{}'{}", f.target, describe(&f.labels));
+            if !alerting.contains(&name) {
+                let text = format!(
+                    "Forecast {}{}{} in {:.1} days ({:?} confidence)",
+                    name,
+                    ALERT_TEXT,
+                    f.limit,
+                    f.days_to_full.unwrap_or_default(),
+                    f.confidence
+                );
+                warn!("{}", text);
+                self.annotations.note(response.to, text, &["forecast", "alert"]);
+            }
+            now_alerting.insert(name);
+        }
+        // Includes series that no longer have enough history to forecast
+        for name in alerting.drain() {
+            if !now_alerting.contains(&name) {
+                let text = format!(
+                    "Forecast {}{}its limit within {} days",
+                    name, RESOLVED_TEXT, self.config.warn_days
+                );
+                info!("{}", text);
+                self.annotations.note(response.to, text, &["forecast", "resolved"]);
+            }
+        }
+        *alerting = now_alerting;
+        drop(alerting);
+        if !samples.is_empty() {
+            self.history.record(response.to, samples);
+        }
+    }
+}
+
+/// Series whose latest forecast annotation is an alert, so a restart does
+/// not annotate them again.
+fn alerting_from(annotations: &AnnotationStore) -> HashSet<String> {
+    let mut alerting = HashSet::new();
+    for annotation in annotations.list(None, None, Some("forecast")) {
+        let Some(text) = annotation.text.strip_prefix("Forecast ") else {
+            continue;
+        };
+        if let Some((name, _)) = text.split_once(RESOLVED_TEXT) {
+            alerting.remove(name);
+        } else if let Some((name, _)) = text.split_once(ALERT_TEXT) {
+            alerting.insert(name.to_string());
+        }
+    }
+    alerting
+}
+
+fn describe(labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    format!(" ({})", pairs.join(", "))
+}
+
+pub async fn api_forecast(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    forecasts: web::Data<ForecastStore>,
+    query: web::Query<ForecastQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let window_days = query
+        .window_days
+        .unwrap_or(forecasts.config.window_days)
+        .clamp(1, 730);
+    encoding::respond(&req, &forecasts.forecasts(window_days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn start() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    /// Hourly points with the given values.
+    fn hourly(values: &[f64]) -> Vec<SeriesPoint> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| SeriesPoint {
+                timestamp: start() + TimeDelta::hours(i as i64),
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fit_exact_line() {
+        // One unit per hour is 24 per day
+        let values: Vec<f64> = (0..48).map(|i| 10.0 + i as f64).collect();
+        let line = fit(&hourly(&values));
+        assert!((line.slope - 24.0).abs() < 1e-9);
+        assert!(line.slope_err < 1e-6);
+        assert!((line.r_squared.unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_noisy_and_flat() {
+        let values: Vec<f64> = (0..48)
+            .map(|i| 50.0 - i as f64 * 0.5 + if i % 2 == 0 { 2.0 } else { -2.0 })
+            .collect();
+        let noisy = fit(&hourly(&values));
+        assert!((noisy.slope + 12.0).abs() < 1.0);
+        assert!(noisy.slope_err > 0.0);
+        assert!(noisy.r_squared.unwrap() < 1.0);
+
+        let flat = fit(&hourly(&[7.0; 10]));
+        assert_eq!(flat.slope, 0.0);
+        assert_eq!(flat.r_squared, None);
+    }
+
+    #[test]
+    fn fit_single_timestamp() {
+        let mut points = hourly(&[1.0, 2.0, 3.0]);
+        for p in &mut points {
+            p.timestamp = start();
+        }
+        let vertical = fit(&points);
+        assert_eq!((vertical.slope, vertical.r_squared), (0.0, None));
+    }
+
+    #[test]
+    fn trend_restarts_after_drop() {
+        let points = hourly(&[40.0, 50.0, 60.0, 20.0, 25.0, 30.0, 27.0, 35.0]);
+        // Drops of 40 and 3 against a limit of 100: only the first resets
+        assert_eq!(trend_start(&points, 100.0), 3);
+        // Against a limit of 50 the 3-point dip resets too
+        assert_eq!(trend_start(&points, 50.0), 6);
+        assert_eq!(trend_start(&hourly(&[1.0, 2.0, 3.0]), 100.0), 0);
+        assert_eq!(trend_start(&[], 100.0), 0);
+    }
+
+    #[test]
+    fn days_until_limit() {
+        assert_eq!(days_until(10.0, 2.0), Some(5.0));
+        assert_eq!(days_until(0.0, -1.0), Some(0.0));
+        assert_eq!(days_until(10.0, 0.0), None);
+        assert_eq!(days_until(10.0, -2.0), None);
+        assert_eq!(days_until(MAX_DAYS * 2.0, 1.0), None);
+    }
+}
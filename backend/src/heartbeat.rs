@@ -0,0 +1,492 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::models::{HeartbeatRun, HeartbeatState, HeartbeatStatus, RunOutcome};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+const MAX_RUNS_PER_SLUG: usize = 1000;
+const MAX_MESSAGE_BYTES: usize = 1024;
+const EVALUATE_INTERVAL_SECS: u64 = 30;
+const HEARTBEATS_FILE: &str = "heartbeats.json";
+
+fn default_grace_secs() -> u64 {
+    3600
+}
+
+/// One entry of the `heartbeats` array in the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeartbeatConfig {
+    pub slug: String,
+    /// Shared secret the job must send as `?key=` or `X-Heartbeat-Key`.
+    pub secret: String,
+    /// How often the job is expected to report in.
+    pub period_secs: u64,
+    #[serde(default = "default_grace_secs")]
+    pub grace_secs: u64,
+}
+
+/// Persisted state of a single heartbeat.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HeartbeatRecord {
+    state: Option<HeartbeatState>,
+    state_since: Option<DateTime<Utc>>,
+    last_ping: Option<DateTime<Utc>>,
+    last_finished: Option<DateTime<Utc>>,
+    last_outcome: Option<RunOutcome>,
+    running_since: Option<DateTime<Utc>>,
+    runs: VecDeque<HeartbeatRun>,
+}
+
+impl HeartbeatRecord {
+    /// Evaluate the state at `now`, along with the moment it began when that
+    /// follows from the timestamps alone.
+    fn evaluate(
+        &self,
+        config: &HeartbeatConfig,
+        now: DateTime<Utc>,
+    ) -> (HeartbeatState, Option<DateTime<Utc>>) {
+        let period = TimeDelta::seconds(config.period_secs as i64);
+        let grace = TimeDelta::seconds(config.grace_secs as i64);
+
+        // A run that started but never reported back counts as hung after the grace time
+        if let Some(started) = self.running_since
+            && now > started + grace
+        {
+            return (HeartbeatState::Down, Some(started + grace));
+        }
+
+        let Some(finished) = self.last_finished else {
+            return (HeartbeatState::New, None);
+        };
+        if self.last_outcome == Some(RunOutcome::Fail) {
+            (HeartbeatState::Down, Some(finished))
+        } else if now > finished + period + grace {
+            (HeartbeatState::Down, Some(finished + period + grace))
+        } else if now > finished + period {
+            (HeartbeatState::Late, Some(finished + period))
+        } else {
+            (HeartbeatState::Up, None)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingKind {
+    Start,
+    Success,
+    Fail,
+}
+
+pub enum PingError {
+    UnknownSlug,
+    BadKey,
+}
+
+struct HeartbeatStoreInner {
+    configs: Vec<HeartbeatConfig>,
+    records: HashMap<String, HeartbeatRecord>,
+}
+
+/// Tracks heartbeat pings from cron jobs and flags the ones that stop reporting.
+#[derive(Clone)]
+pub struct HeartbeatStore {
+    inner: Arc<RwLock<HeartbeatStoreInner>>,
+}
+
+impl HeartbeatStore {
+    pub fn new(configs: Vec<HeartbeatConfig>) -> Self {
+        let mut records = Self::load_from_disk().unwrap_or_default();
+        records.retain(|slug, _| configs.iter().any(|c| &c.slug == slug));
+
+        HeartbeatStore {
+            inner: Arc::new(RwLock::new(HeartbeatStoreInner { configs, records })),
+        }
+    }
+
+    fn load_from_disk() -> Option<HashMap<String, HeartbeatRecord>> {
+        let path = config::data_path(HEARTBEATS_FILE);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(records) => {
+                println!("Loaded heartbeats from {}", path.display());
+                Some(records)
+            }
+            Err(e) => {
+                eprintln!("Failed to parse heartbeats {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Pings are rare, so state is written through on every change. The file is
+    /// replaced atomically so a power cut cannot leave it half-written.
+    fn save_to_disk(records: &HashMap<String, HeartbeatRecord>) {
+        let path = config::data_path(HEARTBEATS_FILE);
+        match serde_json::to_vec(records) {
+            Ok(json) => {
+                if let Err(e) = writes::replace(&path, &json) {
+                    eprintln!("Failed to write heartbeats to {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize heartbeats: {}", e),
+        }
+    }
+
+    /// Periodically re-evaluate states so late and down transitions are
+    /// recorded even when no pings arrive.
+    pub fn spawn(&self) {
+        if self.inner.read().configs.is_empty() {
+            return;
+        }
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(EVALUATE_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                store.evaluate_all();
+            }
+        });
+    }
+
+    fn evaluate_all(&self) {
+        let mut guard = self.inner.write();
+        let inner = &mut *guard;
+        let now = Utc::now();
+        let mut changed = false;
+        for config in &inner.configs {
+            let record = inner.records.entry(config.slug.clone()).or_default();
+            changed |= Self::update_state(config, record, now);
+        }
+        if changed {
+            Self::save_to_disk(&inner.records);
+        }
+    }
+
+    /// Apply the evaluated state to the record. Returns true on a transition.
+    fn update_state(
+        config: &HeartbeatConfig,
+        record: &mut HeartbeatRecord,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let (state, since) = record.evaluate(config, now);
+        if record.state == Some(state) {
+            return false;
+        }
+        match state {
+            HeartbeatState::Late => warn!("Heartbeat '{}' is late", config.slug),
+            HeartbeatState::Down => warn!("Heartbeat '{}' is down", config.slug),
+            HeartbeatState::Up if record.state.is_some() => {
+                info!("Heartbeat '{}' is up", config.slug)
+            }
+            _ => {}
+        }
+        record.state = Some(state);
+        record.state_since = Some(since.unwrap_or(now));
+        true
+    }
+
+    pub fn ping(
+        &self,
+        slug: &str,
+        key: &str,
+        kind: PingKind,
+        message: Option<String>,
+    ) -> Result<(), PingError> {
+        let mut guard = self.inner.write();
+        let inner = &mut *guard;
+        let config = inner
+            .configs
+            .iter()
+            .find(|c| c.slug == slug)
+            .ok_or(PingError::UnknownSlug)?;
+        if !constant_time_eq(config.secret.as_bytes(), key.as_bytes()) {
+            return Err(PingError::BadKey);
+        }
+
+        let now = Utc::now();
+        let record = inner.records.entry(slug.to_string()).or_default();
+        record.last_ping = Some(now);
+
+        match kind {
+            PingKind::Start => record.running_since = Some(now),
+            PingKind::Success | PingKind::Fail => {
+                let started_at = record.running_since.take();
+                let outcome = if kind == PingKind::Success {
+                    RunOutcome::Success
+                } else {
+                    RunOutcome::Fail
+                };
+                record.runs.push_back(HeartbeatRun {
+                    started_at,
+                    finished_at: now,
+                    outcome,
+                    duration_secs: started_at.map(|s| (now - s).num_milliseconds() as f64 / 1000.0),
+                    message,
+                });
+                while record.runs.len() > MAX_RUNS_PER_SLUG {
+                    record.runs.pop_front();
+                }
+                record.last_finished = Some(now);
+                record.last_outcome = Some(outcome);
+            }
+        }
+
+        Self::update_state(config, record, now);
+        Self::save_to_disk(&inner.records);
+        Ok(())
+    }
+
+    pub fn statuses(&self) -> Vec<HeartbeatStatus> {
+        let inner = self.inner.read();
+        inner
+            .configs
+            .iter()
+            .map(|c| Self::status(c, inner.records.get(&c.slug), false))
+            .collect()
+    }
+
+    pub fn status_with_runs(&self, slug: &str) -> Option<HeartbeatStatus> {
+        let inner = self.inner.read();
+        let config = inner.configs.iter().find(|c| c.slug == slug)?;
+        Some(Self::status(config, inner.records.get(slug), true))
+    }
+
+    fn status(
+        config: &HeartbeatConfig,
+        record: Option<&HeartbeatRecord>,
+        with_runs: bool,
+    ) -> HeartbeatStatus {
+        let empty = HeartbeatRecord::default();
+        let record = record.unwrap_or(&empty);
+        let now = Utc::now();
+        let (state, derived_since) = record.evaluate(config, now);
+
+        HeartbeatStatus {
+            slug: config.slug.clone(),
+            period_secs: config.period_secs,
+            grace_secs: config.grace_secs,
+            state,
+            // The stored transition time is stale if the state changed since the last tick
+            since: if record.state == Some(state) {
+                record.state_since
+            } else {
+                derived_since
+            },
+            last_ping: record.last_ping,
+            running_since: record.running_since,
+            next_due: record
+                .last_finished
+                .map(|t| t + TimeDelta::seconds(config.period_secs as i64)),
+            runs: with_runs.then(|| record.runs.iter().cloned().collect()),
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+pub struct PingQuery {
+    pub key: Option<String>,
+}
+
+async fn handle_ping(
+    req: HttpRequest,
+    heartbeats: web::Data<HeartbeatStore>,
+    slug: &str,
+    kind: PingKind,
+    query: PingQuery,
+    body: web::Bytes,
+) -> HttpResponse {
+    let key = query.key.or_else(|| {
+        req.headers()
+            .get("X-Heartbeat-Key")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    });
+    let Some(key) = key else {
+        return HttpResponse::Forbidden().body("Missing key");
+    };
+
+    let message = if body.is_empty() {
+        None
+    } else {
+        let end = body.len().min(MAX_MESSAGE_BYTES);
+        Some(String::from_utf8_lossy(&body[..end]).into_owned())
+    };
+
+    match heartbeats.ping(slug, &key, kind, message) {
+        Ok(()) => HttpResponse::Ok().body("OK"),
+        Err(PingError::UnknownSlug) => HttpResponse::NotFound().body("Unknown heartbeat"),
+        Err(PingError::BadKey) => HttpResponse::Forbidden().body("Invalid key"),
+    }
+}
+
+/// `/api/heartbeat/{slug}`: a plain ping reports success.
+pub async fn ping(
+    req: HttpRequest,
+    heartbeats: web::Data<HeartbeatStore>,
+    slug: web::Path<String>,
+    query: web::Query<PingQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    handle_ping(
+        req,
+        heartbeats,
+        &slug,
+        PingKind::Success,
+        query.into_inner(),
+        body,
+    )
+    .await
+}
+
+/// `/api/heartbeat/{slug}/{start|success|fail}`
+pub async fn ping_kind(
+    req: HttpRequest,
+    heartbeats: web::Data<HeartbeatStore>,
+    path: web::Path<(String, String)>,
+    query: web::Query<PingQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let (slug, kind) = path.into_inner();
+    let kind = match kind.as_str() {
+        "start" => PingKind::Start,
+        "success" => PingKind::Success,
+        "fail" => PingKind::Fail,
+        _ => return HttpResponse::NotFound().body("Unknown ping type"),
+    };
+    handle_ping(req, heartbeats, &slug, kind, query.into_inner(), body).await
+}
+
+pub async fn api_heartbeats(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    heartbeats: web::Data<HeartbeatStore>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    encoding::respond(&req, &heartbeats.statuses())
+}
+
+pub async fn api_heartbeat(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    heartbeats: web::Data<HeartbeatStore>,
+    slug: web::Path<String>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    match heartbeats.status_with_runs(&slug) {
+        Some(status) => encoding::respond(&req, &status),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error": "Unknown heartbeat"})),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HeartbeatConfig {
+        HeartbeatConfig {
+            slug: "backup".to_string(),
+            secret: "s".to_string(),
+            period_secs: 3600,
+            grace_secs: 600,
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn finished(secs: i64, outcome: RunOutcome) -> HeartbeatRecord {
+        HeartbeatRecord {
+            last_finished: Some(at(secs)),
+            last_outcome: Some(outcome),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_until_first_run() {
+        let record = HeartbeatRecord::default();
+        assert_eq!(record.evaluate(&config(), at(0)), (HeartbeatState::New, None));
+    }
+
+    #[test]
+    fn up_late_down_after_success() {
+        let record = finished(0, RunOutcome::Success);
+        let config = config();
+        assert_eq!(record.evaluate(&config, at(3600)), (HeartbeatState::Up, None));
+        assert_eq!(
+            record.evaluate(&config, at(3601)),
+            (HeartbeatState::Late, Some(at(3600)))
+        );
+        assert_eq!(
+            record.evaluate(&config, at(4200)),
+            (HeartbeatState::Late, Some(at(3600)))
+        );
+        assert_eq!(
+            record.evaluate(&config, at(4201)),
+            (HeartbeatState::Down, Some(at(4200)))
+        );
+    }
+
+    #[test]
+    fn down_after_failure() {
+        let record = finished(0, RunOutcome::Fail);
+        assert_eq!(
+            record.evaluate(&config(), at(10)),
+            (HeartbeatState::Down, Some(at(0)))
+        );
+    }
+
+    #[test]
+    fn hung_run_is_down_after_grace() {
+        let mut record = finished(0, RunOutcome::Success);
+        record.running_since = Some(at(100));
+        let config = config();
+        assert_eq!(record.evaluate(&config, at(700)), (HeartbeatState::Up, None));
+        assert_eq!(
+            record.evaluate(&config, at(701)),
+            (HeartbeatState::Down, Some(at(700)))
+        );
+
+        // A first run that never finishes
+        let record = HeartbeatRecord {
+            running_since: Some(at(0)),
+            ..Default::default()
+        };
+        assert_eq!(record.evaluate(&config, at(600)), (HeartbeatState::New, None));
+        assert_eq!(
+            record.evaluate(&config, at(601)),
+            (HeartbeatState::Down, Some(at(600)))
+        );
+    }
+}
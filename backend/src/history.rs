@@ -1,17 +1,268 @@
-use crate::models::{HistoryPoint, SystemStats, TempGroup};
+use crate::clock::{self, Clock, Reading};
+use crate::collector::round1;
+use crate::columns::{Column, HeapSize, Interner};
+use crate::config;
+use crate::metrics::{self, MetricRegistry, Sample, SeriesKey};
+use crate::models::{
+    Aggregate, ClockFlag, ClockFlagKind, DayAvailability, Gap, GapReason, HistoryPoint,
+    ImportReport, MetricKind, SeriesData, SeriesPoint, SystemStats, TempGroup, TierImport,
+};
+use crate::sketch::Sketch;
+use crate::wal::{self, Wal};
+use crate::writes;
 use chrono::{DateTime, TimeDelta, Utc};
-use parking_lot::RwLock;
-use std::collections::VecDeque;
+use log::warn;
+use parking_lot::{Mutex, RwLock};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-const RAW_RETENTION_SECS: i64 = 300; // 5 minutes of raw data
-const DAY_BUCKET_SECS: i64 = 60; // 1-minute buckets for day view
-const WEEK_BUCKET_SECS: i64 = 900; // 15-minute buckets for week view
-const DAY_RETENTION_SECS: i64 = 86400; // 24 hours
-const WEEK_RETENTION_SECS: i64 = 604800; // 7 days
+mod sqlite;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub use sqlite::is_database;
+
+pub const WAL_DIR: &str = "history"; // segment directory inside the data dir
+pub const SQLITE_FILE: &str = "history.db"; // database inside the data dir with `storage: sqlite`
+const LEGACY_FILE: &str = "history.json.zst";
+const LEGACY_JSON_FILE: &str = "history.json";
+const FLUSH_INTERVAL_SECS: u64 = 10; // at most this much is lost on power failure
+const LOW_WEAR_FLUSH_INTERVAL_SECS: u64 = 600;
+const COMPACT_SEGMENT_BYTES: u64 = 4 * 1024 * 1024; // snapshot once the active segment grows past this
+const GAP_THRESHOLD_SECS: i64 = 120; // longer silences are recorded as gaps
+const GAP_THRESHOLD_SAMPLES: i64 = 3; // ...as are silences this many sample intervals long
+const BOOT_SYNC_GRACE_SECS: i64 = 600; // the clock of a freshly booted board may not be synced yet
+const MAX_HOLD_SECS: i64 = 1800; // stop waiting for a time sync after this long
+const MAX_HELD_SAMPLES: usize = 4096; // oldest held samples are dropped beyond this
+const MAX_DECODED_BYTES: u64 = 512 * 1024 * 1024; // history files expanding past this are refused
+
+/// Configuration of one aggregation tier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TierConfig {
+    /// Name used as `/api/history?range=<name>`.
+    pub name: String,
+    /// Width of each bucket.
+    pub resolution_secs: i64,
+    /// How far back buckets are kept.
+    pub retention_secs: i64,
+}
+
+impl TierConfig {
+    fn new(name: &str, resolution_secs: i64, retention_secs: i64) -> Self {
+        TierConfig {
+            name: name.to_string(),
+            resolution_secs,
+            retention_secs,
+        }
+    }
+}
+
+/// Where history is saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// Append-only segment log in `/data/history`.
+    #[default]
+    Log,
+    /// SQLite database at `/data/history.db`, readable with standard tools.
+    Sqlite,
+}
+
+/// `history` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// How long unaggregated samples are kept (`range=raw`).
+    pub raw_retention_secs: i64,
+    pub tiers: Vec<TierConfig>,
+    /// Write only closed buckets of the finest tier instead of every sample,
+    /// and flush less often. Raw samples are then not restored on restart.
+    pub low_wear: bool,
+    /// How often buffered history is written out. Defaults to 10 seconds, or
+    /// 10 minutes in low-wear mode.
+    pub flush_interval_secs: Option<u64>,
+    /// How often system stats are sampled into history, whether or not
+    /// anyone is watching the live view.
+    pub sample_interval_secs: u64,
+    /// Upper bound on memory held by history. Once reached, the oldest
+    /// entries of the largest tier are dropped early. Zero for no limit.
+    pub memory_budget_mb: u64,
+    /// Switching migrates what the other backend saved on the next start.
+    pub storage: StorageKind,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            raw_retention_secs: 300, // 5 minutes of raw data
+            tiers: vec![
+                TierConfig::new("day", 60, 86400),    // 1-minute buckets, 24 hours
+                TierConfig::new("week", 900, 604800), // 15-minute buckets, 7 days
+                TierConfig::new("month", 3600, 7776000), // 1-hour buckets, 90 days
+                TierConfig::new("year", 86400, 63072000), // 1-day buckets, 2 years
+            ],
+            low_wear: false,
+            flush_interval_secs: None,
+            sample_interval_secs: 5,
+            memory_budget_mb: 64,
+            storage: StorageKind::Log,
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Drop invalid or duplicate tiers and order the rest from finest to coarsest.
+    fn validated(mut self) -> Self {
+        let mut tiers: Vec<TierConfig> = Vec::new();
+        for tier in self.tiers {
+            if tier.resolution_secs <= 0 || tier.retention_secs <= 0 {
+                eprintln!(
+                    "Ignoring history tier '{}': resolution and retention must be positive",
+                    tier.name
+                );
+            } else if tier.name == "raw" || tiers.iter().any(|t| t.name == tier.name) {
+                eprintln!(
+                    "Ignoring history tier '{}': duplicate or reserved name",
+                    tier.name
+                );
+            } else {
+                tiers.push(tier);
+            }
+        }
+        tiers.sort_by_key(|t| t.resolution_secs);
+        self.tiers = tiers;
+        self.sample_interval_secs = self.sample_interval_secs.max(1);
+        self
+    }
+}
+
+/// Summary of one metric within a bucket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FieldStats {
+    sum: f64,
+    count: u32,
+    min: f64,
+    max: f64,
+    last: f64,
+    sketch: Sketch,
+}
+
+impl FieldStats {
+    fn add(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.sum += value;
+        self.count += 1;
+        self.last = value;
+        self.sketch.insert(value);
+    }
+
+    fn merge(&mut self, other: &FieldStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+        self.last = other.last;
+        self.sketch.merge(&other.sketch);
+    }
+
+    /// Stats for buckets saved before min/max were tracked, where only the
+    /// average survives.
+    fn from_average(avg: f64, count: u32) -> Self {
+        let mut sketch = Sketch::default();
+        sketch.insert_n(avg, count);
+        FieldStats {
+            sum: avg * count as f64,
+            count,
+            min: avg,
+            max: avg,
+            last: avg,
+            sketch,
+        }
+    }
+
+    fn get(&self, agg: Aggregate) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let value = match agg {
+            Aggregate::Avg => self.sum / self.count as f64,
+            Aggregate::Min => self.min,
+            Aggregate::Max => self.max,
+            Aggregate::Last => self.last,
+            Aggregate::P95 => self.sketch.quantile(0.95)?,
+            Aggregate::P99 => self.sketch.quantile(0.99)?,
+        };
+        // Sketch estimates may overshoot the exact extremes slightly
+        Some(value.clamp(self.min, self.max))
+    }
+}
+
+impl HeapSize for FieldStats {
+    fn heap_bytes(&self) -> usize {
+        self.sketch.heap_bytes()
+    }
+}
+
+/// Every series observed during one bucket interval. This is the layout
+/// used in the log; in memory buckets are split into per-series columns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Bucket {
+    timestamp: DateTime<Utc>,
+    /// In first-seen order, which keeps sensors in collector order.
+    series: Vec<SeriesStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeriesStats {
+    key: SeriesKey,
+    stats: FieldStats,
+}
+
+impl Bucket {
+    fn new(timestamp: DateTime<Utc>) -> Self {
+        Bucket {
+            timestamp,
+            series: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, samples: &[Sample]) {
+        for sample in samples {
+            let pos = match self.series.iter().position(|s| s.key == sample.key) {
+                Some(pos) => pos,
+                None => {
+                    self.series.push(SeriesStats {
+                        key: sample.key.clone(),
+                        stats: FieldStats::default(),
+                    });
+                    self.series.len() - 1
+                }
+            };
+            self.series[pos].stats.add(sample.value);
+        }
+    }
+}
+
+/// Bucket layout of the original day/week history file, holding only sums.
+#[derive(Deserialize)]
+struct LegacyBucket {
     timestamp: DateTime<Utc>,
     cpu_percent_sum: f64,
     cpu_freq_sum: u64,
@@ -27,213 +278,2258 @@ struct Bucket {
     temperatures: Vec<TempGroup>,
 }
 
-impl Bucket {
-    fn new(timestamp: DateTime<Utc>) -> Self {
-        Bucket {
-            timestamp,
-            cpu_percent_sum: 0.0,
-            cpu_freq_sum: 0,
-            cpu_temp_sum: 0.0,
-            cpu_temp_count: 0,
-            mem_percent_sum: 0.0,
-            disk_percent_sum: 0.0,
-            network_rx_sum: 0,
-            network_tx_sum: 0,
-            disk_read_sum: 0,
-            disk_write_sum: 0,
-            count: 0,
-            temperatures: Vec::new(),
-        }
-    }
-
-    fn add(&mut self, point: &HistoryPoint) {
-        self.cpu_percent_sum += point.cpu_percent as f64;
-        self.cpu_freq_sum += point.cpu_freq;
-        if let Some(temp) = point.cpu_temp {
-            self.cpu_temp_sum += temp as f64;
-            self.cpu_temp_count += 1;
-        }
-        self.mem_percent_sum += point.mem_percent as f64;
-        self.disk_percent_sum += point.disk_percent as f64;
-        self.network_rx_sum += point.network_rx_bytes_sec;
-        self.network_tx_sum += point.network_tx_bytes_sec;
-        self.disk_read_sum += point.disk_read_bytes_sec;
-        self.disk_write_sum += point.disk_write_bytes_sec;
-        self.count += 1;
-        // Keep the latest temperature readings
-        self.temperatures = point.temperatures.clone();
-    }
-
-    fn to_history_point(&self) -> HistoryPoint {
-        let n = self.count.max(1) as f64;
-        let n_u64 = self.count.max(1) as u64;
-        HistoryPoint {
-            timestamp: self.timestamp,
-            cpu_percent: (self.cpu_percent_sum / n) as f32,
-            cpu_freq: self.cpu_freq_sum / n_u64,
-            cpu_temp: if self.cpu_temp_count > 0 {
-                Some((self.cpu_temp_sum / self.cpu_temp_count as f64) as f32)
-            } else {
-                None
-            },
-            mem_percent: (self.mem_percent_sum / n) as f32,
-            disk_percent: (self.disk_percent_sum / n) as f32,
-            network_rx_bytes_sec: self.network_rx_sum / n_u64,
-            network_tx_bytes_sec: self.network_tx_sum / n_u64,
-            disk_read_bytes_sec: self.disk_read_sum / n_u64,
-            disk_write_bytes_sec: self.disk_write_sum / n_u64,
-            temperatures: self.temperatures.clone(),
+impl From<LegacyBucket> for Bucket {
+    fn from(b: LegacyBucket) -> Self {
+        let n = b.count.max(1) as f64;
+        let avg = |sum: f64| FieldStats::from_average(sum / n, b.count);
+        let cpu_temp = if b.cpu_temp_count > 0 {
+            FieldStats::from_average(b.cpu_temp_sum / b.cpu_temp_count as f64, b.cpu_temp_count)
+        } else {
+            FieldStats::default()
+        };
+        let fields = [
+            (metrics::CPU_PERCENT, avg(b.cpu_percent_sum)),
+            (metrics::CPU_FREQ, avg(b.cpu_freq_sum as f64)),
+            (metrics::CPU_TEMP, cpu_temp),
+            (metrics::MEM_PERCENT, avg(b.mem_percent_sum)),
+            (metrics::DISK_PERCENT, avg(b.disk_percent_sum)),
+            (metrics::NETWORK_RX, avg(b.network_rx_sum as f64)),
+            (metrics::NETWORK_TX, avg(b.network_tx_sum as f64)),
+            (metrics::DISK_READ, avg(b.disk_read_sum as f64)),
+            (metrics::DISK_WRITE, avg(b.disk_write_sum as f64)),
+        ];
+        let mut bucket = Bucket::new(b.timestamp);
+        for (metric, stats) in fields {
+            if stats.count > 0 {
+                bucket.series.push(SeriesStats {
+                    key: SeriesKey::new(metric),
+                    stats,
+                });
+            }
+        }
+        // Only the last reading of each group was kept; it is taken as a
+        // single sample
+        for group in b.temperatures {
+            bucket.series.push(SeriesStats {
+                key: SeriesKey::new(metrics::TEMPERATURE)
+                    .with_label(metrics::SENSOR_LABEL, &group.label),
+                stats: FieldStats::from_average(group.temperature as f64, 1),
+            });
+        }
+        bucket
+    }
+}
+
+/// Samples recorded together at one instant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawSample {
+    timestamp: DateTime<Utc>,
+    samples: Vec<Sample>,
+}
+
+impl From<HistoryPoint> for RawSample {
+    fn from(point: HistoryPoint) -> Self {
+        RawSample {
+            timestamp: point.timestamp,
+            samples: metrics::samples_from_point(&point),
+        }
+    }
+}
+
+/// Result of [`HistoryStore::window`].
+pub struct Window {
+    /// Tier the points were taken from.
+    pub range: String,
+    pub step_secs: i64,
+    pub points: Vec<HistoryPoint>,
+}
+
+/// Which part of history a query covers.
+pub enum Selection {
+    /// A whole tier, or `raw`.
+    Range(String),
+    /// A time window re-aggregated to a step, from the best fitting tier.
+    Window {
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        step_secs: Option<i64>,
+    },
+}
+
+/// One aggregation tier. Every series has its own column of buckets keyed by
+/// their start time in Unix seconds.
+#[derive(Clone, Serialize, Deserialize)]
+struct Tier {
+    name: String,
+    resolution_secs: i64,
+    retention_secs: i64,
+    columns: BTreeMap<u32, Column<FieldStats>>,
+}
+
+impl Tier {
+    fn empty(config: &TierConfig) -> Self {
+        Tier {
+            name: config.name.clone(),
+            resolution_secs: config.resolution_secs,
+            retention_secs: config.retention_secs,
+            columns: BTreeMap::new(),
+        }
+    }
+
+    /// An empty tier with the same layout.
+    fn shape(&self) -> Self {
+        Tier {
+            name: self.name.clone(),
+            resolution_secs: self.resolution_secs,
+            retention_secs: self.retention_secs,
+            columns: BTreeMap::new(),
+        }
+    }
+
+    fn first_time(&self) -> Option<i64> {
+        self.columns.values().filter_map(Column::first_time).min()
+    }
+
+    fn add(&mut self, id: u32, t: DateTime<Utc>, value: f64) {
+        let slot = bucket_start(t.timestamp(), self.resolution_secs);
+        self.columns.entry(id).or_default().slot_mut(slot).add(value);
+    }
+
+    fn merge(&mut self, id: u32, t: i64, stats: &FieldStats) {
+        let slot = bucket_start(t, self.resolution_secs);
+        self.columns.entry(id).or_default().slot_mut(slot).merge(stats);
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = (now - TimeDelta::seconds(self.retention_secs)).timestamp();
+        for column in self.columns.values_mut() {
+            column.prune(cutoff);
         }
+        self.columns.retain(|_, c| !c.is_empty());
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct HistoryStoreInner {
-    raw: VecDeque<HistoryPoint>,
-    day_buckets: VecDeque<Bucket>,
-    week_buckets: VecDeque<Bucket>,
+    series: Interner,
+    /// Unaggregated samples per series, keyed by Unix microseconds.
+    raw: BTreeMap<u32, Column<f64>>,
+    tiers: Vec<Tier>,
+    /// Periods without system samples, oldest first.
+    #[serde(default)]
+    gaps: VecDeque<Gap>,
+    /// Ranges with unreliable timestamps, oldest first.
+    #[serde(default)]
+    clock_flags: VecDeque<ClockFlag>,
+    /// Time of the newest system sample.
+    #[serde(default)]
+    last_sample: Option<DateTime<Utc>>,
+    /// Time of the first system sample ever recorded.
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    /// Samples not yet appended to the log.
+    #[serde(skip)]
+    pending: Vec<RawSample>,
+    /// Gaps and clock flags not yet appended to the log.
+    #[serde(skip)]
+    pending_gaps: Vec<Gap>,
+    #[serde(skip)]
+    pending_flags: Vec<ClockFlag>,
+}
+
+impl HistoryStoreInner {
+    fn add(&mut self, raw: &RawSample) {
+        if raw.samples.iter().any(|s| s.key.metric == metrics::CPU_PERCENT) {
+            self.saw_system_sample(raw.timestamp);
+        }
+        let micros = raw.timestamp.timestamp_micros();
+        for sample in &raw.samples {
+            let id = self.series.intern(&sample.key);
+            self.raw.entry(id).or_default().push(micros, sample.value);
+            for tier in &mut self.tiers {
+                tier.add(id, raw.timestamp, sample.value);
+            }
+        }
+    }
+
+    fn saw_system_sample(&mut self, t: DateTime<Utc>) {
+        // Not the maximum: after the clock is stepped back, later samples
+        // carry earlier times
+        self.last_sample = Some(t);
+        self.since = self.since.or(Some(t));
+    }
+
+    /// Fold buckets of another resolution into every tier.
+    fn merge(&mut self, buckets: &VecDeque<Bucket>, resolution_secs: i64) {
+        for bucket in buckets {
+            if bucket.series.iter().any(|s| s.key.metric == metrics::CPU_PERCENT) {
+                let end = bucket.timestamp + TimeDelta::seconds(resolution_secs);
+                self.saw_system_sample(end.min(Utc::now()));
+            }
+            let t = bucket.timestamp.timestamp();
+            for series in &bucket.series {
+                let id = self.series.intern(&series.key);
+                for tier in &mut self.tiers {
+                    tier.merge(id, t, &series.stats);
+                }
+            }
+        }
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>, raw_retention_secs: i64) {
+        let cutoff = (now - TimeDelta::seconds(raw_retention_secs)).timestamp_micros();
+        for column in self.raw.values_mut() {
+            column.prune(cutoff);
+        }
+        let columns = self.column_count();
+        self.raw.retain(|_, c| !c.is_empty());
+        for tier in &mut self.tiers {
+            tier.prune(now);
+        }
+        if self.column_count() < columns {
+            self.drop_unused_series();
+        }
+
+        let retention_secs = self
+            .tiers
+            .iter()
+            .map(|t| t.retention_secs)
+            .max()
+            .unwrap_or(raw_retention_secs);
+        let cutoff = now - TimeDelta::seconds(retention_secs);
+        while self.gaps.front().is_some_and(|g| g.end < cutoff) {
+            self.gaps.pop_front();
+        }
+        while self.clock_flags.front().is_some_and(|f| f.end < cutoff) {
+            self.clock_flags.pop_front();
+        }
+    }
+
+    /// Oldest data held in any tier.
+    fn first_time(&self) -> Option<DateTime<Utc>> {
+        let oldest = self.tiers.iter().filter_map(Tier::first_time).min()?;
+        DateTime::from_timestamp(oldest, 0)
+    }
+
+    fn column_count(&self) -> usize {
+        self.raw.len() + self.tiers.iter().map(|t| t.columns.len()).sum::<usize>()
+    }
+
+    /// Forget series with no values left in raw or any tier, so the keys of
+    /// series that stopped reporting do not pile up.
+    fn drop_unused_series(&mut self) {
+        let live: HashSet<u32> = self
+            .raw
+            .keys()
+            .chain(self.tiers.iter().flat_map(|t| t.columns.keys()))
+            .copied()
+            .collect();
+        if self.series.iter().all(|(id, _)| live.contains(&id)) {
+            return;
+        }
+        let remap = self.series.retain(|id| live.contains(&id));
+        self.raw = renumber(std::mem::take(&mut self.raw), &remap);
+        for tier in &mut self.tiers {
+            tier.columns = renumber(std::mem::take(&mut tier.columns), &remap);
+        }
+    }
+
+    /// Drop the oldest entries of whichever part (raw or a tier) takes the
+    /// most memory until the total, series keys included, fits `budget`.
+    /// Returns how many time slots were dropped.
+    fn shrink_to(&mut self, budget: usize) -> usize {
+        let budget = budget.saturating_sub(self.series.heap_bytes());
+        let mut sizes: Vec<usize> = std::iter::once(columns_bytes(&self.raw))
+            .chain(self.tiers.iter().map(|t| columns_bytes(&t.columns)))
+            .collect();
+        let mut total: usize = sizes.iter().sum();
+        let mut dropped = 0;
+        while total > budget {
+            let Some((largest, _)) = sizes
+                .iter()
+                .enumerate()
+                .filter(|(_, size)| **size > 0)
+                .max_by_key(|(_, size)| **size)
+            else {
+                break;
+            };
+            let freed = if largest == 0 {
+                pop_oldest(&mut self.raw)
+            } else {
+                pop_oldest(&mut self.tiers[largest - 1].columns)
+            };
+            if freed == 0 {
+                // Only empty columns left in this part
+                sizes[largest] = 0;
+                continue;
+            }
+            sizes[largest] -= freed.min(sizes[largest]);
+            total -= freed.min(total);
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.drop_unused_series();
+        }
+        dropped
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.series.heap_bytes()
+            + columns_bytes(&self.raw)
+            + self
+                .tiers
+                .iter()
+                .map(|t| columns_bytes(&t.columns))
+                .sum::<usize>()
+    }
+}
+
+fn columns_bytes<T: HeapSize>(columns: &BTreeMap<u32, Column<T>>) -> usize {
+    columns.values().map(Column::memory_bytes).sum()
+}
+
+/// Move `columns` to the ids `remap` gives them, dropping the unmapped ones.
+fn renumber<T>(columns: BTreeMap<u32, Column<T>>, remap: &[Option<u32>]) -> BTreeMap<u32, Column<T>> {
+    columns
+        .into_iter()
+        .filter_map(|(id, column)| Some((remap.get(id as usize).copied().flatten()?, column)))
+        .collect()
+}
+
+/// Drop the oldest time slot across `columns`. Returns the bytes freed.
+fn pop_oldest<T: HeapSize>(columns: &mut BTreeMap<u32, Column<T>>) -> usize {
+    let Some(oldest) = columns.values().filter_map(Column::first_time).min() else {
+        return 0;
+    };
+    let mut freed = 0;
+    for column in columns.values_mut() {
+        if column.first_time() == Some(oldest)
+            && let Some((_, value)) = column.pop_front()
+        {
+            freed += Column::entry_bytes(&value);
+        }
+    }
+    columns.retain(|_, c| !c.is_empty());
+    freed
+}
+
+fn bucket_start(t: i64, bucket_secs: i64) -> i64 {
+    t.div_euclid(bucket_secs) * bucket_secs
+}
+
+/// Entry in the history log. A store snapshot holds everything; the samples
+/// recorded after it are replayed on top at startup.
+#[derive(Deserialize)]
+enum Record {
+    Columns(Box<HistoryStoreInner>),
+    Samples(Vec<RawSample>),
+    /// Closed buckets of the finest tier, written in low-wear mode.
+    Buckets(VecDeque<Bucket>),
+    Gap(Gap),
+    ClockFlag(ClockFlag),
+    Imported(Imported),
+}
+
+/// Borrowing counterpart of [`Record`] used for writing.
+#[derive(Serialize)]
+enum RecordRef<'a> {
+    Columns(&'a HistoryStoreInner),
+    Samples(&'a [RawSample]),
+    Buckets(&'a [Bucket]),
+    Gap(&'a Gap),
+    ClockFlag(&'a ClockFlag),
+    Imported(&'a Imported),
+}
+
+/// What an import added, logged in place of a new snapshot.
+#[derive(Default, Serialize, Deserialize)]
+struct Imported {
+    /// Buckets per tier, filling only slots that were empty.
+    tiers: Vec<ImportedTier>,
+    gaps: Vec<Gap>,
+    clock_flags: Vec<ClockFlag>,
+    /// The store's first and newest sample times after the import.
+    since: Option<DateTime<Utc>>,
+    last_sample: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ImportedTier {
+    name: String,
+    buckets: Vec<Bucket>,
+}
+
+impl HistoryStoreInner {
+    /// Apply an import as logged. Slots that already hold data are left
+    /// alone, as they were when the import was made.
+    fn apply_import(&mut self, imported: Imported) {
+        for saved in imported.tiers {
+            let Some(pos) = self.tiers.iter().position(|t| t.name == saved.name) else {
+                continue;
+            };
+            for bucket in &saved.buckets {
+                let t = bucket.timestamp.timestamp();
+                for series in &bucket.series {
+                    let id = self.series.intern(&series.key);
+                    let column = self.tiers[pos].columns.entry(id).or_default();
+                    if !column.contains(t) {
+                        column.push(t, series.stats.clone());
+                    }
+                }
+            }
+        }
+        self.gaps.extend(imported.gaps);
+        self.gaps.make_contiguous().sort_by_key(|g| g.start);
+        self.clock_flags.extend(imported.clock_flags);
+        self.clock_flags.make_contiguous().sort_by_key(|f| f.start);
+        self.since = imported.since.or(self.since);
+        self.last_sample = imported.last_sample.or(self.last_sample);
+    }
+}
+
+/// Everything saved in the log directory or database at `path`, as a
+/// segment image (see [`wal::pack`]) that [`HistoryStore::decode`] reads.
+pub fn image(path: &Path) -> io::Result<Vec<u8>> {
+    if sqlite::is_database(path) {
+        let inner = sqlite::read(path)?.unwrap_or_default();
+        let json = serde_json::to_vec(&RecordRef::Columns(&inner)).map_err(io::Error::other)?;
+        wal::pack(&[json])
+    } else {
+        wal::pack(&Wal::read(path)?)
+    }
+}
+
+/// Durable home of the history. The store keeps everything in memory and
+/// answers queries itself; a backend saves what it is handed and gives it
+/// back at startup.
+trait Backend: Send {
+    /// Save one record. Returns the bytes written. `now` is the store's
+    /// retention clock, for backends that apply retention as they write.
+    fn append(&mut self, record: &RecordRef, now: DateTime<Utc>) -> io::Result<u64>;
+    /// Replace everything saved with `inner`.
+    fn snapshot(&mut self, inner: &HistoryStoreInner) -> io::Result<u64>;
+    /// Whether enough has been appended that a snapshot is due.
+    fn wants_snapshot(&self) -> bool;
+}
+
+impl Backend for Wal {
+    fn append(&mut self, record: &RecordRef, _now: DateTime<Utc>) -> io::Result<u64> {
+        let json = serde_json::to_vec(record).map_err(io::Error::other)?;
+        Wal::append(self, &json)
+    }
+
+    /// Write the snapshot as the start of a new segment and drop the older ones.
+    fn snapshot(&mut self, inner: &HistoryStoreInner) -> io::Result<u64> {
+        let json = serde_json::to_vec(&RecordRef::Columns(inner)).map_err(io::Error::other)?;
+        self.rotate(&json)
+    }
+
+    fn wants_snapshot(&self) -> bool {
+        self.active_bytes() >= COMPACT_SEGMENT_BYTES
+    }
+}
+
+/// Layout of the whole-store history file written before the log, with
+/// fixed day and week buckets.
+#[derive(Deserialize)]
+struct LegacyHistory {
+    raw: Vec<HistoryPoint>,
+    day_buckets: Vec<LegacyBucket>,
+    week_buckets: Vec<LegacyBucket>,
+}
+
+impl From<LegacyHistory> for HistoryStoreInner {
+    fn from(legacy: LegacyHistory) -> Self {
+        let mut inner = HistoryStoreInner::default();
+        for point in legacy.raw {
+            let raw = RawSample::from(point);
+            let micros = raw.timestamp.timestamp_micros();
+            for sample in &raw.samples {
+                let id = inner.series.intern(&sample.key);
+                inner.raw.entry(id).or_default().push(micros, sample.value);
+            }
+        }
+        let tiers = [
+            ("day", 60, 86400, legacy.day_buckets),
+            ("week", 900, 604800, legacy.week_buckets),
+        ];
+        for (name, resolution_secs, retention_secs, buckets) in tiers {
+            let mut tier = Tier {
+                name: name.to_string(),
+                resolution_secs,
+                retention_secs,
+                columns: BTreeMap::new(),
+            };
+            for bucket in buckets.into_iter().map(Bucket::from) {
+                let t = bucket.timestamp.timestamp();
+                for series in &bucket.series {
+                    let id = inner.series.intern(&series.key);
+                    tier.merge(id, t, &series.stats);
+                }
+            }
+            inner.tiers.push(tier);
+        }
+        inner
+    }
+}
+
+/// Parse a query timestamp given as RFC 3339 or Unix seconds.
+pub fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(secs) = s.parse::<i64>() {
+        return DateTime::from_timestamp(secs, 0);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Read access to the part of the store a query selected. Values are
+/// reduced straight from the columns, so nothing but the result is copied.
+struct View<'a> {
+    inner: &'a HistoryStoreInner,
+    /// `None` for raw samples, otherwise an index into `inner.tiers`.
+    tier: Option<usize>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    /// Re-aggregate to this many seconds; `None` returns entries as stored.
+    step_secs: Option<i64>,
+    agg: Aggregate,
+    /// Only entries from the bucket holding this time on, for polling.
+    since: Option<DateTime<Utc>>,
+}
+
+impl View<'_> {
+    /// Values of series `id`, oldest first.
+    fn values(&self, id: u32) -> Vec<(DateTime<Utc>, f64)> {
+        self.reduce(id, |stats| stats.get(self.agg))
+    }
+
+    /// Like [`View::values`], with each bucket summarised by `f` instead of
+    /// the query's aggregate. Raw samples without a step are returned as is.
+    fn reduce(&self, id: u32, f: impl Fn(&FieldStats) -> Option<f64>) -> Vec<(DateTime<Utc>, f64)> {
+        match self.tier {
+            None => {
+                let Some(column) = self.inner.raw.get(&id) else {
+                    return Vec::new();
+                };
+                let since = self.since.map(|t| match self.step_secs {
+                    Some(step) => HistoryStore::bucket_timestamp(t, step),
+                    None => t,
+                });
+                let from = self.from.max(since).map_or(i64::MIN, |t| t.timestamp_micros());
+                let to = self.to.map_or(i64::MAX, |t| t.timestamp_micros());
+                let entries = column
+                    .range(from, to)
+                    .filter_map(|(t, v)| Some((DateTime::from_timestamp_micros(t)?, v)));
+                match self.step_secs {
+                    Some(step) => regroup(entries, step, |s, v| s.add(*v), f),
+                    None => entries.map(|(t, v)| (t, *v)).collect(),
+                }
+            }
+            Some(index) => {
+                let tier = &self.inner.tiers[index];
+                let Some(column) = tier.columns.get(&id) else {
+                    return Vec::new();
+                };
+                // Include the bucket that straddles `from`
+                let from = self
+                    .from
+                    .map_or(i64::MIN, |t| t.timestamp() - tier.resolution_secs + 1);
+                // The bucket holding `since` may have changed since then
+                let since = self.since.map_or(i64::MIN, |t| match self.step_secs {
+                    Some(step) if step != tier.resolution_secs => {
+                        HistoryStore::bucket_timestamp(t, step).timestamp()
+                    }
+                    _ => bucket_start(t.timestamp(), tier.resolution_secs),
+                });
+                let from = from.max(since);
+                // Buckets start on whole seconds, so round `to` up
+                let to = self
+                    .to
+                    .map_or(i64::MAX, |t| t.timestamp() + i64::from(t.timestamp_subsec_nanos() > 0));
+                let entries = column
+                    .range(from, to)
+                    .filter_map(|(t, s)| Some((DateTime::from_timestamp(t, 0)?, s)));
+                match self.step_secs {
+                    Some(step) if step != tier.resolution_secs => {
+                        regroup(entries, step, |s, other| s.merge(other), f)
+                    }
+                    _ => entries.filter_map(|(t, s)| Some((t, f(s)?))).collect(),
+                }
+            }
+        }
+    }
+
+    fn unlabelled(&self, metric: &str) -> Vec<(DateTime<Utc>, f64)> {
+        self.inner
+            .series
+            .id(&SeriesKey::new(metric))
+            .map(|id| self.values(id))
+            .unwrap_or_default()
+    }
+
+    /// The fixed-field view served by `/api/history`. Only times the system
+    /// collector contributed to are included.
+    fn history_points(&self) -> Vec<HistoryPoint> {
+        let cpu = self.unlabelled(metrics::CPU_PERCENT);
+        let mut fields = [
+            metrics::CPU_FREQ,
+            metrics::CPU_TEMP,
+            metrics::MEM_PERCENT,
+            metrics::DISK_PERCENT,
+            metrics::NETWORK_RX,
+            metrics::NETWORK_TX,
+            metrics::DISK_READ,
+            metrics::DISK_WRITE,
+        ]
+        .map(|metric| Cursor::new(self.unlabelled(metric)));
+        let mut temps: Vec<(&str, Cursor)> = self
+            .inner
+            .series
+            .iter()
+            .filter(|(_, key)| key.metric == metrics::TEMPERATURE)
+            .filter_map(|(id, key)| {
+                let label = key.label(metrics::SENSOR_LABEL)?;
+                Some((label, Cursor::new(self.values(id))))
+            })
+            .collect();
+        // Seconds sampled per bucket; raw samples have no extent
+        let width = self
+            .step_secs
+            .or_else(|| Some(self.inner.tiers[self.tier?].resolution_secs));
+        let mut coverage = width.map(|width| {
+            let id = self.inner.series.id(&SeriesKey::new(metrics::COVERAGE));
+            let sums = id.map(|id| self.reduce(id, |s| Some(s.sum)));
+            let shares = sums
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(t, secs)| (t, (secs / width as f64).clamp(0.0, 1.0)))
+                .collect();
+            Cursor::new(shares)
+        });
+
+        cpu.into_iter()
+            .map(|(t, cpu_percent)| {
+                let [freq, cpu_temp, mem, disk, rx, tx, read, write] =
+                    fields.each_mut().map(|c| c.at(t));
+                let int = |v: Option<f64>| v.unwrap_or(0.0).round() as u64;
+                HistoryPoint {
+                    timestamp: t,
+                    cpu_percent: cpu_percent as f32,
+                    cpu_freq: int(freq),
+                    cpu_temp: cpu_temp.map(|v| v as f32),
+                    mem_percent: mem.unwrap_or(0.0) as f32,
+                    disk_percent: disk.unwrap_or(0.0) as f32,
+                    network_rx_bytes_sec: int(rx),
+                    network_tx_bytes_sec: int(tx),
+                    disk_read_bytes_sec: int(read),
+                    disk_write_bytes_sec: int(write),
+                    temperatures: temps
+                        .iter_mut()
+                        .filter_map(|(label, c)| {
+                            Some(TempGroup {
+                                label: label.to_string(),
+                                temperature: round1(c.at(t)? as f32),
+                            })
+                        })
+                        .collect(),
+                    coverage: coverage.as_mut().and_then(|c| c.at(t)).map(|c| c as f32),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Fold time-ordered entries into `step`-second buckets, each summarised by
+/// `reduce`.
+fn regroup<T>(
+    entries: impl Iterator<Item = (DateTime<Utc>, T)>,
+    step: i64,
+    fold: impl Fn(&mut FieldStats, T),
+    reduce: impl Fn(&FieldStats) -> Option<f64>,
+) -> Vec<(DateTime<Utc>, f64)> {
+    let mut out = Vec::new();
+    let mut current: Option<(DateTime<Utc>, FieldStats)> = None;
+    for (t, value) in entries {
+        let bucket = HistoryStore::bucket_timestamp(t, step);
+        match &mut current {
+            Some((start, stats)) if *start == bucket => fold(stats, value),
+            _ => {
+                if let Some((start, stats)) = current.take()
+                    && let Some(v) = reduce(&stats)
+                {
+                    out.push((start, v));
+                }
+                let mut stats = FieldStats::default();
+                fold(&mut stats, value);
+                current = Some((bucket, stats));
+            }
+        }
+    }
+    if let Some((start, stats)) = current
+        && let Some(v) = reduce(&stats)
+    {
+        out.push((start, v));
+    }
+    out
+}
+
+/// Walks a time-ordered series alongside another one.
+struct Cursor {
+    values: Vec<(DateTime<Utc>, f64)>,
+    pos: usize,
+}
+
+impl Cursor {
+    fn new(values: Vec<(DateTime<Utc>, f64)>) -> Self {
+        Cursor { values, pos: 0 }
+    }
+
+    /// Value at exactly `t`. Calls must come in time order.
+    fn at(&mut self, t: DateTime<Utc>) -> Option<f64> {
+        while self.values.get(self.pos).is_some_and(|(vt, _)| *vt < t) {
+            self.pos += 1;
+        }
+        self.values
+            .get(self.pos)
+            .filter(|(vt, _)| *vt == t)
+            .map(|(_, v)| *v)
+    }
+}
+
+/// Last reading of each counter series.
+type CounterReadings = HashMap<SeriesKey, (DateTime<Utc>, f64)>;
+
+/// History brought in from elsewhere; see [`HistoryStore::import`].
+pub enum ImportSource {
+    /// A whole store, from a log or an old history file.
+    Store(Box<HistoryStoreInner>),
+    /// Single values, as written by an export.
+    Rows(Vec<(SeriesKey, DateTime<Utc>, f64)>),
+}
+
+/// Something recorded while the clock was not yet trusted, kept with the
+/// boot clock reading it was taken at.
+struct Held {
+    reading: Reading,
+    item: HeldItem,
+}
+
+/// Samples, gaps and clock flags taken from the store to be appended.
+struct Queued {
+    samples: Vec<RawSample>,
+    /// Finest-tier resolution the samples are folded into, in low-wear mode.
+    resolution: Option<i64>,
+    gaps: Vec<Gap>,
+    flags: Vec<ClockFlag>,
+}
+
+enum HeldItem {
+    Stats(Box<SystemStats>),
+    Samples(Vec<Sample>),
 }
 
 #[derive(Clone)]
 pub struct HistoryStore {
     inner: Arc<RwLock<HistoryStoreInner>>,
+    /// `None` when the log or database could not be opened; history is
+    /// then kept in memory only. Only the flush task and shutdown take it;
+    /// recording never waits on a write.
+    backend: Arc<Mutex<Option<Box<dyn Backend>>>>,
+    /// Whether there is a backend to queue samples for.
+    persistent: bool,
+    registry: MetricRegistry,
+    /// Used to turn counter totals into rates.
+    counters: Arc<Mutex<CounterReadings>>,
+    raw_retention_secs: i64,
+    low_wear: bool,
+    flush_interval_secs: u64,
+    sample_interval_secs: u64,
+    /// Zero for no limit.
+    memory_budget_bytes: usize,
+    /// Set once the budget has been hit, so it is only logged once.
+    over_budget: Arc<AtomicBool>,
+    clock: Arc<Mutex<Clock>>,
+    /// Samples waiting for the clock to be synced; `None` once it is.
+    held: Arc<Mutex<Option<Vec<Held>>>>,
+    /// Whether the host rebooted since the last run, as the boot log has it.
+    rebooted: bool,
 }
 
 impl HistoryStore {
-    pub fn new() -> Self {
-        let inner = Self::load_from_disk().unwrap_or_else(|| HistoryStoreInner {
-            raw: VecDeque::new(),
-            day_buckets: VecDeque::new(),
-            week_buckets: VecDeque::new(),
+    /// `rebooted` gives the reason for the gap left by the downtime before
+    /// this start.
+    pub fn new(config: &HistoryConfig, rebooted: bool) -> Self {
+        let config = config.clone().validated();
+        let (path, other) = match config.storage {
+            StorageKind::Log => (config::data_path(WAL_DIR), config::data_path(SQLITE_FILE)),
+            StorageKind::Sqlite => (config::data_path(SQLITE_FILE), config::data_path(WAL_DIR)),
+        };
+
+        let opened = match config.storage {
+            StorageKind::Log => Wal::open(&path).map(|(wal, records)| {
+                let (snapshot, replay) = Self::recover(records);
+                (Box::new(wal) as Box<dyn Backend>, snapshot, replay)
+            }),
+            StorageKind::Sqlite => {
+                sqlite::SqliteBackend::open(&path, &config.tiers, config.raw_retention_secs)
+                    .map(|(db, snapshot)| (Box::new(db) as Box<dyn Backend>, snapshot, Vec::new()))
+            }
+        };
+        let (backend, snapshot, mut replay) = match opened {
+            Ok((backend, snapshot, replay)) => (Some(backend), snapshot, replay),
+            Err(e) => {
+                eprintln!("Failed to open history in {}: {}", path.display(), e);
+                (None, None, Vec::new())
+            }
+        };
+        let restored = snapshot.is_some();
+        if restored {
+            println!(
+                "Recovered history from {} (replayed {} records)",
+                path.display(),
+                replay.len()
+            );
+        }
+        // Nothing saved yet: carry over what the other backend or an older
+        // version left behind
+        let migrated = if restored || backend.is_none() {
+            None
+        } else {
+            Self::read_path(&other)
+                .ok()
+                .and_then(|(snapshot, records)| Some((snapshot?, records)))
+        };
+        let from_other = migrated.is_some();
+        let saved = match migrated {
+            Some((snapshot, records)) => {
+                println!("Migrating history from {} to {}", other.display(), path.display());
+                replay = records;
+                Some(snapshot)
+            }
+            None => snapshot.or_else(Self::load_from_disk),
+        };
+
+        let tiers_changed = saved.as_ref().is_some_and(|saved| {
+            saved.tiers.len() != config.tiers.len()
+                || saved.tiers.iter().zip(&config.tiers).any(|(t, c)| {
+                    t.name != c.name
+                        || t.resolution_secs != c.resolution_secs
+                        || t.retention_secs != c.retention_secs
+                })
         });
+        let mut inner = Self::reconcile(&config, saved);
+        Self::replay(&mut inner, replay, config.raw_retention_secs);
 
+        let store = Self::from_inner(&config, inner, backend, rebooted);
+        store.enforce_budget();
+
+        // Without an RTC the clock starts out stale. Hold samples until it
+        // looks synced rather than file them under a bogus time.
+        let now = Reading::now();
+        let last = store.inner.read().last_sample;
+        let suspect = !clock::plausible(now.wall)
+            || last.is_some_and(|last| now.wall < last)
+            || (clock::uptime().num_seconds() < BOOT_SYNC_GRACE_SECS && !clock::synced());
+        if suspect {
+            println!("Clock may not be synced yet; holding history samples until it is");
+            *store.held.lock() = Some(Vec::new());
+        } else {
+            store.record_downtime(now.wall);
+        }
+        println!(
+            "History holds {} KiB in memory",
+            store.inner.read().memory_bytes() / 1024
+        );
+
+        // A fresh snapshot is only needed when nothing was saved here yet,
+        // the tiers changed, or the log has grown enough to be due one
+        let grown = store
+            .backend
+            .lock()
+            .as_ref()
+            .is_some_and(|b| b.wants_snapshot());
+        if restored {
+            if tiers_changed || grown {
+                store.compact();
+            }
+        } else if store.compact() {
+            for legacy in [LEGACY_FILE, LEGACY_JSON_FILE] {
+                let _ = std::fs::remove_file(config::data_path(legacy));
+            }
+            if from_other {
+                Self::remove_saved(&other);
+            }
+        }
+        store
+    }
+
+    /// What a log directory or database holds: the newest snapshot and the
+    /// records after it.
+    fn read_path(path: &Path) -> io::Result<(Option<HistoryStoreInner>, Vec<Record>)> {
+        if sqlite::is_database(path) {
+            Ok((sqlite::read(path)?, Vec::new()))
+        } else {
+            Ok(Self::recover(Wal::read(path)?))
+        }
+    }
+
+    /// Delete a log directory or database once its history has moved.
+    fn remove_saved(path: &Path) {
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            let mut result = std::fs::remove_file(path);
+            for suffix in ["-wal", "-shm"] {
+                let mut side = path.as_os_str().to_owned();
+                side.push(suffix);
+                let _ = std::fs::remove_file(side);
+            }
+            if result.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::NotFound) {
+                result = Ok(());
+            }
+            result
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to remove migrated history in {}: {}", path.display(), e);
+        }
+    }
+
+    /// Load the log directory or database at `path` as it is, for reading
+    /// only: nothing is written back and tiers are kept as saved rather than
+    /// matched to `config`.
+    pub fn open_read_only(config: &HistoryConfig, path: &Path) -> io::Result<Self> {
+        let config = config.clone().validated();
+        let (snapshot, replay) = Self::read_path(path)?;
+        let mut inner = match snapshot {
+            Some(inner) => inner,
+            None => Self::reconcile(&config, None),
+        };
+        Self::replay(&mut inner, replay, config.raw_retention_secs);
+        Ok(Self::from_inner(&config, inner, None, false))
+    }
+
+    fn from_inner(
+        config: &HistoryConfig,
+        inner: HistoryStoreInner,
+        backend: Option<Box<dyn Backend>>,
+        rebooted: bool,
+    ) -> Self {
+        let default_interval = if config.low_wear {
+            LOW_WEAR_FLUSH_INTERVAL_SECS
+        } else {
+            FLUSH_INTERVAL_SECS
+        };
         HistoryStore {
             inner: Arc::new(RwLock::new(inner)),
+            persistent: backend.is_some(),
+            backend: Arc::new(Mutex::new(backend)),
+            registry: MetricRegistry::with_builtins(),
+            counters: Arc::new(Mutex::new(HashMap::new())),
+            raw_retention_secs: config.raw_retention_secs,
+            low_wear: config.low_wear,
+            flush_interval_secs: config.flush_interval_secs.unwrap_or(default_interval).max(1),
+            sample_interval_secs: config.sample_interval_secs,
+            memory_budget_bytes: (config.memory_budget_mb as usize).saturating_mul(1024 * 1024),
+            over_budget: Arc::new(AtomicBool::new(false)),
+            clock: Arc::new(Mutex::new(Clock::new())),
+            held: Arc::new(Mutex::new(None)),
+            rebooted,
         }
     }
 
-    fn load_from_disk() -> Option<HistoryStoreInner> {
-        let path = "/data/history.json.zst";
-        if let Ok(content) = std::fs::read(path) {
-            if let Ok(decompressed) = zstd::decode_all(content.as_slice()) {
-                if let Ok(inner) = serde_json::from_slice(&decompressed) {
-                    println!("Loaded compressed history from {}", path);
-                    return Some(inner);
+    /// Apply the records logged after the snapshot.
+    fn replay(inner: &mut HistoryStoreInner, records: Vec<Record>, raw_retention_secs: i64) {
+        for record in records {
+            match record {
+                Record::Samples(samples) => {
+                    for raw in samples {
+                        Self::ingest(inner, raw, raw_retention_secs, Utc::now());
+                    }
+                }
+                Record::Buckets(buckets) => {
+                    let resolution = inner.tiers.first().map_or(1, |t| t.resolution_secs);
+                    inner.merge(&buckets, resolution);
+                    inner.prune(Utc::now(), raw_retention_secs);
+                }
+                Record::Gap(gap) => inner.gaps.push_back(gap),
+                Record::ClockFlag(flag) => inner.clock_flags.push_back(flag),
+                Record::Imported(imported) => {
+                    inner.apply_import(imported);
+                    inner.prune(Utc::now(), raw_retention_secs);
                 }
+                Record::Columns(_) => {}
             }
         }
+    }
 
-        let legacy_path = "/data/history.json";
-        if let Ok(content) = std::fs::read_to_string(legacy_path) {
-            if let Ok(inner) = serde_json::from_str(&content) {
-                println!("Loaded history from {}", legacy_path);
-                return Some(inner);
+    /// Split log records into the newest snapshot and the records after it.
+    fn recover(records: Vec<Vec<u8>>) -> (Option<HistoryStoreInner>, Vec<Record>) {
+        let mut snapshot = None;
+        let mut replay = Vec::new();
+        for payload in records {
+            match serde_json::from_slice(&payload) {
+                Ok(Record::Columns(inner)) => {
+                    snapshot = Some(*inner);
+                    replay.clear();
+                }
+                Ok(record) => replay.push(record),
+                Err(e) => eprintln!("Skipping unreadable history record: {}", e),
             }
         }
+        (snapshot, replay)
+    }
+
+    /// Deserialize the JSON file at `path`, decompressing it first if it is
+    /// zstd. `Ok(None)` when it is not JSON of that shape.
+    fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+        let reader = wal::open_decoded(path, MAX_DECODED_BYTES)?;
+        match serde_json::from_reader(reader) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_io() => Err(e.into()),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Read an old day/week history file.
+    fn read_legacy(path: &Path) -> io::Result<Option<HistoryStoreInner>> {
+        let Some(legacy) = Self::read_json::<LegacyHistory>(path)? else {
+            return Ok(None);
+        };
+        println!("Migrating history from fixed day/week buckets to tiers");
+        Ok(Some(legacy.into()))
+    }
+
+    /// Read the whole-store files written before the log existed.
+    fn load_from_disk() -> Option<HistoryStoreInner> {
+        let path = config::data_path(LEGACY_FILE);
+        if let Ok(Some(inner)) = Self::read_legacy(&path) {
+            println!("Loaded compressed history from {}", path.display());
+            return Some(inner);
+        }
+
+        let legacy_path = config::data_path(LEGACY_JSON_FILE);
+        if let Ok(Some(inner)) = Self::read_legacy(&legacy_path) {
+            println!("Loaded history from {}", legacy_path.display());
+            return Some(inner);
+        }
         None
     }
 
-    pub fn save_to_disk(&self) {
-        let path = "/data/history.json.zst";
-        let inner = self.inner.read();
-        let _ = std::fs::create_dir_all("/data");
-        match serde_json::to_vec(&*inner) {
-            Ok(json) => match zstd::encode_all(json.as_slice(), 3) {
-                Ok(compressed) => {
-                    if let Err(e) = std::fs::write(path, compressed) {
-                        eprintln!("Failed to write history to {}: {}", path, e);
+    /// Match saved tiers to the configured ones. A tier that is new or whose
+    /// resolution changed is rebuilt from the saved tier that goes back the
+    /// furthest among those whose resolution evenly divides the new one.
+    fn reconcile(config: &HistoryConfig, saved: Option<HistoryStoreInner>) -> HistoryStoreInner {
+        let HistoryStoreInner {
+            series,
+            raw,
+            tiers: mut saved_tiers,
+            gaps,
+            clock_flags,
+            last_sample,
+            since,
+            ..
+        } = saved.unwrap_or_default();
+
+        let mut tiers: Vec<Tier> = Vec::with_capacity(config.tiers.len());
+        for tier_config in &config.tiers {
+            let mut tier = Tier::empty(tier_config);
+            if let Some(pos) = saved_tiers
+                .iter()
+                .position(|t| t.name == tier.name && t.resolution_secs == tier.resolution_secs)
+            {
+                tier.columns = std::mem::take(&mut saved_tiers[pos].columns);
+            } else {
+                let source = saved_tiers
+                    .iter()
+                    .chain(tiers.iter())
+                    .filter(|t| {
+                        t.resolution_secs <= tier.resolution_secs
+                            && tier.resolution_secs % t.resolution_secs == 0
+                    })
+                    .filter_map(|t| t.first_time().map(|oldest| (oldest, t)))
+                    .min_by_key(|(oldest, _)| *oldest)
+                    .map(|(_, t)| t);
+                if let Some(source) = source {
+                    println!(
+                        "Seeding history tier '{}' from '{}'",
+                        tier.name, source.name
+                    );
+                    for (&id, column) in &source.columns {
+                        for (t, stats) in column.iter() {
+                            tier.merge(id, t, stats);
+                        }
+                    }
+                }
+            }
+            tiers.push(tier);
+        }
+
+        let mut inner = HistoryStoreInner {
+            series,
+            raw,
+            tiers,
+            gaps,
+            clock_flags,
+            last_sample,
+            since,
+            pending: Vec::new(),
+            pending_gaps: Vec::new(),
+            pending_flags: Vec::new(),
+        };
+        // Stores saved before gaps were tracked count from their oldest data
+        if inner.since.is_none() {
+            inner.since = inner.first_time();
+            inner.last_sample = inner.last_sample.or(inner.since);
+        }
+        inner.prune(Utc::now(), config.raw_retention_secs);
+        // Tiers dropped above may have held the only values of some series
+        inner.drop_unused_series();
+        inner
+    }
+
+    /// Append buffered samples to the log every flush interval, compacting it
+    /// into a fresh snapshot once the active segment has grown large. Runs on
+    /// the blocking pool since every write is fsynced.
+    pub fn spawn(&self) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let period = std::time::Duration::from_secs(store.flush_interval_secs);
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick fires immediately and there is nothing to write yet
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let s = store.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    s.enforce_budget();
+                    let needs_compaction = s
+                        .backend
+                        .lock()
+                        .as_ref()
+                        .is_some_and(|b| b.wants_snapshot());
+                    if needs_compaction {
+                        s.compact();
                     } else {
-                        println!("Saved history to {}", path);
+                        s.write_pending(false);
                     }
+                })
+                .await;
+            }
+        });
+    }
+
+    /// Drop the oldest history until the store fits its memory budget.
+    fn enforce_budget(&self) {
+        if self.memory_budget_bytes == 0 {
+            return;
+        }
+        let dropped = self.inner.write().shrink_to(self.memory_budget_bytes);
+        if dropped > 0 && !self.over_budget.swap(true, Ordering::Relaxed) {
+            warn!(
+                "History reached its memory budget of {} MiB; the oldest buckets are now dropped before their retention ends",
+                self.memory_budget_bytes / (1024 * 1024)
+            );
+        }
+    }
+
+    /// How many series each metric among `keys` would have once `keys` are
+    /// recorded.
+    pub fn series_after(&self, keys: &HashSet<&SeriesKey>) -> HashMap<String, usize> {
+        let inner = self.inner.read();
+        let metrics: HashSet<&str> = keys.iter().map(|k| k.metric.as_str()).collect();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let held = inner.series.iter().map(|(_, key)| key);
+        let new = keys.iter().copied().filter(|k| inner.series.id(k).is_none());
+        for key in held.chain(new) {
+            if metrics.contains(key.metric.as_str()) {
+                *counts.entry(key.metric.clone()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn registry(&self) -> &MetricRegistry {
+        &self.registry
+    }
+
+    pub fn low_wear(&self) -> bool {
+        self.low_wear
+    }
+
+    pub fn flush_interval_secs(&self) -> u64 {
+        self.flush_interval_secs
+    }
+
+    pub fn sample_interval_secs(&self) -> u64 {
+        self.sample_interval_secs
+    }
+
+    /// Silences longer than this are recorded as gaps.
+    fn gap_threshold_secs(&self) -> i64 {
+        GAP_THRESHOLD_SECS.max(GAP_THRESHOLD_SAMPLES * self.sample_interval_secs as i64)
+    }
+
+    /// Write everything buffered, including the bucket still being filled.
+    pub fn flush(&self) {
+        self.write_pending(true);
+    }
+
+    /// Append buffered samples, gaps and clock flags to the log. In low-wear
+    /// mode samples are folded into finest-tier buckets first, and unless
+    /// `include_open` is set only buckets that have closed are written.
+    ///
+    /// The backend lock is held throughout so a concurrent snapshot cannot
+    /// slip in between taking the queue and appending it; the store itself
+    /// is only locked while the queue is taken.
+    fn write_pending(&self, include_open: bool) {
+        let mut backend = self.backend.lock();
+        let Some(backend) = backend.as_mut() else {
+            return;
+        };
+        let queued = self.take_queued(&mut self.inner.write(), include_open);
+        self.append_queued(backend.as_mut(), queued);
+    }
+
+    fn take_queued(&self, inner: &mut HistoryStoreInner, include_open: bool) -> Queued {
+        let resolution = inner
+            .tiers
+            .first()
+            .map(|t| t.resolution_secs)
+            .filter(|_| self.low_wear);
+        let samples = match resolution {
+            Some(res) if !include_open => {
+                let open_since = Self::bucket_timestamp(Utc::now(), res);
+                inner
+                    .pending
+                    .extract_if(.., |s| s.timestamp < open_since)
+                    .collect()
+            }
+            _ => inner.pending.drain(..).collect(),
+        };
+        Queued {
+            samples,
+            resolution,
+            gaps: std::mem::take(&mut inner.pending_gaps),
+            flags: std::mem::take(&mut inner.pending_flags),
+        }
+    }
+
+    /// Append what was taken from the queue. Whatever fails is put back for
+    /// the next attempt.
+    fn append_queued(&self, backend: &mut dyn Backend, queued: Queued) {
+        let Queued {
+            samples,
+            resolution,
+            gaps,
+            flags,
+        } = queued;
+        let now = self.clock.lock().retention_now();
+        let mut failed_gaps = Vec::new();
+        for gap in gaps {
+            match backend.append(&RecordRef::Gap(&gap), now) {
+                Ok(bytes) => writes::record(bytes),
+                Err(e) => {
+                    eprintln!("Failed to append history gap: {}", e);
+                    failed_gaps.push(gap);
                 }
-                Err(e) => eprintln!("Failed to compress history: {}", e),
-            },
-            Err(e) => eprintln!("Failed to serialize history: {}", e),
+            }
+        }
+        let mut failed_flags = Vec::new();
+        for flag in flags {
+            match backend.append(&RecordRef::ClockFlag(&flag), now) {
+                Ok(bytes) => writes::record(bytes),
+                Err(e) => {
+                    eprintln!("Failed to append history clock flag: {}", e);
+                    failed_flags.push(flag);
+                }
+            }
+        }
+        if !failed_gaps.is_empty() || !failed_flags.is_empty() {
+            let mut inner = self.inner.write();
+            inner.pending_gaps.splice(0..0, failed_gaps);
+            inner.pending_flags.splice(0..0, failed_flags);
+        }
+        if samples.is_empty() {
+            return;
+        }
+
+        let result = match resolution {
+            Some(res) => {
+                let mut buckets = VecDeque::new();
+                for raw in &samples {
+                    Self::add_into(&mut buckets, raw, res);
+                }
+                backend.append(&RecordRef::Buckets(buckets.make_contiguous()), now)
+            }
+            None => backend.append(&RecordRef::Samples(&samples), now),
+        };
+        match result {
+            Ok(bytes) => writes::record(bytes),
+            Err(e) => {
+                eprintln!("Failed to append history: {}", e);
+                self.inner.write().pending.splice(0..0, samples);
+            }
         }
     }
 
-    pub fn push(&self, stats: &SystemStats) {
-        let point = HistoryPoint::from(stats);
-        let mut store = self.inner.write();
-        let now = Utc::now();
+    /// Save a snapshot of the whole store in place of everything saved
+    /// before. Returns whether the snapshot was written.
+    fn compact(&self) -> bool {
+        let mut backend = self.backend.lock();
+        let Some(backend) = backend.as_mut() else {
+            return false;
+        };
 
-        // Add raw point
-        store.raw.push_back(point.clone());
+        // The copy covers everything queued, taken under one lock so no
+        // sample lands in between. It is written with the store unlocked, so
+        // recording and queries go on while the snapshot is serialized and
+        // synced.
+        let (copy, drained, gaps, flags) = {
+            let mut inner = self.inner.write();
+            let drained = std::mem::take(&mut inner.pending);
+            let gaps = std::mem::take(&mut inner.pending_gaps);
+            let flags = std::mem::take(&mut inner.pending_flags);
+            (inner.clone(), drained, gaps, flags)
+        };
+        let result = backend.snapshot(&copy);
+        drop(copy);
+        match result {
+            Ok(bytes) => {
+                writes::record(bytes);
+                println!("Compacted history ({} byte snapshot)", bytes);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to compact history log: {}", e);
+                let mut inner = self.inner.write();
+                inner.pending.splice(0..0, drained);
+                inner.pending_gaps.splice(0..0, gaps);
+                inner.pending_flags.splice(0..0, flags);
+                false
+            }
+        }
+    }
+
+    /// Record a gap covering the time pi-dash was down, judging by the last
+    /// sample it saved. Sampling restarts at `now`.
+    fn record_downtime(&self, now: DateTime<Utc>) {
+        let last = self.inner.read().last_sample;
+        if let Some(last) = last
+            && (now - last).num_seconds() > self.gap_threshold_secs()
+        {
+            self.add_gap(Gap {
+                start: last,
+                end: now,
+                reason: if self.rebooted {
+                    GapReason::Reboot
+                } else {
+                    GapReason::Stopped
+                },
+            });
+        }
+        let mut inner = self.inner.write();
+        if inner.last_sample.is_some() {
+            inner.last_sample = Some(now);
+        }
+    }
 
-        // Aggregate into day bucket (1-minute)
-        Self::aggregate_into(&mut store.day_buckets, &point, DAY_BUCKET_SECS);
+    fn add_gap(&self, gap: Gap) {
+        warn!(
+            "No history samples from {} to {} ({:?})",
+            gap.start, gap.end, gap.reason
+        );
+        let mut inner = self.inner.write();
+        if self.persistent {
+            inner.pending_gaps.push(gap.clone());
+        }
+        inner.gaps.push_back(gap);
+    }
 
-        // Aggregate into week bucket (15-minute)
-        Self::aggregate_into(&mut store.week_buckets, &point, WEEK_BUCKET_SECS);
+    fn add_clock_flag(&self, flag: ClockFlag) {
+        warn!(
+            "History timestamps from {} to {} are unreliable ({:?})",
+            flag.start, flag.end, flag.kind
+        );
+        let mut inner = self.inner.write();
+        if self.persistent {
+            inner.pending_flags.push(flag.clone());
+        }
+        inner.clock_flags.push_back(flag);
+    }
 
-        // Prune old data
-        Self::prune(&mut store.raw, now, RAW_RETENTION_SECS);
-        Self::prune_buckets(&mut store.day_buckets, now, DAY_RETENTION_SECS);
-        Self::prune_buckets(&mut store.week_buckets, now, WEEK_RETENTION_SECS);
+    /// Whether samples are still being held back for an untrusted clock.
+    pub fn holding(&self) -> bool {
+        self.held.lock().is_some()
     }
 
-    fn aggregate_into(buckets: &mut VecDeque<Bucket>, point: &HistoryPoint, bucket_secs: i64) {
-        let bucket_ts = Self::bucket_timestamp(point.timestamp, bucket_secs);
+    /// While the clock is untrusted, keep `item` back and return `true`.
+    /// Once the clock has been stepped forward (a sync), systemd reports it
+    /// synced, or the grace period is over, everything held is re-stamped
+    /// from the boot clock and recorded, and `false` is returned.
+    fn hold(&self, reading: Reading, item: HeldItem) -> bool {
+        let mut held = self.held.lock();
+        let Some(queue) = held.as_mut() else {
+            return false;
+        };
 
-        if let Some(last) = buckets.back_mut() {
-            if last.timestamp == bucket_ts {
-                last.add(point);
-                return;
+        let stepped = self
+            .clock
+            .lock()
+            .observe(reading)
+            .is_some_and(|jump| jump > TimeDelta::zero());
+        let last = self.inner.read().last_sample;
+        let sane = clock::plausible(reading.wall) && last.is_none_or(|last| reading.wall >= last);
+        let settled = clock::synced() || clock::uptime().num_seconds() >= BOOT_SYNC_GRACE_SECS;
+        let waited = queue
+            .first()
+            .is_some_and(|first| reading.since(&first.reading).num_seconds() >= MAX_HOLD_SECS);
+        if !(sane && (stepped || settled) || waited) {
+            if queue.len() >= MAX_HELD_SAMPLES {
+                queue.remove(0);
             }
+            queue.push(Held { reading, item });
+            return true;
         }
 
-        let mut bucket = Bucket::new(bucket_ts);
-        bucket.add(point);
-        buckets.push_back(bucket);
+        let queue = held.take().unwrap_or_default();
+        drop(held);
+        self.release(queue, reading);
+        false
     }
 
-    fn bucket_timestamp(ts: DateTime<Utc>, bucket_secs: i64) -> DateTime<Utc> {
-        let epoch_secs = ts.timestamp();
-        let bucket_epoch = (epoch_secs / bucket_secs) * bucket_secs;
-        DateTime::from_timestamp(bucket_epoch, 0).unwrap_or(ts)
+    /// Record held samples under times derived from the boot clock and
+    /// `now`, which the wall clock is trusted at.
+    fn release(&self, queue: Vec<Held>, now: Reading) {
+        self.clock.lock().reanchor(now);
+        let start = queue.first().map_or(now.wall, |h| now.restamp(&h.reading));
+        self.record_downtime(start);
+        let Some(last) = queue.last() else {
+            println!("Clock looks synced; recording history");
+            return;
+        };
+        let end = now.restamp(&last.reading);
+        println!("Clock looks synced; re-stamping {} held history samples", queue.len());
+
+        for held in queue {
+            let t = now.restamp(&held.reading);
+            match held.item {
+                HeldItem::Stats(mut stats) => {
+                    stats.timestamp = t;
+                    self.push_stats(&stats, false);
+                }
+                HeldItem::Samples(samples) => self.record_samples(t, samples),
+            }
+        }
+        self.add_clock_flag(ClockFlag {
+            start,
+            end,
+            kind: ClockFlagKind::Restamped,
+        });
     }
 
-    fn prune(queue: &mut VecDeque<HistoryPoint>, now: DateTime<Utc>, retention_secs: i64) {
-        let cutoff = now - TimeDelta::seconds(retention_secs);
-        while let Some(front) = queue.front() {
-            if front.timestamp < cutoff {
-                queue.pop_front();
+    pub fn push(&self, stats: &SystemStats) {
+        let reading = Reading::at(stats.timestamp);
+        if self.hold(reading, HeldItem::Stats(Box::new(stats.clone()))) {
+            return;
+        }
+
+        let jump = self.clock.lock().observe(reading);
+        if let Some(jump) = jump {
+            let (start, end, kind) = if jump > TimeDelta::zero() {
+                (reading.wall - jump, reading.wall, ClockFlagKind::SteppedForward)
             } else {
-                break;
-            }
+                (reading.wall, reading.wall - jump, ClockFlagKind::SteppedBack)
+            };
+            self.add_clock_flag(ClockFlag { start, end, kind });
         }
+        self.push_stats(stats, jump.is_some());
     }
 
-    fn prune_buckets(buckets: &mut VecDeque<Bucket>, now: DateTime<Utc>, retention_secs: i64) {
-        let cutoff = now - TimeDelta::seconds(retention_secs);
-        while let Some(front) = buckets.front() {
-            if front.timestamp < cutoff {
-                buckets.pop_front();
+    /// Record a system sample. Each one also covers the time since the
+    /// previous sample, up to twice the sample interval; that coverage
+    /// is split on finest-tier bucket boundaries so every bucket is credited
+    /// with the seconds that fell into it. Nothing is covered across a clock
+    /// step, and a step is not a stall.
+    fn push_stats(&self, stats: &SystemStats, clock_stepped: bool) {
+        let t = stats.timestamp;
+        let (last, split_secs) = {
+            let inner = self.inner.read();
+            let split = inner.tiers.first().map_or(60, |t| t.resolution_secs);
+            (inner.last_sample, split)
+        };
+        let stalled = !clock_stepped
+            && last.is_some_and(|last| (t - last).num_seconds() > self.gap_threshold_secs());
+        if let Some(last) = last
+            && stalled
+        {
+            self.add_gap(Gap {
+                start: last,
+                end: t,
+                reason: GapReason::Stalled,
+            });
+        }
+
+        let mut samples = metrics::samples_from_stats(stats);
+        let coverage = |secs: f64| Sample::new(SeriesKey::new(metrics::COVERAGE), secs);
+        let mut start = match last {
+            Some(last) if !stalled && !clock_stepped => {
+                last.max(t - TimeDelta::seconds(2 * self.sample_interval_secs as i64))
+            }
+            _ => t,
+        };
+        let current = Self::bucket_timestamp(t, split_secs);
+        while start < t {
+            let bucket = Self::bucket_timestamp(start, split_secs);
+            let end = t.min(bucket + TimeDelta::seconds(split_secs));
+            let secs = (end - start).as_seconds_f64();
+            if bucket == current {
+                samples.push(coverage(secs));
             } else {
-                break;
+                self.record_samples(start, vec![coverage(secs)]);
             }
+            start = end;
         }
+        self.record_samples(t, samples);
     }
 
-    pub fn get_raw(&self) -> Vec<HistoryPoint> {
-        self.inner.read().raw.iter().cloned().collect()
+    /// Record samples for registered metrics. Counters are turned into
+    /// per-second rates; their first reading only sets the baseline.
+    pub fn record(&self, timestamp: DateTime<Utc>, samples: Vec<Sample>) {
+        if self.hold(Reading::at(timestamp), HeldItem::Samples(samples.clone())) {
+            return;
+        }
+        self.record_samples(timestamp, samples);
     }
 
-    pub fn get_day(&self) -> Vec<HistoryPoint> {
+    fn record_samples(&self, timestamp: DateTime<Utc>, samples: Vec<Sample>) {
+        let mut recorded = Vec::with_capacity(samples.len());
+        for mut sample in samples {
+            let Some(desc) = self.registry.get(&sample.key.metric) else {
+                continue;
+            };
+            if desc.kind == MetricKind::Counter {
+                let mut counters = self.counters.lock();
+                let previous = counters.insert(sample.key.clone(), (timestamp, sample.value));
+                let Some((prev_ts, prev_value)) = previous else {
+                    continue;
+                };
+                let elapsed = (timestamp - prev_ts).as_seconds_f64();
+                // A drop means the counter was reset
+                if elapsed <= 0.0 || sample.value < prev_value {
+                    continue;
+                }
+                sample.value = (sample.value - prev_value) / elapsed;
+            }
+            recorded.push(sample);
+        }
+        if recorded.is_empty() {
+            return;
+        }
+
+        let raw = RawSample {
+            timestamp,
+            samples: recorded,
+        };
+        let now = self.clock.lock().retention_now();
+        let mut store = self.inner.write();
+        if self.persistent {
+            store.pending.push(raw.clone());
+        }
+        Self::ingest(&mut store, raw, self.raw_retention_secs, now);
+    }
+
+    fn ingest(store: &mut HistoryStoreInner, raw: RawSample, raw_retention_secs: i64, now: DateTime<Utc>) {
+        store.add(&raw);
+        store.prune(now, raw_retention_secs);
+    }
+
+    /// Fold a sample into the bucket it falls into, created in order if
+    /// missing. Samples normally arrive in time order, so the newest bucket
+    /// is checked first.
+    fn add_into(buckets: &mut VecDeque<Bucket>, raw: &RawSample, bucket_secs: i64) {
+        let bucket_ts = Self::bucket_timestamp(raw.timestamp, bucket_secs);
+        let pos = match buckets.back() {
+            Some(last) if last.timestamp == bucket_ts => buckets.len() - 1,
+            Some(last) if last.timestamp > bucket_ts => {
+                match buckets.binary_search_by_key(&bucket_ts, |b| b.timestamp) {
+                    Ok(pos) => pos,
+                    Err(pos) => {
+                        buckets.insert(pos, Bucket::new(bucket_ts));
+                        pos
+                    }
+                }
+            }
+            _ => {
+                buckets.push_back(Bucket::new(bucket_ts));
+                buckets.len() - 1
+            }
+        };
+        buckets[pos].add(&raw.samples);
+    }
+
+    fn bucket_timestamp(ts: DateTime<Utc>, bucket_secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(bucket_start(ts.timestamp(), bucket_secs), 0).unwrap_or(ts)
+    }
+
+    /// Names accepted by [`HistoryStore::get`]: `raw` followed by the tiers.
+    pub fn ranges(&self) -> Vec<String> {
+        let inner = self.inner.read();
+        std::iter::once("raw".to_string())
+            .chain(inner.tiers.iter().map(|t| t.name.clone()))
+            .collect()
+    }
+
+    /// Name of the finest configured tier, used when no range is given.
+    pub fn default_range(&self) -> String {
+        self.inner
+            .read()
+            .tiers
+            .first()
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| "raw".to_string())
+    }
+
+    /// The part of the store covered by `selection`, with the range it comes
+    /// from and the step it is re-aggregated to.
+    ///
+    /// A window is served from the tier that still covers `from` at the
+    /// finest resolution; with an explicit step, the coarsest such tier that
+    /// is not coarser than the step. If no tier reaches back far enough the
+    /// longest one is used.
+    fn view<'a>(
+        &self,
+        inner: &'a HistoryStoreInner,
+        selection: &Selection,
+        agg: Aggregate,
+    ) -> Option<(String, Option<i64>, View<'a>)> {
+        let view = |tier, from, to, step_secs| View {
+            inner,
+            tier,
+            from,
+            to,
+            step_secs,
+            agg,
+            since: None,
+        };
+        let (from, to, step_secs) = match selection {
+            Selection::Range(range) if range == "raw" => {
+                return Some((range.clone(), None, view(None, None, None, None)));
+            }
+            Selection::Range(range) => {
+                let index = inner.tiers.iter().position(|t| &t.name == range)?;
+                return Some((range.clone(), None, view(Some(index), None, None, None)));
+            }
+            Selection::Window {
+                from,
+                to,
+                step_secs,
+            } => (*from, *to, *step_secs),
+        };
+        let needed_secs = (Utc::now() - from).num_seconds();
+
+        // (tier index, resolution, retention); raw samples arrive at most once a second
+        let mut candidates: Vec<(Option<usize>, i64, i64)> =
+            std::iter::once((None, 1, self.raw_retention_secs))
+                .chain(
+                    inner
+                        .tiers
+                        .iter()
+                        .enumerate()
+                        .map(|(i, t)| (Some(i), t.resolution_secs, t.retention_secs)),
+                )
+                .collect();
+        if let Some(step) = step_secs
+            && candidates.iter().any(|c| c.1 <= step)
+        {
+            candidates.retain(|c| c.1 <= step);
+        }
+        let covering = candidates.iter().filter(|c| c.2 >= needed_secs);
+        let chosen = match step_secs {
+            Some(_) => covering.max_by_key(|c| c.1),
+            None => covering.min_by_key(|c| c.1),
+        }
+        .or_else(|| candidates.iter().max_by_key(|c| c.2))
+        .copied()
+        .unwrap_or((None, 1, self.raw_retention_secs));
+        let (tier, resolution, _) = chosen;
+        let step = step_secs.unwrap_or(resolution).max(resolution);
+
+        let name = tier.map_or("raw", |i| inner.tiers[i].name.as_str());
+        Some((
+            name.to_string(),
+            Some(step),
+            view(tier, Some(from), Some(to), Some(step)),
+        ))
+    }
+
+    /// Points between `from` and `to`, re-aggregated to `step_secs`. With
+    /// `since`, only the buckets from the one holding `since` on.
+    pub fn window(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        step_secs: Option<i64>,
+        agg: Aggregate,
+        since: Option<DateTime<Utc>>,
+    ) -> Window {
+        let selection = Selection::Window {
+            from,
+            to,
+            step_secs,
+        };
+        let inner = self.inner.read();
+        match self.view(&inner, &selection, agg) {
+            Some((range, step, view)) => Window {
+                range,
+                step_secs: step.unwrap_or(1),
+                points: View { since, ..view }.history_points(),
+            },
+            None => Window {
+                range: "raw".to_string(),
+                step_secs: step_secs.unwrap_or(1),
+                points: Vec::new(),
+            },
+        }
+    }
+
+    /// Points for `raw` or a tier name; `None` for an unknown range. Tier
+    /// buckets are reduced with `agg`; raw samples are returned as recorded.
+    /// With `since`, only the buckets from the one holding `since` on.
+    pub fn get(
+        &self,
+        range: &str,
+        agg: Aggregate,
+        since: Option<DateTime<Utc>>,
+    ) -> Option<Vec<HistoryPoint>> {
+        let inner = self.inner.read();
+        let (_, _, view) = self.view(&inner, &Selection::Range(range.to_string()), agg)?;
+        Some(View { since, ..view }.history_points())
+    }
+
+    /// How far back `range` reaches; `None` for an unknown range.
+    pub fn retention_secs(&self, range: &str) -> Option<i64> {
+        if range == "raw" {
+            return Some(self.raw_retention_secs);
+        }
+        let inner = self.inner.read();
+        inner
+            .tiers
+            .iter()
+            .find(|t| t.name == range)
+            .map(|t| t.retention_secs)
+    }
+
+    /// Gaps overlapping `from`..`to`.
+    pub fn gaps(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Gap> {
         self.inner
             .read()
-            .day_buckets
+            .gaps
             .iter()
-            .map(|b| b.to_history_point())
+            .filter(|g| g.end > from && g.start < to)
+            .cloned()
             .collect()
     }
 
-    pub fn get_week(&self) -> Vec<HistoryPoint> {
+    /// Clock flags overlapping `from`..`to`.
+    pub fn clock_flags(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<ClockFlag> {
         self.inner
             .read()
-            .week_buckets
+            .clock_flags
+            .iter()
+            .filter(|f| f.end > from && f.start < to)
+            .cloned()
+            .collect()
+    }
+
+    /// Share of each of the last `days` UTC days (today included) not lost to
+    /// gaps. Days before the first sample are left out and the first day
+    /// counts from that sample.
+    pub fn availability(&self, days: u32) -> Vec<DayAvailability> {
+        let inner = self.inner.read();
+        let Some(since) = inner.since else {
+            return Vec::new();
+        };
+        let now = Utc::now();
+        let today = now.date_naive();
+        (0..days as i64)
+            .rev()
+            .filter_map(|ago| {
+                let date = today - TimeDelta::days(ago);
+                let day_start = date.and_time(chrono::NaiveTime::MIN).and_utc();
+                let start = day_start.max(since);
+                let end = (day_start + TimeDelta::days(1)).min(now);
+                let total = (end - start).num_seconds();
+                if total <= 0 {
+                    return None;
+                }
+                let downtime_secs: i64 = inner
+                    .gaps
+                    .iter()
+                    .map(|g| (g.end.min(end) - g.start.max(start)).num_seconds().max(0))
+                    .sum::<i64>()
+                    .min(total);
+                let percent = 100.0 * (total - downtime_secs) as f64 / total as f64;
+                Some(DayAvailability {
+                    date,
+                    availability_percent: (percent * 100.0).round() / 100.0,
+                    downtime_secs,
+                })
+            })
+            .collect()
+    }
+
+    /// Read a file holding a log segment image (see [`wal::pack`]) or a
+    /// history file as written by older versions, compressed or not.
+    /// `Ok(None)` if it is neither. Decompression stops with an error past
+    /// [`MAX_DECODED_BYTES`].
+    pub fn decode_file(&self, path: &Path) -> io::Result<Option<HistoryStoreInner>> {
+        if wal::is_image(path)? {
+            let data = std::fs::read(path)?;
+            let records = wal::unpack(&data, MAX_DECODED_BYTES).unwrap_or_default();
+            let (snapshot, replay) = Self::recover(records);
+            let mut inner = snapshot.unwrap_or_else(|| HistoryStoreInner {
+                tiers: self.inner.read().tiers.iter().map(Tier::shape).collect(),
+                ..Default::default()
+            });
+            Self::replay(&mut inner, replay, self.raw_retention_secs);
+            return Ok(Some(inner));
+        }
+        if let Some(inner) = Self::read_json::<HistoryStoreInner>(path)? {
+            return Ok(Some(inner));
+        }
+        Self::read_legacy(path)
+    }
+
+    /// Merge history from another instance or a backup. Values are
+    /// re-bucketed into this store's tiers, and only buckets this store has
+    /// no data for are filled: existing data always wins, so importing the
+    /// same file twice changes nothing. Raw samples are not imported.
+    ///
+    /// A whole store also brings along its gaps and clock flags from before
+    /// this store's first sample, plus a gap between its last sample and
+    /// that first sample.
+    ///
+    /// What was added is appended to the log as one record rather than
+    /// written out as a new snapshot.
+    pub fn import(&self, source: ImportSource) -> ImportReport {
+        let mut report = ImportReport::default();
+        let mut merged_ids = BTreeSet::new();
+        // Held throughout so samples queued before the import are logged
+        // ahead of it, as they were applied
+        let mut backend = self.backend.lock();
+        let now = self.clock.lock().retention_now();
+        let (queued, imported) = {
+            let mut inner = self.inner.write();
+            let mut imported = Imported::default();
+            let mut staged: Vec<Tier> = inner.tiers.iter().map(Tier::shape).collect();
+
+            match source {
+                ImportSource::Rows(rows) => {
+                    // Rows exported from a coarse tier must not land in
+                    // finer ones, so go by their closest spacing
+                    let mut times: HashMap<&SeriesKey, Vec<i64>> = HashMap::new();
+                    for (key, t, _) in &rows {
+                        times.entry(key).or_default().push(t.timestamp());
+                    }
+                    let spacing = times
+                        .into_values()
+                        .filter_map(|mut ts| {
+                            ts.sort_unstable();
+                            ts.windows(2).map(|w| w[1] - w[0]).filter(|&d| d > 0).min()
+                        })
+                        .min()
+                        .unwrap_or(0);
+                    staged.retain(|t| t.resolution_secs >= spacing);
+
+                    for (key, t, value) in rows {
+                        let id = inner.series.intern(&key);
+                        for tier in &mut staged {
+                            tier.add(id, t, value);
+                        }
+                    }
+                }
+                ImportSource::Store(other) => {
+                    // Their ids in order, mapped to ours
+                    let ids: Vec<u32> = other
+                        .series
+                        .iter()
+                        .map(|(_, key)| inner.series.intern(key))
+                        .collect();
+                    // Same choice of source as when tiers are reconfigured
+                    for tier in &mut staged {
+                        let source = other
+                            .tiers
+                            .iter()
+                            .filter(|t| {
+                                t.resolution_secs <= tier.resolution_secs
+                                    && tier.resolution_secs % t.resolution_secs == 0
+                            })
+                            .filter_map(|t| t.first_time().map(|oldest| (oldest, t)))
+                            .min_by_key(|(oldest, _)| *oldest)
+                            .map(|(_, t)| t);
+                        for (&id, column) in source.iter().flat_map(|s| &s.columns) {
+                            for (t, stats) in column.iter() {
+                                tier.merge(ids[id as usize], t, stats);
+                            }
+                        }
+                    }
+                    let (gaps, clock_flags) = Self::import_gaps(&mut inner, &other);
+                    report.gaps = gaps.len();
+                    imported.gaps = gaps;
+                    imported.clock_flags = clock_flags;
+                }
+            }
+
+            for staged in staged {
+                let Some(tier) = inner.tiers.iter_mut().find(|t| t.name == staged.name) else {
+                    continue;
+                };
+                let cutoff = (now - TimeDelta::seconds(tier.retention_secs)).timestamp();
+                let mut counts = TierImport {
+                    name: tier.name.clone(),
+                    merged: 0,
+                    skipped: 0,
+                    expired: 0,
+                };
+                let mut added: BTreeMap<i64, Vec<(u32, FieldStats)>> = BTreeMap::new();
+                for (id, column) in staged.columns {
+                    let local = tier.columns.entry(id).or_default();
+                    for (t, stats) in column.iter() {
+                        if t < cutoff {
+                            counts.expired += 1;
+                        } else if local.contains(t) {
+                            counts.skipped += 1;
+                        } else {
+                            local.push(t, stats.clone());
+                            added.entry(t).or_default().push((id, stats.clone()));
+                            counts.merged += 1;
+                            merged_ids.insert(id);
+                            let t = DateTime::from_timestamp(t, 0);
+                            report.from = report.from.min(t).or(t);
+                            report.to = report.to.max(t);
+                        }
+                    }
+                }
+                tier.columns.retain(|_, c| !c.is_empty());
+                report.tiers.push(counts);
+                imported.tiers.push(ImportedTier {
+                    name: staged.name,
+                    buckets: added
+                        .into_iter()
+                        .filter_map(|(t, series)| {
+                            Some(Bucket {
+                                timestamp: DateTime::from_timestamp(t, 0)?,
+                                series: series
+                                    .into_iter()
+                                    .filter_map(|(id, stats)| {
+                                        let key = inner.series.key(id)?.clone();
+                                        Some(SeriesStats { key, stats })
+                                    })
+                                    .collect(),
+                            })
+                        })
+                        .collect(),
+                });
+            }
+            imported.since = inner.since;
+            imported.last_sample = inner.last_sample;
+
+            let keys: Vec<SeriesKey> = inner
+                .series
+                .iter()
+                .filter(|(id, _)| merged_ids.contains(id))
+                .map(|(_, key)| key.clone())
+                .collect();
+            report.series = keys.len();
+            for key in keys {
+                if self.registry.get(&key.metric).is_some() {
+                    continue;
+                }
+                let labels: Vec<&str> = key.labels.keys().map(String::as_str).collect();
+                let desc = metrics::desc(&key.metric, "", MetricKind::Gauge, &labels, "");
+                if self.registry.register(desc).is_ok() {
+                    report.new_metrics.push(key.metric);
+                }
+            }
+            (self.take_queued(&mut inner, true), imported)
+        };
+        if !report.new_metrics.is_empty() {
+            self.registry.save_to_disk();
+        }
+
+        let mut saved = true;
+        if let Some(backend) = backend.as_mut() {
+            self.append_queued(backend.as_mut(), queued);
+            match backend.append(&RecordRef::Imported(&imported), now) {
+                Ok(bytes) => writes::record(bytes),
+                Err(e) => {
+                    eprintln!("Failed to append imported history: {}", e);
+                    saved = false;
+                }
+            }
+        }
+        drop(backend);
+        if !saved {
+            // The import is only in memory; a snapshot saves it along with
+            // everything else
+            self.compact();
+        }
+        self.enforce_budget();
+        report
+    }
+
+    /// Take over the gaps and clock flags of `other` from before our first
+    /// sample, and record its downtime up to that sample. Returns the gaps
+    /// and clock flags added.
+    fn import_gaps(
+        inner: &mut HistoryStoreInner,
+        other: &HistoryStoreInner,
+    ) -> (Vec<Gap>, Vec<ClockFlag>) {
+        let Some(since) = other.since.into_iter().chain(inner.since).min() else {
+            return (Vec::new(), Vec::new());
+        };
+        let ours = inner.since;
+        let before = |end: DateTime<Utc>| ours.is_none_or(|ours| end <= ours);
+
+        let mut gaps: Vec<Gap> = other.gaps.iter().filter(|g| before(g.end)).cloned().collect();
+        if let (Some(last), Some(ours)) = (other.last_sample, ours)
+            && last < ours
+        {
+            gaps.push(Gap {
+                start: last,
+                end: ours,
+                reason: GapReason::Stopped,
+            });
+        }
+        let flags: Vec<ClockFlag> = other.clock_flags.iter().filter(|f| before(f.end)).cloned().collect();
+
+        inner.gaps.extend(gaps.iter().cloned());
+        inner.gaps.make_contiguous().sort_by_key(|g| g.start);
+        inner.clock_flags.extend(flags.iter().cloned());
+        inner.clock_flags.make_contiguous().sort_by_key(|f| f.start);
+        inner.since = Some(since);
+        if ours.is_none() {
+            inner.last_sample = other.last_sample;
+        }
+        (gaps, flags)
+    }
+
+    /// Hand every series of `metrics` (all when `None`) within `selection`
+    /// to `emit`, in key order. The store is locked while one series is read,
+    /// not while it is written out. `None` for an unknown range.
+    pub fn export(
+        &self,
+        metrics: Option<&[String]>,
+        selection: &Selection,
+        agg: Aggregate,
+        mut emit: impl FnMut(&SeriesKey, &[(DateTime<Utc>, f64)]) -> std::io::Result<()>,
+    ) -> Option<std::io::Result<()>> {
+        let mut keys: Vec<SeriesKey> = {
+            let inner = self.inner.read();
+            self.view(&inner, selection, agg)?;
+            inner
+                .series
+                .iter()
+                .filter(|(_, key)| metrics.is_none_or(|m| m.contains(&key.metric)))
+                .map(|(_, key)| key.clone())
+                .collect()
+        };
+        keys.sort();
+
+        for key in keys {
+            // Ids change when pruning drops series, so look it up again
+            let values = {
+                let inner = self.inner.read();
+                let (_, _, view) = self.view(&inner, selection, agg)?;
+                match inner.series.id(&key) {
+                    Some(id) => view.values(id),
+                    None => Vec::new(),
+                }
+            };
+            if values.is_empty() {
+                continue;
+            }
+            if let Err(e) = emit(&key, &values) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok(()))
+    }
+
+    /// Every series of `metric` whose labels include `labels`. Returns the
+    /// range and step used; `None` for an unknown range.
+    pub fn series(
+        &self,
+        metric: &str,
+        labels: &BTreeMap<String, String>,
+        selection: &Selection,
+        agg: Aggregate,
+    ) -> Option<(String, Option<i64>, Vec<SeriesData>)> {
+        let inner = self.inner.read();
+        let (range, step, view) = self.view(&inner, selection, agg)?;
+
+        let mut matching: Vec<(u32, &SeriesKey)> = inner
+            .series
             .iter()
-            .map(|b| b.to_history_point())
+            .filter(|(_, key)| {
+                key.metric == metric && labels.iter().all(|(k, v)| key.labels.get(k) == Some(v))
+            })
+            .collect();
+        matching.sort_by_key(|(_, key)| *key);
+
+        let series = matching
+            .into_iter()
+            .filter_map(|(id, key)| {
+                let points: Vec<SeriesPoint> = view
+                    .values(id)
+                    .into_iter()
+                    .map(|(timestamp, value)| SeriesPoint { timestamp, value })
+                    .collect();
+                (!points.is_empty()).then(|| SeriesData {
+                    labels: key.labels.clone(),
+                    points,
+                })
+            })
+            .collect();
+        Some((range, step, series))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One sample a minute for `minutes`, ending just before now.
+    fn samples(minutes: i64) -> Vec<RawSample> {
+        let end = Utc::now() - TimeDelta::seconds(1);
+        (0..minutes)
+            .rev()
+            .map(|i| RawSample {
+                timestamp: end - TimeDelta::minutes(i),
+                samples: vec![
+                    Sample::new(SeriesKey::new(metrics::CPU_PERCENT), i as f64),
+                    Sample::new(SeriesKey::new("temp").with_label("sensor", "a"), 40.0 + i as f64),
+                ],
+            })
             .collect()
     }
+
+    fn empty(config: &HistoryConfig) -> HistoryStoreInner {
+        HistoryStore::reconcile(config, None)
+    }
+
+    /// What the in-memory store holds after `samples`.
+    fn ingested(config: &HistoryConfig, samples: &[RawSample]) -> HistoryStoreInner {
+        let mut inner = empty(config);
+        for raw in samples {
+            HistoryStore::ingest(&mut inner, raw.clone(), config.raw_retention_secs, Utc::now());
+        }
+        inner
+    }
+
+    fn recovered(dir: &Path, config: &HistoryConfig) -> HistoryStoreInner {
+        let (snapshot, replay) = HistoryStore::recover(Wal::read(dir).unwrap());
+        let mut inner = snapshot.expect("log holds a snapshot");
+        HistoryStore::replay(&mut inner, replay, config.raw_retention_secs);
+        inner
+    }
+
+    fn assert_same(a: &HistoryStoreInner, b: &HistoryStoreInner) {
+        assert_eq!(serde_json::to_value(a).unwrap(), serde_json::to_value(b).unwrap());
+    }
+
+    #[test]
+    fn replay_after_snapshot() {
+        let config = HistoryConfig::default();
+        let all = samples(20);
+        let (before, after) = all.split_at(8);
+        let dir = tempfile::tempdir().unwrap();
+        let (mut wal, _) = Wal::open(dir.path()).unwrap();
+        Backend::append(&mut wal, &RecordRef::Samples(&before[..4]), Utc::now()).unwrap();
+        // Records before the newest snapshot are already part of it
+        wal.snapshot(&ingested(&config, before)).unwrap();
+        Backend::append(&mut wal, &RecordRef::Samples(&after[..5]), Utc::now()).unwrap();
+        Backend::append(&mut wal, &RecordRef::Samples(&after[5..]), Utc::now()).unwrap();
+        let gap = Gap {
+            start: all[2].timestamp,
+            end: all[3].timestamp,
+            reason: GapReason::Stopped,
+        };
+        Backend::append(&mut wal, &RecordRef::Gap(&gap), Utc::now()).unwrap();
+        drop(wal);
+
+        let mut expected = ingested(&config, &all);
+        expected.gaps.push_back(gap);
+        assert_same(&recovered(dir.path(), &config), &expected);
+    }
+
+    #[test]
+    fn replay_buckets_and_torn_tail() {
+        let config = HistoryConfig::default();
+        let all = samples(10);
+        let mut buckets = VecDeque::new();
+        for raw in &all {
+            HistoryStore::add_into(&mut buckets, raw, 60);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let (mut wal, _) = Wal::open(dir.path()).unwrap();
+        wal.snapshot(&empty(&config)).unwrap();
+        Backend::append(&mut wal, &RecordRef::Buckets(buckets.make_contiguous()), Utc::now()).unwrap();
+        Backend::append(&mut wal, &RecordRef::Samples(&samples(3)), Utc::now()).unwrap();
+        drop(wal);
+        // The last record was cut short by a crash
+        let segment = std::fs::read_dir(dir.path()).unwrap().next().unwrap().unwrap().path();
+        let len = std::fs::metadata(&segment).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&segment).unwrap().set_len(len - 1).unwrap();
+
+        let inner = recovered(dir.path(), &config);
+        let expected = ingested(&config, &all);
+        assert_eq!(
+            serde_json::to_value(&inner.tiers).unwrap(),
+            serde_json::to_value(&expected.tiers).unwrap()
+        );
+        // Low-wear buckets carry no raw samples
+        assert!(inner.raw.is_empty());
+        assert!(inner.last_sample.is_some());
+    }
+
+    fn stored(inner: &HistoryStoreInner) -> serde_json::Value {
+        serde_json::to_value((&inner.series, &inner.raw, &inner.tiers)).unwrap()
+    }
+
+    #[test]
+    fn pruned_series_are_forgotten() {
+        let config = HistoryConfig {
+            tiers: vec![TierConfig::new("day", 60, 3600)],
+            ..HistoryConfig::default()
+        };
+        let stale = RawSample {
+            timestamp: Utc::now() - TimeDelta::hours(2),
+            samples: vec![Sample::new(SeriesKey::new("stale"), 1.0)],
+        };
+        let mut all = vec![stale];
+        all.extend(samples(5));
+
+        let inner = ingested(&config, &all);
+        assert_eq!(inner.series.id(&SeriesKey::new("stale")), None);
+        // The remaining series are renumbered as if the stale one never was
+        assert_eq!(stored(&inner), stored(&ingested(&config, &all[1..])));
+    }
+
+    #[test]
+    fn budget_counts_series_keys() {
+        let config = HistoryConfig::default();
+        let mut inner = ingested(&config, &samples(30));
+        let keys = inner.series.heap_bytes();
+        assert!(keys > 0);
+        assert!(inner.memory_bytes() > keys);
+
+        // A budget the keys alone fill drops every value, and then the keys
+        inner.shrink_to(keys);
+        assert_eq!(inner.column_count(), 0);
+        assert_eq!(inner.series.iter().count(), 0);
+        assert_eq!(inner.memory_bytes(), inner.series.heap_bytes());
+    }
 }
@@ -0,0 +1,778 @@
+use super::{
+    Backend, Bucket, FieldStats, HistoryStoreInner, Imported, RawSample, RecordRef, Tier,
+    TierConfig, bucket_start,
+};
+use crate::metrics::{self, SeriesKey};
+use crate::models::{ClockFlag, Gap};
+use crate::sketch::Sketch;
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, params};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SCHEMA_VERSION: i64 = 2;
+const HEADER: &[u8] = b"SQLite format 3\0"; // first bytes of every database file
+const CHECKPOINT_WAL_BYTES: u64 = 4 * 1024 * 1024; // the WAL is copied into the database past this
+
+/// Tables laid out for querying with the `sqlite3` shell or any other tool.
+/// Times are Unix seconds (`samples.ts_us` and the bounds of gaps and clock
+/// flags in microseconds), labels a JSON object, and `points` joins buckets
+/// to their series with the average already worked out.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS series (
+    id INTEGER PRIMARY KEY,
+    metric TEXT NOT NULL,
+    labels TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS samples (
+    series INTEGER NOT NULL,
+    ts_us INTEGER NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_ts ON samples (ts_us);
+CREATE TABLE IF NOT EXISTS buckets (
+    tier TEXT NOT NULL,
+    series INTEGER NOT NULL,
+    ts INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    sum REAL NOT NULL,
+    min REAL NOT NULL,
+    max REAL NOT NULL,
+    last REAL NOT NULL,
+    sketch TEXT NOT NULL,
+    PRIMARY KEY (tier, series, ts)
+) WITHOUT ROWID;
+CREATE INDEX IF NOT EXISTS buckets_ts ON buckets (tier, ts);
+CREATE TABLE IF NOT EXISTS gaps (start INTEGER NOT NULL, end INTEGER NOT NULL, reason TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS clock_flags (start INTEGER NOT NULL, end INTEGER NOT NULL, kind TEXT NOT NULL);
+CREATE VIEW IF NOT EXISTS points AS
+    SELECT b.tier, b.ts, s.metric, s.labels, b.sum / b.count AS avg, b.min, b.max, b.last, b.count
+    FROM buckets b JOIN series s ON s.id = b.series;
+";
+
+/// History kept in a SQLite database rather than the segment log. Every
+/// write is folded into the tier tables as it is appended, using the same
+/// bucket stats as memory, so the database is always a complete copy and
+/// nothing has to be replayed at startup. Retention is applied in place.
+///
+/// Checkpoints are run here rather than by SQLite, truncating the WAL each
+/// time, so what a write cost can be measured: the bytes it added to the WAL
+/// plus the pages a checkpoint then copied into the database.
+pub struct SqliteBackend {
+    conn: Connection,
+    wal_path: PathBuf,
+    page_size: u64,
+    tiers: Vec<TierConfig>,
+    raw_retention_secs: i64,
+    series: HashMap<SeriesKey, i64>,
+}
+
+impl SqliteBackend {
+    /// Open or create the database at `path` and load what it holds, or
+    /// `None` when nothing has been saved to it yet.
+    pub fn open(
+        path: &Path,
+        tiers: &[TierConfig],
+        raw_retention_secs: i64,
+    ) -> io::Result<(Self, Option<HistoryStoreInner>)> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut conn = Connection::open(path).map_err(io::Error::other)?;
+        // WAL journaling with full syncs: a power cut can lose the write in
+        // progress but never leaves the database corrupt
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| conn.pragma_update(None, "synchronous", "FULL"))
+            .and_then(|_| conn.pragma_update(None, "wal_autocheckpoint", 0))
+            .and_then(|_| migrate(&mut conn))
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .map_err(io::Error::other)?;
+        let page_size: i64 = conn
+            .query_row("PRAGMA page_size", [], |row| row.get(0))
+            .map_err(io::Error::other)?;
+        let mut wal_path = path.as_os_str().to_owned();
+        wal_path.push("-wal");
+        let loaded = load(&conn).map_err(io::Error::other)?;
+        let series = conn
+            .prepare("SELECT id, metric, labels FROM series")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((series_key(row.get(1)?, row.get(2)?), row.get(0)?)))?
+                    .collect::<rusqlite::Result<_>>()
+            })
+            .map_err(io::Error::other)?;
+        let backend = SqliteBackend {
+            conn,
+            wal_path: wal_path.into(),
+            page_size: page_size as u64,
+            tiers: tiers.to_vec(),
+            raw_retention_secs,
+            series,
+        };
+        Ok((backend, loaded))
+    }
+
+    /// Run `write` and return the bytes it cost on disk. Once the WAL has
+    /// grown past [`CHECKPOINT_WAL_BYTES`] it is checkpointed and truncated,
+    /// and the pages copied are counted too.
+    fn measured(
+        &mut self,
+        write: impl FnOnce(&mut Self) -> rusqlite::Result<()>,
+    ) -> rusqlite::Result<u64> {
+        let wal_len = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len());
+        let before = wal_len(&self.wal_path);
+        write(self)?;
+        let after = wal_len(&self.wal_path);
+        let mut bytes = after.saturating_sub(before);
+        if after >= CHECKPOINT_WAL_BYTES {
+            let copied: i64 =
+                self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(2))?;
+            bytes += copied.max(0) as u64 * self.page_size;
+        }
+        Ok(bytes)
+    }
+
+    /// Fold `samples` into every tier and into `samples`.
+    fn add_samples(&mut self, samples: &[RawSample], now: DateTime<Utc>) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut buckets = BTreeMap::new();
+        for raw in samples {
+            if raw.samples.iter().any(|s| s.key.metric == metrics::CPU_PERCENT) {
+                saw_system_sample(&tx, raw.timestamp)?;
+            }
+            let micros = raw.timestamp.timestamp_micros();
+            for sample in &raw.samples {
+                let id = series_id(&tx, &mut self.series, &sample.key)?;
+                tx.prepare_cached("INSERT INTO samples (series, ts_us, value) VALUES (?1, ?2, ?3)")?
+                    .execute(params![id, micros, sample.value])?;
+                for (i, tier) in self.tiers.iter().enumerate() {
+                    let slot = bucket_start(raw.timestamp.timestamp(), tier.resolution_secs);
+                    buckets
+                        .entry((i, id, slot))
+                        .or_insert_with(FieldStats::default)
+                        .add(sample.value);
+                }
+            }
+        }
+        merge_buckets(&tx, &self.tiers, buckets)?;
+        prune(&tx, &self.tiers, self.raw_retention_secs, now, &mut self.series)?;
+        tx.commit()
+    }
+
+    /// Fold closed buckets of the finest tier into every tier.
+    fn add_buckets(&mut self, closed: &[Bucket], now: DateTime<Utc>) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        let resolution = self.tiers.first().map_or(1, |t| t.resolution_secs);
+        let mut buckets = BTreeMap::new();
+        for bucket in closed {
+            if bucket.series.iter().any(|s| s.key.metric == metrics::CPU_PERCENT) {
+                let end = bucket.timestamp + TimeDelta::seconds(resolution);
+                saw_system_sample(&tx, end.min(Utc::now()))?;
+            }
+            for series in &bucket.series {
+                let id = series_id(&tx, &mut self.series, &series.key)?;
+                for (i, tier) in self.tiers.iter().enumerate() {
+                    let slot = bucket_start(bucket.timestamp.timestamp(), tier.resolution_secs);
+                    buckets
+                        .entry((i, id, slot))
+                        .or_insert_with(FieldStats::default)
+                        .merge(&series.stats);
+                }
+            }
+        }
+        merge_buckets(&tx, &self.tiers, buckets)?;
+        prune(&tx, &self.tiers, self.raw_retention_secs, now, &mut self.series)?;
+        tx.commit()
+    }
+
+    /// Add the buckets, gaps and clock flags an import brought in. Its
+    /// buckets only fill empty slots, so they are inserted as they are.
+    fn add_imported(&mut self, imported: &Imported) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        for tier in &imported.tiers {
+            for bucket in &tier.buckets {
+                let t = bucket.timestamp.timestamp();
+                for series in &bucket.series {
+                    let id = series_id(&tx, &mut self.series, &series.key)?;
+                    insert_bucket(&tx, &tier.name, id, t, &series.stats)?;
+                }
+            }
+        }
+        for gap in &imported.gaps {
+            insert_gap(&tx, gap)?;
+        }
+        for flag in &imported.clock_flags {
+            insert_clock_flag(&tx, flag)?;
+        }
+        if let Some(t) = imported.since {
+            set_meta(&tx, "since", &time_text(t))?;
+        }
+        if let Some(t) = imported.last_sample {
+            set_meta(&tx, "last_sample", &time_text(t))?;
+        }
+        tx.commit()
+    }
+
+    fn write_snapshot(&mut self, inner: &HistoryStoreInner) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute_batch(
+            "DELETE FROM series; DELETE FROM samples; DELETE FROM buckets;
+             DELETE FROM gaps; DELETE FROM clock_flags; DELETE FROM meta;",
+        )?;
+        let mut series = HashMap::new();
+        for (id, key) in inner.series.iter() {
+            let labels = labels_text(key);
+            tx.execute(
+                "INSERT INTO series (id, metric, labels) VALUES (?1, ?2, ?3)",
+                params![id, key.metric, labels],
+            )?;
+            series.insert(key.clone(), id as i64);
+        }
+        {
+            let mut insert = tx.prepare_cached("INSERT INTO samples (series, ts_us, value) VALUES (?1, ?2, ?3)")?;
+            for (&id, column) in &inner.raw {
+                for (t, value) in column.iter() {
+                    insert.execute(params![id, t, value])?;
+                }
+            }
+        }
+        for tier in &inner.tiers {
+            for (&id, column) in &tier.columns {
+                for (t, stats) in column.iter() {
+                    insert_bucket(&tx, &tier.name, id as i64, t, stats)?;
+                }
+            }
+        }
+        for gap in &inner.gaps {
+            insert_gap(&tx, gap)?;
+        }
+        for flag in &inner.clock_flags {
+            insert_clock_flag(&tx, flag)?;
+        }
+        let tiers: Vec<TierConfig> = inner
+            .tiers
+            .iter()
+            .map(|t| TierConfig::new(&t.name, t.resolution_secs, t.retention_secs))
+            .collect();
+        set_meta(&tx, "version", &SCHEMA_VERSION.to_string())?;
+        set_meta(&tx, "tiers", &serde_json::to_string(&tiers).unwrap_or_default())?;
+        if let Some(t) = inner.last_sample {
+            set_meta(&tx, "last_sample", &time_text(t))?;
+        }
+        if let Some(t) = inner.since {
+            set_meta(&tx, "since", &time_text(t))?;
+        }
+        tx.commit()?;
+        self.series = series;
+        self.tiers = tiers;
+        Ok(())
+    }
+}
+
+impl Backend for SqliteBackend {
+    fn append(&mut self, record: &RecordRef, now: DateTime<Utc>) -> io::Result<u64> {
+        self.measured(|db| match record {
+            RecordRef::Samples(samples) => db.add_samples(samples, now),
+            RecordRef::Buckets(buckets) => db.add_buckets(buckets, now),
+            RecordRef::Gap(gap) => insert_gap(&db.conn, gap),
+            RecordRef::ClockFlag(flag) => insert_clock_flag(&db.conn, flag),
+            RecordRef::Columns(inner) => db.write_snapshot(inner),
+            RecordRef::Imported(imported) => db.add_imported(imported),
+        })
+        .map_err(io::Error::other)
+    }
+
+    fn snapshot(&mut self, inner: &HistoryStoreInner) -> io::Result<u64> {
+        self.measured(|db| db.write_snapshot(inner))
+            .map_err(io::Error::other)
+    }
+
+    fn wants_snapshot(&self) -> bool {
+        // Appends keep the tables complete and pruned
+        false
+    }
+}
+
+/// Id of `key` in the `series` table, adding it when new.
+fn series_id(tx: &Transaction, ids: &mut HashMap<SeriesKey, i64>, key: &SeriesKey) -> rusqlite::Result<i64> {
+    if let Some(&id) = ids.get(key) {
+        return Ok(id);
+    }
+    tx.execute(
+        "INSERT INTO series (metric, labels) VALUES (?1, ?2)",
+        params![key.metric, labels_text(key)],
+    )?;
+    let id = tx.last_insert_rowid();
+    ids.insert(key.clone(), id);
+    Ok(id)
+}
+
+/// Merge stats into the stored buckets. Done here rather than in SQL so the
+/// sketches merge exactly as they do in memory.
+fn merge_buckets(
+    tx: &Transaction,
+    tiers: &[TierConfig],
+    buckets: BTreeMap<(usize, i64, i64), FieldStats>,
+) -> rusqlite::Result<()> {
+    let mut select = tx.prepare_cached(
+        "SELECT count, sum, min, max, last, sketch FROM buckets WHERE tier = ?1 AND series = ?2 AND ts = ?3",
+    )?;
+    for ((tier, id, slot), stats) in buckets {
+        let name = &tiers[tier].name;
+        let mut stored = select
+            .query_row(params![name, id, slot], field_stats)
+            .optional()?
+            .unwrap_or_default();
+        stored.merge(&stats);
+        insert_bucket(tx, name, id, slot, &stored)?;
+    }
+    Ok(())
+}
+
+/// Apply retention as of `now`, the store's retention clock, so the tables
+/// keep what memory keeps. Series left without samples or buckets are
+/// removed from `series` and the table.
+fn prune(
+    tx: &Transaction,
+    tiers: &[TierConfig],
+    raw_retention_secs: i64,
+    now: DateTime<Utc>,
+    series: &mut HashMap<SeriesKey, i64>,
+) -> rusqlite::Result<()> {
+    let cutoff = now - TimeDelta::seconds(raw_retention_secs);
+    tx.execute("DELETE FROM samples WHERE ts_us < ?1", [cutoff.timestamp_micros()])?;
+    let mut expired = 0;
+    for tier in tiers {
+        let cutoff = now - TimeDelta::seconds(tier.retention_secs);
+        expired += tx.execute(
+            "DELETE FROM buckets WHERE tier = ?1 AND ts < ?2",
+            params![tier.name, cutoff.timestamp()],
+        )?;
+    }
+    // Every sample is also in the tiers, so a series can only run out of
+    // values when buckets expire
+    if expired > 0 {
+        let dropped: Vec<i64> = tx
+            .prepare_cached(
+                "DELETE FROM series WHERE id NOT IN (SELECT series FROM buckets)
+                 AND id NOT IN (SELECT series FROM samples) RETURNING id",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        if !dropped.is_empty() {
+            series.retain(|_, id| !dropped.contains(id));
+        }
+    }
+    let retention_secs = tiers
+        .iter()
+        .map(|t| t.retention_secs)
+        .max()
+        .unwrap_or(raw_retention_secs);
+    let cutoff = (now - TimeDelta::seconds(retention_secs)).timestamp_micros();
+    tx.execute("DELETE FROM gaps WHERE end < ?1", [cutoff])?;
+    tx.execute("DELETE FROM clock_flags WHERE end < ?1", [cutoff])?;
+    Ok(())
+}
+
+/// Whether `table` keeps its bounds as RFC 3339 text, as version 1 did.
+fn text_bounds(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    let kind: Option<String> = conn
+        .query_row(
+            "SELECT type FROM pragma_table_info(?1) WHERE name = 'end'",
+            [table],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(kind.is_some_and(|k| k.eq_ignore_ascii_case("TEXT")))
+}
+
+/// Rewrite the gaps and clock flags of a version 1 database with integer
+/// bounds, so retention compares numbers rather than text.
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    for (table, kind) in [("gaps", "reason"), ("clock_flags", "kind")] {
+        if !text_bounds(&tx, table)? {
+            continue;
+        }
+        let rows: Vec<(Option<i64>, Option<i64>, String)> = tx
+            .prepare(&format!("SELECT start, end, {} FROM {}", kind, table))?
+            .query_map([], |row| {
+                Ok((
+                    time_micros(row.get_ref(0)?),
+                    time_micros(row.get_ref(1)?),
+                    row.get(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        tx.execute_batch(&format!(
+            "DROP TABLE {0};
+             CREATE TABLE {0} (start INTEGER NOT NULL, end INTEGER NOT NULL, {1} TEXT NOT NULL);",
+            table, kind
+        ))?;
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {} (start, end, {}) VALUES (?1, ?2, ?3)",
+            table, kind
+        ))?;
+        for (start, end, kind) in rows {
+            if let (Some(start), Some(end)) = (start, end) {
+                insert.execute(params![start, end, kind])?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+/// Whether `path` is a SQLite database file.
+pub fn is_database(path: &Path) -> bool {
+    use std::io::Read;
+    let mut header = [0; 16];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|_| header == HEADER)
+}
+
+/// Load the database at `path` without writing to it.
+pub fn read(path: &Path) -> io::Result<Option<HistoryStoreInner>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(io::Error::other)?;
+    load(&conn).map_err(io::Error::other)
+}
+
+fn load(conn: &Connection) -> rusqlite::Result<Option<HistoryStoreInner>> {
+    let version: Option<String> = conn
+        .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| row.get(0))
+        .optional()?;
+    if version.is_none() {
+        return Ok(None);
+    }
+    let meta = |key: &str| -> rusqlite::Result<Option<String>> {
+        conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+            .optional()
+    };
+
+    let mut inner = HistoryStoreInner::default();
+    let tiers: Vec<TierConfig> = meta("tiers")?
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or_default();
+    inner.tiers = tiers.iter().map(Tier::empty).collect();
+    inner.last_sample = meta("last_sample")?.as_deref().and_then(parse_time_text);
+    inner.since = meta("since")?.as_deref().and_then(parse_time_text);
+
+    let mut ids = HashMap::new();
+    let mut stmt = conn.prepare("SELECT id, metric, labels FROM series ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let key = series_key(row.get(1)?, row.get(2)?);
+        ids.insert(row.get::<_, i64>(0)?, inner.series.intern(&key));
+    }
+
+    let mut stmt = conn.prepare("SELECT series, ts_us, value FROM samples ORDER BY ts_us")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if let Some(&id) = ids.get(&row.get::<_, i64>(0)?) {
+            inner.raw.entry(id).or_default().push(row.get(1)?, row.get(2)?);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT tier, series, ts, count, sum, min, max, last, sketch FROM buckets ORDER BY ts",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let (Some(tier), Some(&id)) = (
+            inner.tiers.iter_mut().find(|t| t.name == name),
+            ids.get(&row.get::<_, i64>(1)?),
+        ) else {
+            continue;
+        };
+        let stats = FieldStats {
+            count: row.get(3)?,
+            sum: row.get(4)?,
+            min: row.get(5)?,
+            max: row.get(6)?,
+            last: row.get(7)?,
+            sketch: parse_sketch(row.get(8)?),
+        };
+        tier.merge(id, row.get(2)?, &stats);
+    }
+
+    let mut stmt = conn.prepare("SELECT start, end, reason FROM gaps ORDER BY start")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if let Some(gap) = parse_range(row, |start, end, reason| Gap {
+            start,
+            end,
+            reason,
+        })? {
+            inner.gaps.push_back(gap);
+        }
+    }
+    let mut stmt = conn.prepare("SELECT start, end, kind FROM clock_flags ORDER BY start")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if let Some(flag) = parse_range(row, |start, end, kind| ClockFlag {
+            start,
+            end,
+            kind,
+        })? {
+            inner.clock_flags.push_back(flag);
+        }
+    }
+    Ok(Some(inner))
+}
+
+fn field_stats(row: &rusqlite::Row) -> rusqlite::Result<FieldStats> {
+    Ok(FieldStats {
+        count: row.get(0)?,
+        sum: row.get(1)?,
+        min: row.get(2)?,
+        max: row.get(3)?,
+        last: row.get(4)?,
+        sketch: parse_sketch(row.get(5)?),
+    })
+}
+
+fn parse_sketch(json: String) -> Sketch {
+    serde_json::from_str(&json).unwrap_or_default()
+}
+
+fn insert_bucket(tx: &Connection, tier: &str, id: i64, ts: i64, stats: &FieldStats) -> rusqlite::Result<()> {
+    let sketch = serde_json::to_string(&stats.sketch).unwrap_or_default();
+    tx.prepare_cached(
+        "INSERT OR REPLACE INTO buckets (tier, series, ts, count, sum, min, max, last, sketch)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?
+    .execute(params![
+        tier,
+        id,
+        ts,
+        stats.count,
+        stats.sum,
+        stats.min,
+        stats.max,
+        stats.last,
+        sketch
+    ])?;
+    Ok(())
+}
+
+fn insert_gap(conn: &Connection, gap: &Gap) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO gaps (start, end, reason) VALUES (?1, ?2, ?3)",
+        params![gap.start.timestamp_micros(), gap.end.timestamp_micros(), enum_text(&gap.reason)],
+    )?;
+    Ok(())
+}
+
+fn insert_clock_flag(conn: &Connection, flag: &ClockFlag) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO clock_flags (start, end, kind) VALUES (?1, ?2, ?3)",
+        params![flag.start.timestamp_micros(), flag.end.timestamp_micros(), enum_text(&flag.kind)],
+    )?;
+    Ok(())
+}
+
+fn saw_system_sample(tx: &Transaction, t: DateTime<Utc>) -> rusqlite::Result<()> {
+    set_meta(tx, "last_sample", &time_text(t))?;
+    tx.execute(
+        "INSERT OR IGNORE INTO meta (key, value) VALUES ('since', ?1)",
+        [time_text(t)],
+    )?;
+    Ok(())
+}
+
+fn set_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+fn labels_text(key: &SeriesKey) -> String {
+    serde_json::to_string(&key.labels).unwrap_or_else(|_| "{}".to_string())
+}
+
+fn series_key(metric: String, labels: String) -> SeriesKey {
+    SeriesKey {
+        metric,
+        labels: serde_json::from_str(&labels).unwrap_or_default(),
+    }
+}
+
+fn time_text(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+fn parse_time_text(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// A unit enum as the name it has in the API.
+fn enum_text<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// A gap or clock flag bound in Unix microseconds. Databases not yet
+/// migrated, read without writing, still hold RFC 3339 text.
+fn time_micros(value: ValueRef) -> Option<i64> {
+    match value {
+        ValueRef::Integer(us) => Some(us),
+        ValueRef::Text(text) => {
+            parse_time_text(std::str::from_utf8(text).ok()?).map(|t| t.timestamp_micros())
+        }
+        _ => None,
+    }
+}
+
+fn parse_range<K: serde::de::DeserializeOwned, T>(
+    row: &rusqlite::Row,
+    build: impl FnOnce(DateTime<Utc>, DateTime<Utc>, K) -> T,
+) -> rusqlite::Result<Option<T>> {
+    let start = time_micros(row.get_ref(0)?).and_then(DateTime::from_timestamp_micros);
+    let end = time_micros(row.get_ref(1)?).and_then(DateTime::from_timestamp_micros);
+    let kind = serde_json::from_value(serde_json::Value::String(row.get(2)?)).ok();
+    Ok(start.zip(end).zip(kind).map(|((start, end), kind)| build(start, end, kind)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HistoryConfig, HistoryStore, Record};
+    use super::*;
+    use crate::metrics::Sample;
+    use crate::models::GapReason;
+    use std::collections::VecDeque;
+
+    /// One sample a minute for `minutes`, ending just before now.
+    fn samples(minutes: i64) -> Vec<RawSample> {
+        let end = Utc::now() - TimeDelta::seconds(1);
+        (0..minutes)
+            .rev()
+            .map(|i| RawSample {
+                timestamp: end - TimeDelta::minutes(i),
+                samples: vec![
+                    Sample::new(SeriesKey::new(metrics::CPU_PERCENT), i as f64),
+                    Sample::new(SeriesKey::new("temp").with_label("sensor", "a"), 40.0 + i as f64),
+                ],
+            })
+            .collect()
+    }
+
+    /// A database holding an empty store laid out as `config`.
+    fn create(path: &Path, config: &HistoryConfig) -> SqliteBackend {
+        let (mut db, loaded) = SqliteBackend::open(path, &config.tiers, config.raw_retention_secs).unwrap();
+        assert!(loaded.is_none());
+        db.snapshot(&HistoryStore::reconcile(config, None)).unwrap();
+        db
+    }
+
+    fn reopen(path: &Path, config: &HistoryConfig) -> HistoryStoreInner {
+        let (_, loaded) = SqliteBackend::open(path, &config.tiers, config.raw_retention_secs).unwrap();
+        loaded.expect("database holds a store")
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = HistoryConfig::default();
+        let all = samples(20);
+        let (older, newer) = all.split_at(12);
+        let mut buckets = VecDeque::new();
+        for raw in newer {
+            HistoryStore::add_into(&mut buckets, raw, 60);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let mut db = create(&path, &config);
+        db.append(&RecordRef::Samples(older), Utc::now()).unwrap();
+        db.append(&RecordRef::Buckets(buckets.make_contiguous()), Utc::now()).unwrap();
+        drop(db);
+
+        let mut expected = HistoryStore::reconcile(&config, None);
+        let records = vec![Record::Samples(older.to_vec()), Record::Buckets(buckets)];
+        HistoryStore::replay(&mut expected, records, config.raw_retention_secs);
+        let loaded = reopen(&path, &config);
+        assert_eq!(json(&loaded.tiers), json(&expected.tiers));
+        assert_eq!(json(&loaded.series), json(&expected.series));
+        assert_eq!(json(&read(&path).unwrap().unwrap().tiers), json(&expected.tiers));
+    }
+
+    #[test]
+    fn future_sample_keeps_history() {
+        let config = HistoryConfig::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let mut db = create(&path, &config);
+        let all = samples(4);
+        db.append(&RecordRef::Samples(&all), Utc::now()).unwrap();
+        // Bounds are kept to the microsecond
+        let second = |t: DateTime<Utc>| DateTime::from_timestamp(t.timestamp(), 0).unwrap();
+        let gap = Gap {
+            start: second(all[0].timestamp),
+            end: second(all[1].timestamp),
+            reason: GapReason::Stopped,
+        };
+        db.append(&RecordRef::Gap(&gap), Utc::now()).unwrap();
+        // A reading from a clock set years ahead
+        let mut bogus = samples(1);
+        bogus[0].timestamp += TimeDelta::days(3650);
+        db.append(&RecordRef::Samples(&bogus), Utc::now()).unwrap();
+        drop(db);
+
+        let loaded = reopen(&path, &config);
+        let cpu = loaded.series.id(&SeriesKey::new(metrics::CPU_PERCENT)).unwrap();
+        assert_eq!(loaded.raw[&cpu].iter().count(), all.len() + 1);
+        assert_eq!(loaded.tiers[0].columns[&cpu].iter().count(), all.len() + 1);
+        assert_eq!(json(&loaded.gaps), json(&[gap]));
+    }
+
+    #[test]
+    fn text_bounds_are_migrated() {
+        let config = HistoryConfig::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let db = create(&path, &config);
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        let (old, recent) = (now - TimeDelta::days(3650), now - TimeDelta::hours(1));
+        // Laid out as version 1 did
+        db.conn
+            .execute_batch(
+                "DROP TABLE gaps;
+                 CREATE TABLE gaps (start TEXT NOT NULL, end TEXT NOT NULL, reason TEXT NOT NULL);",
+            )
+            .unwrap();
+        for (start, end) in [(old, old), (recent, now)] {
+            db.conn
+                .execute(
+                    "INSERT INTO gaps (start, end, reason) VALUES (?1, ?2, 'stopped')",
+                    params![time_text(start), time_text(end)],
+                )
+                .unwrap();
+        }
+        drop(db);
+        // Read as it is, without migrating
+        assert_eq!(read(&path).unwrap().unwrap().gaps.len(), 2);
+
+        let (mut db, loaded) = SqliteBackend::open(&path, &config.tiers, config.raw_retention_secs).unwrap();
+        assert!(!text_bounds(&db.conn, "gaps").unwrap());
+        assert_eq!(loaded.unwrap().gaps.len(), 2);
+        db.append(&RecordRef::Samples(&samples(1)), Utc::now()).unwrap();
+        drop(db);
+
+        let recent = Gap {
+            start: recent,
+            end: now,
+            reason: GapReason::Stopped,
+        };
+        assert_eq!(json(&reopen(&path, &config).gaps), json(&[recent]));
+    }
+}
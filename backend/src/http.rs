@@ -0,0 +1,114 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024; // stop reading HTTP bodies after 1 MiB
+
+/// Send the request and read the response until the server closes the connection.
+pub async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    request: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    stream.write_all(request).await?;
+    stream.flush().await?;
+
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(n) => {
+                buf.extend_from_slice(&chunk[..n]);
+                if buf.len() >= MAX_RESPONSE_BYTES {
+                    break;
+                }
+            }
+            // Plenty of servers close TLS connections without close_notify
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !buf.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(buf)
+}
+
+/// Split a raw HTTP/1.x response into its status code and (de-chunked) body.
+pub fn parse_response(raw: &[u8]) -> Result<(u16, Vec<u8>), String> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("incomplete HTTP response")?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let body = &raw[header_end + 4..];
+
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| format!("invalid status line: {}", status_line))?;
+
+    let chunked = lines.any(|l| {
+        l.split_once(':').is_some_and(|(k, v)| {
+            k.trim().eq_ignore_ascii_case("transfer-encoding")
+                && v.to_ascii_lowercase().contains("chunked")
+        })
+    });
+
+    let body = if chunked {
+        decode_chunked(body)
+    } else {
+        body.to_vec()
+    };
+    Ok((status, body))
+}
+
+fn decode_chunked(mut data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(line_end) = data.windows(2).position(|w| w == b"\r\n") {
+        let size_str = String::from_utf8_lossy(&data[..line_end]);
+        let size_hex = size_str.split(';').next().unwrap_or_default().trim();
+        let Ok(size) = usize::from_str_radix(size_hex, 16) else {
+            break;
+        };
+        data = &data[line_end + 2..];
+        if size == 0 {
+            break;
+        }
+        let take = size.min(data.len());
+        out.extend_from_slice(&data[..take]);
+        data = &data[take..];
+        data = data.strip_prefix(b"\r\n").unwrap_or(data);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_plain() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        assert_eq!(parse_response(raw).unwrap(), (200, b"hello".to_vec()));
+    }
+
+    #[test]
+    fn response_chunked() {
+        let raw = b"HTTP/1.1 503 Service Unavailable\r\n\
+            transfer-encoding: Chunked\r\n\r\n\
+            4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+        assert_eq!(parse_response(raw).unwrap(), (503, b"Wikipedia".to_vec()));
+    }
+
+    #[test]
+    fn response_truncated_chunk() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nabc";
+        assert_eq!(parse_response(raw).unwrap().1, b"abc");
+    }
+
+    #[test]
+    fn response_invalid() {
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(parse_response(b"garbage\r\n\r\n").is_err());
+        assert!(parse_response(b"HTTP/1.1 abc OK\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,623 @@
+use crate::auth::{self, AuthConfig};
+use crate::history::{self, HistoryStore, ImportSource};
+use crate::http;
+use crate::metrics::SeriesKey;
+use crate::models::ImportReport;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+const MAX_IMPORT_BYTES: u64 = 256 * 1024 * 1024; // larger uploads are refused
+const USAGE: &str = "\
+Usage: pi-dash import PATH [--url URL]
+
+Merge history into a running pi-dash. PATH is a history log directory
+(/data/history of another instance), a history.db, a single .seg file, an old
+history.json(.zst), or a CSV, NDJSON or Parquet file from `pi-dash export`.
+Existing data is kept where both have values.
+
+Options:
+  --url URL    Server to import into (default http://127.0.0.1:$PI_DASH_PORT)
+
+Logs in with PI_DASH_USER and PI_DASH_PASS, which must both be set.";
+
+/// Numbers upload files so concurrent imports do not share one.
+static UPLOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Upload spooled to the temporary directory, removed when dropped.
+struct Upload(PathBuf);
+
+impl Upload {
+    fn new() -> Self {
+        let n = UPLOADS.fetch_add(1, Ordering::Relaxed);
+        Upload(std::env::temp_dir().join(format!("pi-dash-import-{}-{}", std::process::id(), n)))
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[derive(Deserialize)]
+struct NdjsonRow {
+    timestamp: DateTime<Utc>,
+    metric: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    value: f64,
+}
+
+/// Labels from the `name=value,...` form exports use.
+fn parse_labels(s: &str) -> Result<BTreeMap<String, String>, String> {
+    s.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .ok_or_else(|| format!("Invalid labels '{}'", s))
+        })
+        .collect()
+}
+
+/// Split one CSV line, undoing the quoting exports apply.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+type Rows = Vec<(SeriesKey, DateTime<Utc>, f64)>;
+
+fn parse_csv(header: &str, lines: io::Lines<impl BufRead>) -> Result<Rows, String> {
+    let header = split_csv_line(header);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| format!("CSV has no '{}' column", name))
+    };
+    let (ts, metric, labels, value) = (
+        column("timestamp")?,
+        column("metric")?,
+        column("labels")?,
+        column("value")?,
+    );
+
+    let mut rows = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(&line);
+        let field = |index: usize| fields.get(index).map(String::as_str).unwrap_or("");
+        let row = (|| {
+            let t = history::parse_time(field(ts)).ok_or("invalid timestamp")?;
+            let v: f64 = field(value).parse().map_err(|_| "invalid value")?;
+            let labels = parse_labels(field(labels))?;
+            Ok::<_, String>((
+                SeriesKey {
+                    metric: field(metric).to_string(),
+                    labels,
+                },
+                t,
+                v,
+            ))
+        })();
+        rows.push(row.map_err(|e| format!("Line {}: {}", i + 2, e))?);
+    }
+    Ok(rows)
+}
+
+fn parse_ndjson(first: &str, lines: io::Lines<impl BufRead>) -> Result<Rows, String> {
+    let mut rows = Vec::new();
+    for (i, line) in std::iter::once(Ok(first.to_string())).chain(lines).enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: NdjsonRow =
+            serde_json::from_str(&line).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        rows.push((
+            SeriesKey {
+                metric: row.metric,
+                labels: row.labels,
+            },
+            row.timestamp,
+            row.value,
+        ));
+    }
+    Ok(rows)
+}
+
+fn parse_parquet(file: File) -> Result<Rows, String> {
+    let reader = SerializedFileReader::new(file).map_err(|e| e.to_string())?;
+    let mut rows = Vec::new();
+    for row in reader.get_row_iter(None).map_err(|e| e.to_string())? {
+        let row = row.map_err(|e| e.to_string())?;
+        let (mut t, mut metric, mut labels, mut value) = (None, None, None, None);
+        for (name, field) in row.get_column_iter() {
+            match (name.as_str(), field) {
+                ("timestamp", Field::TimestampMillis(ms)) => t = DateTime::from_timestamp_millis(*ms),
+                ("timestamp", Field::TimestampMicros(us)) => t = DateTime::from_timestamp_micros(*us),
+                ("metric", Field::Str(s)) => metric = Some(s.clone()),
+                ("labels", Field::Str(s)) => labels = Some(parse_labels(s)?),
+                ("value", Field::Double(v)) => value = Some(*v),
+                _ => {}
+            }
+        }
+        let (Some(t), Some(metric), Some(value)) = (t, metric, value) else {
+            return Err("Parquet rows need timestamp, metric and value columns".to_string());
+        };
+        let labels = labels.unwrap_or_default();
+        rows.push((SeriesKey { metric, labels }, t, value));
+    }
+    Ok(rows)
+}
+
+/// Work out what the file at `path` is and read it.
+fn parse(history: &HistoryStore, path: &Path) -> Result<(&'static str, ImportSource), String> {
+    let failed = |e: io::Error| format!("Failed to read upload: {}", e);
+    let mut file = File::open(path).map_err(failed)?;
+    let mut magic = Vec::new();
+    (&mut file).take(4).read_to_end(&mut magic).map_err(failed)?;
+    file.rewind().map_err(failed)?;
+    if magic == b"PAR1" {
+        return Ok(("parquet", ImportSource::Rows(parse_parquet(file)?)));
+    }
+    if let Some(inner) = history.decode_file(path).map_err(failed)? {
+        return Ok(("history", ImportSource::Store(Box::new(inner))));
+    }
+
+    let unrecognised = "Unrecognised file; expected a history log, an old history file, or a CSV, NDJSON or Parquet export";
+    let mut lines = BufReader::new(file).lines();
+    let first = match lines.next() {
+        Some(Ok(line)) => line,
+        _ => return Err(unrecognised.to_string()),
+    };
+    let first = first.trim_start_matches('\u{feff}');
+    let (format, rows) = if first.starts_with('{') {
+        ("ndjson", parse_ndjson(first, lines)?)
+    } else if first.starts_with("timestamp") || first.contains(",metric,") {
+        ("csv", parse_csv(first, lines)?)
+    } else {
+        return Err(unrecognised.to_string());
+    };
+    if let Some((key, _, _)) = rows.iter().find(|(key, _, v)| key.metric.is_empty() || !v.is_finite()) {
+        return Err(format!(
+            "Every row needs a metric name and a finite value (metric '{}')",
+            key.metric
+        ));
+    }
+    Ok((format, ImportSource::Rows(rows)))
+}
+
+/// Merge an uploaded history file or export into the store. See
+/// [`HistoryStore::import`] for how overlaps are resolved.
+pub async fn api_import(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+    mut payload: web::Payload,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    // Spooled to a file rather than held in memory while it arrives
+    let upload = Upload::new();
+    let mut file = match tokio::fs::File::create(&upload.0).await {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to create {}: {}", upload.0.display(), e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Import failed"}));
+        }
+    };
+    let mut received = 0;
+    while let Some(chunk) = payload.next().await {
+        let Ok(chunk) = chunk else {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "Failed to read upload"}));
+        };
+        received += chunk.len() as u64;
+        if received > MAX_IMPORT_BYTES {
+            return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "error": format!("Imports are limited to {} MiB", MAX_IMPORT_BYTES / (1024 * 1024)),
+            }));
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            eprintln!("Failed to write {}: {}", upload.0.display(), e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Import failed"}));
+        }
+    }
+    if let Err(e) = file.flush().await {
+        eprintln!("Failed to write {}: {}", upload.0.display(), e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Import failed"}));
+    }
+    drop(file);
+
+    let store = history.get_ref().clone();
+    let result = web::block(move || {
+        let (format, source) = parse(&store, &upload.0)?;
+        let mut report = store.import(source);
+        report.format = format.to_string();
+        Ok::<ImportReport, String>(report)
+    })
+    .await;
+    match result {
+        Ok(Ok(report)) => {
+            println!(
+                "Merged a {} import into history ({} series)",
+                report.format, report.series
+            );
+            HttpResponse::Ok().json(report)
+        }
+        Ok(Err(e)) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({"error": "Import failed"})),
+    }
+}
+
+/// `pi-dash import`: upload history to a running server, which does the
+/// merge since it owns the log. Returns the process exit code.
+pub async fn run_cli(args: &[String]) -> i32 {
+    match cli(args).await {
+        Ok(report) => {
+            println!("{}", report);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+async fn cli(args: &[String]) -> Result<String, String> {
+    let port = std::env::var("PI_DASH_PORT").unwrap_or_else(|_| "3300".to_string());
+    let mut url = format!("http://127.0.0.1:{}", port);
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(USAGE.to_string()),
+            "--url" => {
+                url = args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for --url\n\n{}", USAGE))?
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+    let Some(path) = path else {
+        return Err(USAGE.to_string());
+    };
+    let Some(addr) = url.strip_prefix("http://").map(|a| a.trim_end_matches('/')) else {
+        return Err(format!("Only http:// URLs are supported, not {}", url));
+    };
+
+    let path = Path::new(&path);
+    let data = if path.is_dir() || history::is_database(path) {
+        history::image(path).map_err(|e| format!("Failed to read history in {}: {}", path.display(), e))?
+    } else {
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+    };
+
+    let credential = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| format!("{} must be set to log in to {}", name, url))
+    };
+    let username = credential("PI_DASH_USER")?;
+    let password = credential("PI_DASH_PASS")?;
+    let login = serde_json::json!({"username": username, "password": password});
+    let (status, body) = request(addr, "/api/login", None, "application/json", login.to_string().as_bytes()).await?;
+    let token = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v["token"].as_str().map(String::from))
+        .filter(|_| status == 200)
+        .ok_or_else(|| format!("Login to {} failed ({})", url, status))?;
+
+    let (status, body) = request(
+        addr,
+        "/api/history/import",
+        Some(&token),
+        "application/octet-stream",
+        &data,
+    )
+    .await?;
+    let body = String::from_utf8_lossy(&body).into_owned();
+    if status != 200 {
+        return Err(format!("Import failed ({}): {}", status, body));
+    }
+    Ok(serde_json::from_str::<serde_json::Value>(&body)
+        .and_then(|v| serde_json::to_string_pretty(&v))
+        .unwrap_or(body))
+}
+
+/// POST `body` to `path` on `addr` and return the status and response body.
+async fn request(
+    addr: &str,
+    path: &str,
+    token: Option<&str>,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(u16, Vec<u8>), String> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+    let auth = token
+        .map(|t| format!("Authorization: Bearer {}\r\n", t))
+        .unwrap_or_default();
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        addr,
+        auth,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(body);
+    let raw = http::exchange(&mut stream, &request)
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", addr, e))?;
+    http::parse_response(&raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{HistoryConfig, Selection};
+    use crate::metrics::{self, Sample};
+    use crate::models::Aggregate;
+    use crate::wal::{self, Wal};
+    use chrono::TimeDelta;
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    fn store() -> HistoryStore {
+        let dir = tempfile::tempdir().unwrap();
+        HistoryStore::open_read_only(&HistoryConfig::default(), dir.path()).unwrap()
+    }
+
+    /// An hour ago, on a minute boundary.
+    fn hour_ago() -> DateTime<Utc> {
+        let t = Utc::now().timestamp() - 3600;
+        DateTime::from_timestamp(t - t % 60, 0).unwrap()
+    }
+
+    fn attic() -> SeriesKey {
+        SeriesKey::new("room_temp")
+            .with_label("floor", "2")
+            .with_label("room", "\"attic\"")
+    }
+
+    fn rows(history: &HistoryStore, path: &Path) -> (&'static str, Rows) {
+        match parse(history, path).unwrap() {
+            (format, ImportSource::Rows(rows)) => (format, rows),
+            (format, ImportSource::Store(_)) => panic!("{} read as a whole store", format),
+        }
+    }
+
+    fn imported(history: &HistoryStore, path: &Path) -> (&'static str, ImportReport) {
+        match parse(history, path).unwrap() {
+            (format, source @ ImportSource::Store(_)) => (format, history.import(source)),
+            (format, ImportSource::Rows(_)) => panic!("{} read as rows", format),
+        }
+    }
+
+    /// Averages of the `metric` buckets in the finest tier.
+    fn day_values(history: &HistoryStore, metric: &str) -> Vec<f64> {
+        let (_, _, series) = history
+            .series(metric, &BTreeMap::new(), &Selection::Range("day".to_string()), Aggregate::Avg)
+            .unwrap();
+        series.iter().flat_map(|s| &s.points).map(|p| p.value).collect()
+    }
+
+    fn day_counts(report: &ImportReport) -> (usize, usize) {
+        let day = report.tiers.iter().find(|t| t.name == "day").unwrap();
+        (day.merged, day.skipped)
+    }
+
+    #[test]
+    fn csv_rows() {
+        let t = hour_ago();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.csv");
+        let csv = format!(
+            "\u{feff}timestamp,metric,labels,value\n\
+             {},room_temp,\"floor=2,room=\"\"attic\"\"\",21.5\n\
+             \n\
+             {},cpu_percent,,3\n",
+            t.to_rfc3339(),
+            t.timestamp()
+        );
+        std::fs::write(&path, csv).unwrap();
+
+        let (format, rows) = rows(&store(), &path);
+        assert_eq!(format, "csv");
+        assert_eq!(rows, [(attic(), t, 21.5), (SeriesKey::new(metrics::CPU_PERCENT), t, 3.0)]);
+
+        std::fs::write(&path, "timestamp,metric,labels,value\nyesterday,cpu_percent,,3\n").unwrap();
+        assert_eq!(parse(&store(), &path).err().unwrap(), "Line 2: invalid timestamp");
+    }
+
+    #[test]
+    fn ndjson_rows() {
+        let t = hour_ago();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.ndjson");
+        let lines = [
+            serde_json::json!({
+                "timestamp": t, "metric": "room_temp",
+                "labels": {"floor": "2", "room": "\"attic\""}, "value": 21.5,
+            }),
+            serde_json::json!({"timestamp": t, "metric": metrics::CPU_PERCENT, "value": 3.0}),
+        ];
+        let ndjson: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+        std::fs::write(&path, ndjson).unwrap();
+
+        let (format, rows) = rows(&store(), &path);
+        assert_eq!(format, "ndjson");
+        assert_eq!(rows, [(attic(), t, 21.5), (SeriesKey::new(metrics::CPU_PERCENT), t, 3.0)]);
+    }
+
+    #[test]
+    fn parquet_rows() {
+        let t = hour_ago();
+        let schema = "
+            message history {
+                REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+                REQUIRED BINARY metric (STRING);
+                REQUIRED BINARY labels (STRING);
+                REQUIRED DOUBLE value;
+            }
+        ";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.parquet");
+        let schema = Arc::new(parse_message_type(schema).unwrap());
+        let file = File::create(&path).unwrap();
+        let mut writer = SerializedFileWriter::new(file, schema, Default::default()).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let times = [t.timestamp_millis(), (t + TimeDelta::minutes(1)).timestamp_millis()];
+        let metrics = [ByteArray::from("room_temp"), ByteArray::from(metrics::CPU_PERCENT)];
+        let labels = [ByteArray::from("floor=2,room=\"attic\""), ByteArray::from("")];
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column.typed::<Int64Type>().write_batch(&times, None, None).unwrap();
+        column.close().unwrap();
+        for values in [&metrics, &labels] {
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column.typed::<ByteArrayType>().write_batch(values, None, None).unwrap();
+            column.close().unwrap();
+        }
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column.typed::<DoubleType>().write_batch(&[21.5, 3.0], None, None).unwrap();
+        column.close().unwrap();
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let (format, rows) = rows(&store(), &path);
+        assert_eq!(format, "parquet");
+        assert_eq!(
+            rows,
+            [
+                (attic(), t, 21.5),
+                (SeriesKey::new(metrics::CPU_PERCENT), t + TimeDelta::minutes(1), 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn log_image() {
+        let t = hour_ago();
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("history");
+        let (mut wal, _) = Wal::open(&log).unwrap();
+        for (i, value) in [10.0, 20.0].into_iter().enumerate() {
+            let record = serde_json::json!({"Samples": [{
+                "timestamp": t + TimeDelta::minutes(i as i64),
+                "samples": [{"key": {"metric": metrics::CPU_PERCENT}, "value": value}],
+            }]});
+            wal.append(record.to_string().as_bytes()).unwrap();
+        }
+        drop(wal);
+        let path = dir.path().join("history.seg");
+        std::fs::write(&path, history::image(&log).unwrap()).unwrap();
+        assert!(wal::is_image(&path).unwrap());
+
+        let history = store();
+        let (format, report) = imported(&history, &path);
+        assert_eq!(format, "history");
+        assert_eq!(report.series, 1);
+        assert_eq!(day_counts(&report), (2, 0));
+        assert_eq!(day_values(&history, metrics::CPU_PERCENT), [10.0, 20.0]);
+    }
+
+    #[test]
+    fn legacy_history() {
+        let t = hour_ago();
+        let bucket = |minutes: i64, cpu: f64| {
+            serde_json::json!({
+                "timestamp": t + TimeDelta::minutes(minutes),
+                "cpu_percent_sum": cpu * 2.0, "cpu_freq_sum": 0, "cpu_temp_sum": 0.0,
+                "cpu_temp_count": 0, "mem_percent_sum": 0.0, "disk_percent_sum": 0.0,
+                "network_rx_sum": 0, "network_tx_sum": 0, "disk_read_sum": 0,
+                "disk_write_sum": 0, "count": 2,
+                "temperatures": [{"label": "cpu", "temperature": 45.0}],
+            })
+        };
+        let legacy = serde_json::json!({
+            "raw": [],
+            "day_buckets": [bucket(0, 5.0), bucket(1, 7.0)],
+            "week_buckets": [],
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let history = store();
+        let (format, report) = imported(&history, &path);
+        assert_eq!(format, "history");
+        // Every summed field but the unsampled CPU temperature, plus the sensor
+        assert_eq!(report.series, 9);
+        assert_eq!(day_counts(&report), (2 * 9, 0));
+        assert_eq!(day_values(&history, metrics::CPU_PERCENT), [5.0, 7.0]);
+        assert_eq!(day_values(&history, metrics::TEMPERATURE), [45.0, 45.0]);
+    }
+
+    #[test]
+    fn existing_data_wins() {
+        let t = hour_ago();
+        let history = store();
+        history.record(t, vec![Sample::new(SeriesKey::new(metrics::CPU_PERCENT), 10.0)]);
+
+        let cpu = SeriesKey::new(metrics::CPU_PERCENT);
+        let rows = vec![
+            (cpu.clone(), t, 90.0),
+            (cpu.clone(), t + TimeDelta::minutes(1), 50.0),
+        ];
+        let report = history.import(ImportSource::Rows(rows.clone()));
+        assert_eq!(day_counts(&report), (1, 1));
+        assert_eq!(day_values(&history, metrics::CPU_PERCENT), [10.0, 50.0]);
+
+        // Importing the same rows again fills nothing
+        let report = history.import(ImportSource::Rows(rows));
+        assert_eq!(day_counts(&report), (0, 2));
+        assert_eq!(report.series, 0);
+        assert_eq!(day_values(&history, metrics::CPU_PERCENT), [10.0, 50.0]);
+    }
+}
@@ -1,25 +1,57 @@
+mod annotations;
 mod auth;
+mod checks;
+mod clock;
 mod collector;
+mod columns;
+mod config;
+mod downsample;
+mod encoding;
+mod export;
+mod forecast;
+mod heartbeat;
 mod history;
+mod http;
+mod import;
+mod metrics;
 mod models;
+mod mounts;
+mod paths;
+mod sketch;
+mod storage;
+mod uptime;
+mod wal;
+mod writes;
 mod ws;
 
 use actix_files::Files;
+use actix_web::http::header;
 use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use chrono::{TimeDelta, Utc};
 use log::info;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::broadcast;
 
+use annotations::AnnotationStore;
 use auth::AuthConfig;
+use checks::CheckStore;
 use collector::Collector;
+use config::Config;
+use forecast::ForecastStore;
+use heartbeat::HeartbeatStore;
 use history::HistoryStore;
 use models::HistoryQuery;
+use paths::PathMonitorStore;
+use uptime::UptimeStore;
 use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
 
 async fn api_history(
     req: HttpRequest,
     auth: web::Data<AuthConfig>,
     history: web::Data<HistoryStore>,
+    annotations: web::Data<AnnotationStore>,
     query: web::Query<HistoryQuery>,
 ) -> HttpResponse {
     // Check auth
@@ -31,17 +63,175 @@ async fn api_history(
         return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
     }
 
-    let range = query.range.as_deref().unwrap_or("day");
-    let points = match range {
-        "raw" => history.get_raw(),
-        "week" => history.get_week(),
-        _ => history.get_day(),
+    let agg = query.agg.unwrap_or_default();
+
+    let fields: Option<Vec<&str>> = query
+        .fields
+        .as_deref()
+        .map(|f| f.split(',').map(str::trim).filter(|f| !f.is_empty()).collect());
+    if let Some(unknown) = fields
+        .iter()
+        .flatten()
+        .find(|f| !models::HistoryPoint::FIELDS.contains(f))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown field '{}'", unknown),
+            "fields": models::HistoryPoint::FIELDS,
+        }));
+    }
+
+    if query.max_points.is_some_and(|m| m < 3) {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "'max_points' must be at least 3"}));
+    }
+
+    let since = match query.since.as_deref().map(history::parse_time) {
+        None => None,
+        Some(Some(t)) => Some(t),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'since' time"}));
+        }
+    };
+
+    let windowed = query.from.is_some() || query.to.is_some() || query.step.is_some();
+    let (range, mut points, from, to, step_secs) = if windowed {
+        let to = match query.to.as_deref().map(history::parse_time) {
+            None => Utc::now(),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'to' time"}));
+            }
+        };
+        let from = match query.from.as_deref().map(history::parse_time) {
+            None => to - TimeDelta::days(1),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'from' time"}));
+            }
+        };
+        if from >= to {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "'from' must be before 'to'"}));
+        }
+        if query.step.is_some_and(|s| s <= 0) {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "'step' must be positive"}));
+        }
+
+        let window = history.window(from, to, query.step, agg, since);
+        (window.range, window.points, Some(from), Some(to), Some(window.step_secs))
+    } else {
+        let range = query
+            .range
+            .clone()
+            .unwrap_or_else(|| history.default_range());
+        let Some(points) = history.get(&range, agg, since) else {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Unknown range",
+                "ranges": history.ranges(),
+            }));
+        };
+        (range, points, None, None, None)
     };
 
-    HttpResponse::Ok().json(models::HistoryResponse {
-        range: range.to_string(),
+    let (span_from, span_to) = match (from, to) {
+        (Some(from), Some(to)) => (from.max(since.unwrap_or(from)), to),
+        _ => {
+            let now = Utc::now();
+            let retention = history.retention_secs(&range).unwrap_or(0);
+            let start = now - TimeDelta::seconds(retention);
+            (start.max(since.unwrap_or(start)), now)
+        }
+    };
+    let cursor = points.last().map(|p| p.timestamp).or(since);
+    let gaps = history.gaps(span_from, span_to);
+    let clock_flags = history.clock_flags(span_from, span_to);
+    let annotations = annotations.list(Some(span_from), Some(span_to), None);
+
+    let sensors: Option<Vec<&str>> = query
+        .sensors
+        .as_deref()
+        .map(|s| s.split(',').map(str::trim).filter(|s| !s.is_empty()).collect());
+
+    if let Some(sensors) = &sensors {
+        for p in &mut points {
+            p.temperatures.retain(|t| sensors.contains(&t.label.as_str()));
+        }
+    }
+    if let Some(max) = query.max_points
+        && points.len() > max
+    {
+        points = downsample::history_points(points, fields.as_deref(), max);
+    }
+
+    let points = points
+        .into_iter()
+        .map(|p| {
+            let mut value = serde_json::to_value(&p).unwrap_or_default();
+            if let (Some(fields), Some(map)) = (&fields, value.as_object_mut()) {
+                map.retain(|k, _| k == "timestamp" || fields.contains(&k.as_str()));
+            }
+            value
+        })
+        .collect();
+
+    let encoding = encoding::Encoding::from_accept(&req);
+    let body = match encoding.encode(&models::HistoryResponse {
+        range,
+        agg,
+        from,
+        to,
+        step_secs,
         points,
-    })
+        gaps,
+        clock_flags,
+        annotations,
+        cursor,
+    }) {
+        Ok(body) => body,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
+        }
+    };
+
+    // Charts are refetched periodically; let unchanged ranges come back as 304
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(&body)[..16]));
+    let unchanged = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        });
+    if unchanged {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::VARY, "Accept"))
+            .finish();
+    }
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, "private, no-cache"))
+        .insert_header((header::VARY, "Accept"))
+        .content_type(encoding.content_type())
+        .body(body)
+}
+
+async fn api_availability(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+    query: web::Query<models::AvailabilityQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 730);
+    encoding::respond(&req, &history.availability(days))
 }
 
 async fn api_stats(
@@ -58,24 +248,64 @@ async fn api_stats(
     }
 
     let stats = collector.lock().collect();
-    HttpResponse::Ok().json(stats)
+    encoding::respond(&req, &stats)
+}
+
+async fn api_storage_arrays(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    collector: web::Data<Arc<Mutex<Collector>>>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let stats = collector.lock().collect();
+    encoding::respond(&req, &stats.storage_arrays)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|a| a == "export") {
+        std::process::exit(export::run_cli(&args[2..]));
+    }
+    if args.get(1).is_some_and(|a| a == "import") {
+        std::process::exit(import::run_cli(&args[2..]).await);
+    }
+
     let port: u16 = std::env::var("PI_DASH_PORT")
         .unwrap_or_else(|_| "3300".to_string())
         .parse()
         .expect("PI_DASH_PORT must be a valid port number");
 
+    let started = Instant::now();
     let auth_config = AuthConfig::from_env();
     info!("Pi Dash starting on port {}", port);
     info!("Username: {}", auth_config.username);
 
-    let history = HistoryStore::new();
-    let (ws_tx, _) = broadcast::channel::<String>(128);
+    let config = Config::load();
+    writes::load_from_disk();
+    let uptime = UptimeStore::new();
+    let history = HistoryStore::new(&config.history, uptime.rebooted());
+    history.spawn();
+    let checks = CheckStore::new(config.checks, history.clone());
+    checks.spawn();
+    let heartbeats = HeartbeatStore::new(config.heartbeats);
+    heartbeats.spawn();
+    let path_monitors = PathMonitorStore::new(config.paths, history.clone());
+    path_monitors.spawn();
+    let annotations = AnnotationStore::new();
+    let forecasts = ForecastStore::new(config.forecast, history.clone(), annotations.clone());
+    forecasts.spawn();
+    uptime.spawn(history.clone(), annotations.clone(), started);
+    let (ws_tx, _) = broadcast::channel::<Arc<ws::Frame>>(128);
     let collector = Arc::new(Mutex::new(Collector::new()));
 
     // Background collection task
@@ -84,24 +314,39 @@ async fn main() -> std::io::Result<()> {
     let bg_tx = ws_tx.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
+        // After a stall, carry on rather than firing the missed ticks back to back
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
         let mut ticks = 0;
         let mut delay_secs = 1;
+        // History is sampled at a fixed rate; only the broadcast backs off
+        let history_every = bg_history.sample_interval_secs();
+        let mut history_ticks = history_every;
+        let dictionary = Arc::new(Mutex::new(encoding::Dictionary::default()));
 
         loop {
             interval.tick().await;
             ticks += 1;
+            history_ticks += 1;
 
             let active = bg_tx.receiver_count() > 0;
             if active {
                 delay_secs = 1;
             }
 
-            if ticks >= delay_secs {
-                let stats = bg_collector.lock().collect();
+            let broadcast = ticks >= delay_secs;
+            let sample = history_ticks >= history_every;
+            if !broadcast && !sample {
+                continue;
+            }
+
+            let stats = bg_collector.lock().collect();
+            if sample {
                 bg_history.push(&stats);
-                if let Ok(json) = serde_json::to_string(&stats) {
-                    let _ = bg_tx.send(json);
-                }
+                history_ticks = 0;
+            }
+
+            if broadcast {
+                let _ = bg_tx.send(Arc::new(ws::Frame::new(stats, dictionary.clone())));
 
                 ticks = 0;
 
@@ -123,13 +368,22 @@ async fn main() -> std::io::Result<()> {
     let history_data = web::Data::new(history.clone());
     let ws_tx_data = web::Data::new(ws_tx);
     let collector_data = web::Data::new(collector);
+    let checks_data = web::Data::new(checks.clone());
+    let heartbeats_data = web::Data::new(heartbeats);
+    let paths_data = web::Data::new(path_monitors.clone());
+    let annotations_data = web::Data::new(annotations);
+    let uptime_data = web::Data::new(uptime.clone());
+    let forecasts_data = web::Data::new(forecasts);
 
     // Background save task (every 10 minutes)
-    let save_history = history.clone();
+    let save_checks = checks.clone();
+    let save_paths = path_monitors.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(tokio::time::Duration::from_secs(600)).await;
-            save_history.save_to_disk();
+            save_checks.save_to_disk();
+            save_paths.save_to_disk();
+            writes::save_to_disk();
         }
     });
 
@@ -139,12 +393,41 @@ async fn main() -> std::io::Result<()> {
             .app_data(history_data.clone())
             .app_data(ws_tx_data.clone())
             .app_data(collector_data.clone())
+            .app_data(checks_data.clone())
+            .app_data(heartbeats_data.clone())
+            .app_data(paths_data.clone())
+            .app_data(annotations_data.clone())
+            .app_data(uptime_data.clone())
+            .app_data(forecasts_data.clone())
             .route("/api/login", web::post().to(auth::login))
             .route("/api/refresh", web::post().to(auth::refresh))
             .route("/api/logout", web::post().to(auth::logout))
             .route("/api/auth", web::get().to(auth::check_auth))
             .route("/api/history", web::get().to(api_history))
+            .route("/api/history/availability", web::get().to(api_availability))
+            .route("/api/uptime", web::get().to(uptime::api_uptime))
+            .route("/api/forecast", web::get().to(forecast::api_forecast))
+            .route("/api/history/export", web::get().to(export::api_export))
+            .route("/api/history/import", web::post().to(import::api_import))
             .route("/api/stats", web::get().to(api_stats))
+            .route("/api/storage/arrays", web::get().to(api_storage_arrays))
+            .route("/api/checks", web::get().to(checks::api_checks))
+            .route("/api/checks/{name}", web::get().to(checks::api_check))
+            .route("/api/writes", web::get().to(writes::api_writes))
+            .route("/api/metrics", web::get().to(metrics::api_metrics))
+            .route("/api/metrics", web::post().to(metrics::api_push_metrics))
+            .route("/api/series", web::get().to(metrics::api_series))
+            .route("/api/paths", web::get().to(paths::api_paths))
+            .route("/api/paths/{name}", web::get().to(paths::api_path))
+            .route("/api/heartbeats", web::get().to(heartbeat::api_heartbeats))
+            .route("/api/heartbeats/{slug}", web::get().to(heartbeat::api_heartbeat))
+            .route("/api/heartbeat/{slug}", web::get().to(heartbeat::ping))
+            .route("/api/heartbeat/{slug}", web::post().to(heartbeat::ping))
+            .route("/api/heartbeat/{slug}/{kind}", web::get().to(heartbeat::ping_kind))
+            .route("/api/heartbeat/{slug}/{kind}", web::post().to(heartbeat::ping_kind))
+            .route("/api/annotations", web::get().to(annotations::api_annotations))
+            .route("/api/annotations", web::post().to(annotations::api_create_annotation))
+            .route("/api/annotations/{id}", web::delete().to(annotations::api_delete_annotation))
             .route("/ws", web::get().to(ws::ws_handler))
             // Serve static files (React build) - must be last
             .service(
@@ -159,10 +442,30 @@ async fn main() -> std::io::Result<()> {
             )
     })
     .bind(format!("0.0.0.0:{}", port))?
+    .disable_signals()
     .run();
 
+    // Mark the stop as clean as soon as it is asked for: draining connections
+    // can outlast the init system's patience, and a kill then is no crash
+    let handle = server.handle();
+    let stopping = uptime.clone();
+    tokio::spawn(async move {
+        let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+        stopping.shutdown();
+        handle.stop(true).await;
+    });
+
     let res = server.await;
     println!("Shutting down, saving history data to disk...");
-    history.save_to_disk();
+    history.flush();
+    checks.save_to_disk();
+    path_monitors.save_to_disk();
+    writes::save_to_disk();
+    uptime.shutdown();
     res
 }
@@ -0,0 +1,489 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::history::{self, HistoryStore};
+use crate::models::{
+    Aggregate, HistoryPoint, MetricDesc, MetricKind, MetricPush, SeriesQuery, SeriesResponse,
+    SystemStats,
+};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{TimeDelta, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+pub const CPU_PERCENT: &str = "cpu_percent";
+pub const CPU_FREQ: &str = "cpu_freq";
+pub const CPU_TEMP: &str = "cpu_temp";
+pub const MEM_PERCENT: &str = "mem_percent";
+pub const SWAP_PERCENT: &str = "swap_percent";
+pub const DISK_PERCENT: &str = "disk_percent";
+pub const NETWORK_RX: &str = "network_rx_bytes_sec";
+pub const NETWORK_TX: &str = "network_tx_bytes_sec";
+pub const DISK_READ: &str = "disk_read_bytes_sec";
+pub const DISK_WRITE: &str = "disk_write_bytes_sec";
+pub const TEMPERATURE: &str = "temperature";
+pub const SENSOR_LABEL: &str = "sensor";
+pub const COVERAGE: &str = "sample_coverage_secs";
+
+const METRICS_FILE: &str = "metrics.json";
+const MAX_METRICS: usize = 1000; // registered names, built-in ones included; pushes past this are refused
+const MAX_SERIES_PER_METRIC: usize = 200; // label sets a pushed metric may have
+
+/// Identifies one series: a metric name plus its label values.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SeriesKey {
+    pub metric: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl SeriesKey {
+    pub fn new(metric: &str) -> Self {
+        SeriesKey {
+            metric: metric.to_string(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_label(mut self, name: &str, value: &str) -> Self {
+        self.labels.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels.get(name).map(String::as_str)
+    }
+}
+
+/// One observation of a series.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub key: SeriesKey,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn new(key: SeriesKey, value: f64) -> Self {
+        Sample { key, value }
+    }
+}
+
+/// Known metrics. Any collector or client may register new ones; samples for
+/// unregistered metrics are dropped by the history store.
+#[derive(Clone, Default)]
+pub struct MetricRegistry {
+    metrics: Arc<RwLock<BTreeMap<String, MetricDesc>>>,
+}
+
+impl MetricRegistry {
+    /// Registry holding the built-in metrics plus those pushed by clients in
+    /// earlier runs.
+    pub fn with_builtins() -> Self {
+        let registry = MetricRegistry::default();
+        for desc in builtin() {
+            let _ = registry.register(desc);
+        }
+        registry.load_from_disk();
+        registry
+    }
+
+    fn load_from_disk(&self) {
+        let path = config::data_path(METRICS_FILE);
+        let Ok(content) = std::fs::read(&path) else {
+            return;
+        };
+        match serde_json::from_slice::<Vec<MetricDesc>>(&content) {
+            Ok(saved) => {
+                for desc in saved {
+                    if let Err(e) = self.register(desc) {
+                        eprintln!("Ignoring saved metric: {}", e);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Failed to parse {}: {}", path.display(), e),
+        }
+    }
+
+    /// Save every metric that is not built in. Collectors register theirs on
+    /// each start, so this matters for pushed metrics only.
+    pub fn save_to_disk(&self) {
+        let builtin: Vec<String> = builtin().into_iter().map(|d| d.name).collect();
+        let custom: Vec<MetricDesc> = self
+            .list()
+            .into_iter()
+            .filter(|d| !builtin.contains(&d.name))
+            .collect();
+        let path = config::data_path(METRICS_FILE);
+        let json = match serde_json::to_vec(&custom) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Failed to serialize metrics: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = writes::replace(&path, &json) {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    /// Add a metric. Registering the same name again is fine as long as the
+    /// kind and unit match.
+    pub fn register(&self, desc: MetricDesc) -> Result<(), String> {
+        let mut metrics = self.metrics.write();
+        if let Some(existing) = metrics.get(&desc.name) {
+            return check_same(existing, &desc);
+        }
+        metrics.insert(desc.name.clone(), desc);
+        Ok(())
+    }
+
+    /// Register the metrics of a push: all of them, or none when one
+    /// conflicts with a registered metric or another in the batch, or when
+    /// they would take the registry past [`MAX_METRICS`]. Entries that do not
+    /// declare a unit or kind (`declared` false) only need the name to exist.
+    /// Returns how many metrics were added.
+    fn register_pushed(&self, pushed: Vec<(MetricDesc, bool)>) -> Result<usize, RegisterError> {
+        let mut metrics = self.metrics.write();
+        let mut added: BTreeMap<String, MetricDesc> = BTreeMap::new();
+        for (desc, declared) in pushed {
+            match metrics.get(&desc.name).or_else(|| added.get(&desc.name)) {
+                Some(existing) if declared => {
+                    check_same(existing, &desc).map_err(RegisterError::Conflict)?
+                }
+                Some(_) => {}
+                None => {
+                    added.insert(desc.name.clone(), desc);
+                }
+            }
+        }
+        if metrics.len() + added.len() > MAX_METRICS {
+            return Err(RegisterError::Limit(format!(
+                "At most {} metrics can be registered",
+                MAX_METRICS
+            )));
+        }
+        let count = added.len();
+        metrics.extend(added);
+        Ok(count)
+    }
+
+    pub fn get(&self, name: &str) -> Option<MetricDesc> {
+        self.metrics.read().get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<MetricDesc> {
+        self.metrics.read().values().cloned().collect()
+    }
+}
+
+/// Why a push could not register its metrics.
+enum RegisterError {
+    Conflict(String),
+    Limit(String),
+}
+
+fn check_same(existing: &MetricDesc, desc: &MetricDesc) -> Result<(), String> {
+    if existing.kind != desc.kind || existing.unit != desc.unit {
+        return Err(format!(
+            "Metric '{}' is already registered as a {:?} in '{}'",
+            desc.name, existing.kind, existing.unit
+        ));
+    }
+    Ok(())
+}
+
+pub fn desc(name: &str, unit: &str, kind: MetricKind, labels: &[&str], help: &str) -> MetricDesc {
+    MetricDesc {
+        name: name.to_string(),
+        unit: unit.to_string(),
+        kind,
+        labels: labels.iter().map(|l| l.to_string()).collect(),
+        help: help.to_string(),
+    }
+}
+
+fn builtin() -> Vec<MetricDesc> {
+    use MetricKind::Gauge;
+    vec![
+        desc(CPU_PERCENT, "percent", Gauge, &[], "CPU usage"),
+        desc(CPU_FREQ, "MHz", Gauge, &[], "CPU frequency"),
+        desc(CPU_TEMP, "celsius", Gauge, &[], "CPU temperature"),
+        desc(MEM_PERCENT, "percent", Gauge, &[], "Memory usage"),
+        desc(SWAP_PERCENT, "percent", Gauge, &[], "Swap usage"),
+        desc(DISK_PERCENT, "percent", Gauge, &[], "Disk usage"),
+        desc(NETWORK_RX, "bytes/s", Gauge, &[], "Network receive rate"),
+        desc(NETWORK_TX, "bytes/s", Gauge, &[], "Network transmit rate"),
+        desc(DISK_READ, "bytes/s", Gauge, &[], "Disk read rate"),
+        desc(DISK_WRITE, "bytes/s", Gauge, &[], "Disk write rate"),
+        desc(TEMPERATURE, "celsius", Gauge, &[SENSOR_LABEL], "Temperature per sensor group"),
+        desc(COVERAGE, "seconds", Gauge, &[], "Seconds covered by each system sample"),
+        desc("mount_usage_percent", "percent", Gauge, &["mount"], "Network mount usage"),
+        desc("mount_response_ms", "ms", Gauge, &["mount"], "Network mount statvfs latency"),
+    ]
+}
+
+/// Samples for the built-in metrics.
+pub fn samples_from_stats(stats: &SystemStats) -> Vec<Sample> {
+    let mut samples = samples_from_point(&HistoryPoint::from(stats));
+    if let Some(swap) = stats.memory.swap_percent {
+        samples.push(Sample::new(SeriesKey::new(SWAP_PERCENT), swap as f64));
+    }
+    for mount in &stats.network_mounts {
+        let key = |metric: &str| SeriesKey::new(metric).with_label("mount", &mount.mount_point);
+        if let Some(usage) = mount.usage_percent {
+            samples.push(Sample::new(key("mount_usage_percent"), usage as f64));
+        }
+        if let Some(ms) = mount.response_ms {
+            samples.push(Sample::new(key("mount_response_ms"), ms));
+        }
+    }
+    samples
+}
+
+/// Split a fixed-field history point into series samples.
+pub fn samples_from_point(point: &HistoryPoint) -> Vec<Sample> {
+    let gauge = |metric: &str, value: f64| Sample::new(SeriesKey::new(metric), value);
+    let mut samples = vec![
+        gauge(CPU_PERCENT, point.cpu_percent as f64),
+        gauge(CPU_FREQ, point.cpu_freq as f64),
+        gauge(MEM_PERCENT, point.mem_percent as f64),
+        gauge(DISK_PERCENT, point.disk_percent as f64),
+        gauge(NETWORK_RX, point.network_rx_bytes_sec as f64),
+        gauge(NETWORK_TX, point.network_tx_bytes_sec as f64),
+        gauge(DISK_READ, point.disk_read_bytes_sec as f64),
+        gauge(DISK_WRITE, point.disk_write_bytes_sec as f64),
+    ];
+    if let Some(temp) = point.cpu_temp {
+        samples.push(gauge(CPU_TEMP, temp as f64));
+    }
+    for group in &point.temperatures {
+        samples.push(Sample::new(
+            SeriesKey::new(TEMPERATURE).with_label(SENSOR_LABEL, &group.label),
+            group.temperature as f64,
+        ));
+    }
+    samples
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+pub async fn api_metrics(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    encoding::respond(&req, &history.registry().list())
+}
+
+/// Record custom samples. Unknown metrics are registered as gauges unless the
+/// sample says otherwise. The batch is checked as a whole first, so it is
+/// either recorded in full or not at all.
+pub async fn api_push_metrics(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+    body: web::Json<Vec<MetricPush>>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    if body.iter().any(|p| p.name.is_empty() || !p.value.is_finite()) {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Each sample needs a name and a finite value"}));
+    }
+
+    let mut pushed = Vec::with_capacity(body.len());
+    let mut samples = Vec::with_capacity(body.len());
+    for push in body.into_inner() {
+        let labels: Vec<&str> = push.labels.keys().map(String::as_str).collect();
+        let desc = desc(
+            &push.name,
+            push.unit.as_deref().unwrap_or(""),
+            push.kind.unwrap_or(MetricKind::Gauge),
+            &labels,
+            "",
+        );
+        pushed.push((desc, push.unit.is_some() || push.kind.is_some()));
+        samples.push(Sample::new(
+            SeriesKey {
+                metric: push.name,
+                labels: push.labels,
+            },
+            push.value,
+        ));
+    }
+
+    let keys: HashSet<&SeriesKey> = samples.iter().map(|s| &s.key).collect();
+    for (metric, count) in history.series_after(&keys) {
+        if count > MAX_SERIES_PER_METRIC {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!(
+                    "Metric '{}' would have {} label sets; at most {} are kept",
+                    metric, count, MAX_SERIES_PER_METRIC
+                )
+            }));
+        }
+    }
+
+    let registry = history.registry();
+    match registry.register_pushed(pushed) {
+        Ok(0) => {}
+        Ok(_) => registry.save_to_disk(),
+        Err(RegisterError::Conflict(e)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({"error": e}));
+        }
+        Err(RegisterError::Limit(e)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": e}));
+        }
+    }
+    let count = samples.len();
+    history.record(Utc::now(), samples);
+    HttpResponse::Ok().json(serde_json::json!({"recorded": count}))
+}
+
+/// History of every series of one metric, optionally filtered by labels.
+pub async fn api_series(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+    query: web::Query<SeriesQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let Some(metric) = history.registry().get(&query.metric) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": "Unknown metric"}));
+    };
+
+    let mut labels = BTreeMap::new();
+    for pair in query.labels.as_deref().unwrap_or("").split(',') {
+        if pair.trim().is_empty() {
+            continue;
+        }
+        let Some((k, v)) = pair.split_once('=') else {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "Labels must be given as name=value"}));
+        };
+        labels.insert(k.trim().to_string(), v.trim().to_string());
+    }
+
+    let agg = query.agg.unwrap_or(Aggregate::Avg);
+    let windowed = query.from.is_some() || query.to.is_some() || query.step.is_some();
+    let selection = if windowed {
+        let to = match query.to.as_deref().map(history::parse_time) {
+            None => Utc::now(),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'to' time"}));
+            }
+        };
+        let from = match query.from.as_deref().map(history::parse_time) {
+            None => to - TimeDelta::days(1),
+            Some(Some(t)) => t,
+            Some(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid 'from' time"}));
+            }
+        };
+        if from >= to || query.step.is_some_and(|s| s <= 0) {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "Invalid window"}));
+        }
+        history::Selection::Window {
+            from,
+            to,
+            step_secs: query.step,
+        }
+    } else {
+        history::Selection::Range(
+            query
+                .range
+                .clone()
+                .unwrap_or_else(|| history.default_range()),
+        )
+    };
+
+    let Some((range, step_secs, series)) = history.series(&metric.name, &labels, &selection, agg)
+    else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unknown range",
+            "ranges": history.ranges(),
+        }));
+    };
+
+    encoding::respond(&req, &SeriesResponse {
+        metric,
+        range,
+        agg,
+        step_secs,
+        series,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gauge(name: &str, unit: &str) -> MetricDesc {
+        desc(name, unit, MetricKind::Gauge, &[], "")
+    }
+
+    #[test]
+    fn conflicting_push_registers_nothing() {
+        let registry = MetricRegistry::default();
+        registry.register(gauge("fan", "rpm")).unwrap();
+
+        let pushed = vec![
+            (gauge("pump", "rpm"), true),
+            (gauge("valve", ""), false),
+            (gauge("fan", "%"), true),
+        ];
+        assert!(matches!(registry.register_pushed(pushed), Err(RegisterError::Conflict(_))));
+        assert!(registry.get("pump").is_none());
+        assert!(registry.get("valve").is_none());
+
+        // The same name twice in one batch must agree too.
+        let pushed = vec![(gauge("pump", "rpm"), true), (gauge("pump", "%"), true)];
+        assert!(matches!(registry.register_pushed(pushed), Err(RegisterError::Conflict(_))));
+        assert!(registry.get("pump").is_none());
+
+        // Undeclared entries only need the name to exist.
+        let pushed = vec![(gauge("fan", ""), false), (gauge("pump", "rpm"), true)];
+        assert_eq!(registry.register_pushed(pushed).ok(), Some(1));
+        assert_eq!(registry.get("pump").unwrap().unit, "rpm");
+    }
+
+    #[test]
+    fn push_past_the_metric_cap_is_refused() {
+        let registry = MetricRegistry::default();
+        for i in 0..MAX_METRICS - 1 {
+            registry.register(gauge(&format!("m{}", i), "")).unwrap();
+        }
+        let pushed = vec![(gauge("a", ""), false), (gauge("b", ""), false)];
+        assert!(matches!(registry.register_pushed(pushed), Err(RegisterError::Limit(_))));
+        assert!(registry.get("a").is_none());
+        assert_eq!(registry.register_pushed(vec![(gauge("a", ""), false)]).ok(), Some(1));
+    }
+}
@@ -14,6 +14,8 @@ pub struct MemoryStats {
     pub total_bytes: u64,
     pub free_bytes: u64,
     pub used_bytes: u64,
+    /// Share of swap in use; `None` when there is no swap.
+    pub swap_percent: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +64,59 @@ pub struct SystemStats {
     pub network: NetworkStats,
     pub disk_io: DiskIoStats,
     pub temperatures: Vec<TempGroup>,
+    /// Software RAID, btrfs and ZFS pools found on the host.
+    pub storage_arrays: Vec<StorageArray>,
+    /// Network filesystems, probed out-of-band so a hung server cannot stall collection.
+    pub network_mounts: Vec<NetworkMount>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayHealth {
+    Healthy,
+    /// Layout is intact but members have reported I/O or checksum errors.
+    Errors,
+    /// Running with missing or failed members.
+    Degraded,
+    Failed,
+    Unknown,
+}
+
+/// A resync, recovery, scrub or resilver in progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrayOperation {
+    /// e.g. "resync", "recovery", "check", "scrub", "resilver".
+    pub kind: String,
+    pub progress_percent: Option<f32>,
+    /// Estimated seconds until the operation finishes.
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrayDevice {
+    pub name: String,
+    /// Member state as reported by the array, e.g. "active", "faulty", "ONLINE".
+    pub state: String,
+    pub read_errors: Option<u64>,
+    pub write_errors: Option<u64>,
+    /// ZFS CKSUM column, btrfs corruption errors.
+    pub checksum_errors: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageArray {
+    /// "mdraid", "btrfs" or "zfs".
+    pub kind: String,
+    pub name: String,
+    /// RAID level or vdev layout, e.g. "raid1", "mirror", "raidz1".
+    pub level: Option<String>,
+    pub health: ArrayHealth,
+    /// Raw state string from the source, e.g. "active", "DEGRADED".
+    pub state: String,
+    pub size_bytes: Option<u64>,
+    pub used_bytes: Option<u64>,
+    pub devices: Vec<ArrayDevice>,
+    pub operation: Option<ArrayOperation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,6 +132,27 @@ pub struct HistoryPoint {
     pub disk_read_bytes_sec: u64,
     pub disk_write_bytes_sec: u64,
     pub temperatures: Vec<TempGroup>,
+    /// Share of the bucket (0.0–1.0) during which samples were being taken.
+    /// Absent for raw samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f32>,
+}
+
+impl HistoryPoint {
+    /// Field names accepted by `/api/history?fields=`.
+    pub const FIELDS: &[&str] = &[
+        "cpu_percent",
+        "cpu_freq",
+        "cpu_temp",
+        "mem_percent",
+        "disk_percent",
+        "network_rx_bytes_sec",
+        "network_tx_bytes_sec",
+        "disk_read_bytes_sec",
+        "disk_write_bytes_sec",
+        "temperatures",
+        "coverage",
+    ];
 }
 
 impl From<&SystemStats> for HistoryPoint {
@@ -93,6 +169,7 @@ impl From<&SystemStats> for HistoryPoint {
             disk_read_bytes_sec: s.disk_io.read_bytes_per_sec,
             disk_write_bytes_sec: s.disk_io.write_bytes_per_sec,
             temperatures: s.temperatures.clone(),
+            coverage: None,
         }
     }
 }
@@ -100,7 +177,78 @@ impl From<&SystemStats> for HistoryPoint {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryResponse {
     pub range: String,
-    pub points: Vec<HistoryPoint>,
+    pub agg: Aggregate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_secs: Option<i64>,
+    /// History points, reduced to `timestamp` plus the requested fields
+    /// when `fields` is given.
+    pub points: Vec<serde_json::Value>,
+    /// Periods without samples that overlap the returned span.
+    pub gaps: Vec<Gap>,
+    /// Ranges with unreliable timestamps that overlap the returned span.
+    pub clock_flags: Vec<ClockFlag>,
+    /// Annotations that overlap the returned span.
+    pub annotations: Vec<Annotation>,
+    /// Start of the newest, possibly still open bucket. Polling with
+    /// `since` set to it returns that bucket again plus any newer ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GapReason {
+    /// pi-dash was not running.
+    Stopped,
+    /// The host rebooted while pi-dash was down.
+    Reboot,
+    /// pi-dash was running but no samples were taken.
+    Stalled,
+}
+
+/// A period with no system samples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub reason: GapReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockFlagKind {
+    /// The wall clock was stepped forward; no time really passed here.
+    SteppedForward,
+    /// The wall clock was stepped back; samples here may overlap.
+    SteppedBack,
+    /// Samples taken before the clock was synced, stamped afterwards from
+    /// the boot clock.
+    Restamped,
+}
+
+/// A range of history whose timestamps cannot be taken at face value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockFlag {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub kind: ClockFlagKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AvailabilityQuery {
+    pub days: Option<u32>,
+}
+
+/// Share of one UTC day during which samples were taken.
+#[derive(Debug, Clone, Serialize)]
+pub struct DayAvailability {
+    pub date: chrono::NaiveDate,
+    pub availability_percent: f64,
+    pub downtime_secs: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -112,4 +260,464 @@ pub struct LoginRequest {
 #[derive(Debug, Deserialize)]
 pub struct HistoryQuery {
     pub range: Option<String>,
+    pub agg: Option<Aggregate>,
+    /// Window start, RFC 3339 or Unix seconds.
+    pub from: Option<String>,
+    /// Window end, RFC 3339 or Unix seconds.
+    pub to: Option<String>,
+    pub step: Option<i64>,
+    /// Comma-separated list of `HistoryPoint` fields to return.
+    pub fields: Option<String>,
+    /// Comma-separated temperature group labels to keep in `temperatures`.
+    pub sensors: Option<String>,
+    /// Downsample to at most this many points, keeping the chart's shape.
+    pub max_points: Option<usize>,
+    /// Only return buckets from the one holding this time on (RFC 3339 or
+    /// Unix seconds); pass the previous response's `cursor`.
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationSource {
+    /// Added through the API.
+    User,
+    /// Generated by pi-dash for an event it detected.
+    Auto,
+}
+
+/// A note on the timeline, at one moment or over a range.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: u64,
+    pub start: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    pub text: String,
+    pub tags: Vec<String>,
+    pub source: AnnotationSource,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewAnnotation {
+    /// RFC 3339 or Unix seconds; defaults to now.
+    #[serde(alias = "timestamp")]
+    pub start: Option<String>,
+    /// End of a range, RFC 3339 or Unix seconds.
+    pub end: Option<String>,
+    pub text: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationQuery {
+    /// RFC 3339 or Unix seconds.
+    pub from: Option<String>,
+    /// RFC 3339 or Unix seconds.
+    pub to: Option<String>,
+    /// Only annotations carrying this tag.
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownKind {
+    /// pi-dash stopped normally and saved its state before the reboot.
+    Clean,
+    /// pi-dash never got to save: a crash, power loss or hard reset.
+    Crash,
+}
+
+/// The host going down and coming back up.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reboot {
+    /// Last time pi-dash saw the previous boot running.
+    pub last_seen: DateTime<Utc>,
+    pub booted: DateTime<Utc>,
+    pub downtime_secs: i64,
+    pub shutdown: ShutdownKind,
+    pub boot_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UptimeResponse {
+    pub boot_id: String,
+    pub booted: DateTime<Utc>,
+    pub uptime_secs: i64,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// `from`, or the start of the first boot pi-dash recorded when later.
+    /// Time before it is left out of `uptime_percent`.
+    pub tracked_since: DateTime<Utc>,
+    pub uptime_percent: f64,
+    /// Reboots within the window, newest first.
+    pub reboots: Vec<Reboot>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UptimeQuery {
+    pub days: Option<u32>,
+}
+
+/// How well a straight line explains the series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForecastConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+/// Linear trend of one series towards its limit.
+#[derive(Debug, Clone, Serialize)]
+pub struct Forecast {
+    pub target: String,
+    pub metric: String,
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub labels: std::collections::BTreeMap<String, String>,
+    pub limit: f64,
+    pub current: f64,
+    /// Hourly averages the trend was fitted to, since the last reset.
+    pub samples: usize,
+    pub slope_per_day: f64,
+    pub r_squared: Option<f64>,
+    /// Absent when the series is not growing towards the limit.
+    pub days_to_full: Option<f64>,
+    pub full_at: Option<DateTime<Utc>>,
+    /// Bounds from the 95% interval of the slope. No `full_latest` means
+    /// the series may not be growing at all.
+    pub full_earliest: Option<DateTime<Utc>>,
+    pub full_latest: Option<DateTime<Utc>>,
+    pub confidence: ForecastConfidence,
+    /// Expected to reach the limit within `warn_days`, with at least medium
+    /// confidence.
+    pub alert: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForecastResponse {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub window_days: u32,
+    pub warn_days: f64,
+    pub forecasts: Vec<Forecast>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForecastQuery {
+    pub window_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,
+    pub range: Option<String>,
+    pub agg: Option<Aggregate>,
+    /// Window start, RFC 3339 or Unix seconds.
+    pub from: Option<String>,
+    /// Window end, RFC 3339 or Unix seconds.
+    pub to: Option<String>,
+    pub step: Option<i64>,
+    /// Comma-separated metric names to export; all when absent.
+    pub fields: Option<String>,
+}
+
+/// What an import added to history.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// What the file was recognised as.
+    pub format: String,
+    /// Series that gained at least one bucket.
+    pub series: usize,
+    /// Metrics that were unknown here and have been registered as gauges.
+    pub new_metrics: Vec<String>,
+    /// Span of the merged buckets.
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub tiers: Vec<TierImport>,
+    /// Gaps taken over, including the one between the imported history and
+    /// this one.
+    pub gaps: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TierImport {
+    pub name: String,
+    /// Buckets added.
+    pub merged: usize,
+    /// Buckets left out because this tier already had data for them.
+    pub skipped: usize,
+    /// Buckets left out because they are past the tier's retention.
+    pub expired: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    /// A value that goes up and down (usage, temperature).
+    Gauge,
+    /// A monotonically increasing total; history stores its per-second rate.
+    Counter,
+}
+
+/// Entry of the metric registry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricDesc {
+    pub name: String,
+    pub unit: String,
+    pub kind: MetricKind,
+    /// Label names that distinguish the series of this metric.
+    pub labels: Vec<String>,
+    pub help: String,
+}
+
+/// A custom sample posted to `/api/metrics`.
+#[derive(Debug, Deserialize)]
+pub struct MetricPush {
+    pub name: String,
+    pub value: f64,
+    #[serde(default)]
+    pub labels: std::collections::BTreeMap<String, String>,
+    pub unit: Option<String>,
+    pub kind: Option<MetricKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeriesQuery {
+    pub metric: String,
+    /// Comma-separated `name=value` label filters.
+    pub labels: Option<String>,
+    pub range: Option<String>,
+    pub agg: Option<Aggregate>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub step: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesData {
+    pub labels: std::collections::BTreeMap<String, String>,
+    pub points: Vec<SeriesPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesResponse {
+    pub metric: MetricDesc,
+    pub range: String,
+    pub agg: Aggregate,
+    pub step_secs: Option<i64>,
+    pub series: Vec<SeriesData>,
+}
+
+/// How the samples inside a history bucket are reduced to one value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    #[default]
+    Avg,
+    Min,
+    Max,
+    Last,
+    P95,
+    P99,
+}
+
+/// Outcome of a single synthetic check run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub timestamp: DateTime<Utc>,
+    pub up: bool,
+    /// Wall time from connect to the end of the response, in milliseconds.
+    pub response_ms: Option<f64>,
+    /// HTTP status code (HTTP checks only).
+    pub status_code: Option<u16>,
+    /// Expiry of the leaf certificate presented by the server (HTTPS checks only).
+    pub tls_expiry: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckState {
+    /// Configured but not run yet.
+    Pending,
+    Up,
+    Down,
+}
+
+/// Current state of a configured check, as returned by `/api/checks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckStatus {
+    pub name: String,
+    /// Probe type: "http", "tcp" or "dns".
+    pub kind: String,
+    pub target: String,
+    pub interval_secs: u64,
+    pub state: CheckState,
+    /// When the check entered its current state, if known from retained results.
+    pub since: Option<DateTime<Utc>>,
+    pub last: Option<CheckResult>,
+    /// Share of successful runs over the retained results.
+    pub uptime_percent: Option<f32>,
+    /// Average response time of successful runs over the retained results.
+    pub avg_response_ms: Option<f64>,
+    /// Whole days until the TLS certificate expires (negative once expired).
+    pub tls_days_left: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<CheckResult>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartbeatState {
+    /// No ping received yet.
+    New,
+    Up,
+    /// Past the expected period but still within the grace time.
+    Late,
+    /// Past period + grace, or the last run reported failure.
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Success,
+    Fail,
+}
+
+/// One completed run of a heartbeat-monitored job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatRun {
+    /// Set when the job sent a `/start` ping before finishing.
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: DateTime<Utc>,
+    pub outcome: RunOutcome,
+    pub duration_secs: Option<f64>,
+    /// Start of the request body sent with the ping (e.g. script output).
+    pub message: Option<String>,
+}
+
+/// Current state of a heartbeat, as returned by `/api/heartbeats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatStatus {
+    pub slug: String,
+    pub period_secs: u64,
+    pub grace_secs: u64,
+    pub state: HeartbeatState,
+    /// When the heartbeat entered its current state.
+    pub since: Option<DateTime<Utc>>,
+    pub last_ping: Option<DateTime<Utc>>,
+    /// Set while a run has started but not reported back yet.
+    pub running_since: Option<DateTime<Utc>>,
+    /// Deadline for the next ping before the heartbeat is considered late.
+    pub next_due: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runs: Option<Vec<HeartbeatRun>>,
+}
+
+/// One scan of a monitored file or directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathSample {
+    pub timestamp: DateTime<Utc>,
+    pub exists: bool,
+    /// File size, or total size of the directory tree.
+    pub size_bytes: Option<u64>,
+    /// Seconds since the last modification (newest entry for directories).
+    pub age_secs: Option<i64>,
+    /// Number of files counted in a directory scan.
+    pub file_count: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathState {
+    /// Configured but not scanned yet.
+    Pending,
+    Ok,
+    Stale,
+    Oversize,
+    Missing,
+    Error,
+}
+
+/// Current state of a path monitor, as returned by `/api/paths`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathStatus {
+    pub name: String,
+    pub path: String,
+    pub interval_secs: u64,
+    pub max_age_secs: Option<u64>,
+    pub max_size_bytes: Option<u64>,
+    pub state: PathState,
+    pub stale: bool,
+    pub oversize: bool,
+    pub last: Option<PathSample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Vec<PathSample>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountState {
+    /// Not probed yet.
+    Pending,
+    Healthy,
+    /// The server answered with an error (stale file handle, I/O error, ...).
+    Stale,
+    /// The probe did not return within the timeout.
+    Unreachable,
+}
+
+/// An NFS, SMB, sshfs or other network filesystem mount.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkMount {
+    pub mount_point: String,
+    /// e.g. "nas:/export/media" or "//nas/share".
+    pub source: String,
+    pub fs_type: String,
+    pub state: MountState,
+    pub usage_percent: Option<f32>,
+    pub used_bytes: Option<u64>,
+    pub available_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    /// How long the last successful `statvfs` took.
+    pub response_ms: Option<f64>,
+    pub error: Option<String>,
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
+/// Bytes pi-dash wrote to its data directory on one UTC day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyWrites {
+    pub date: chrono::NaiveDate,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataWrites {
+    pub low_wear: bool,
+    pub flush_interval_secs: u64,
+    pub today_bytes: u64,
+    pub days: Vec<DailyWrites>,
+    /// Bytes sent to storage by the pi-dash process since it started, from
+    /// `/proc/self/io`; `None` where that is unavailable.
+    pub process_write_bytes: Option<u64>,
 }
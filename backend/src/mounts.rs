@@ -0,0 +1,256 @@
+use crate::collector::round1;
+use crate::models::{MountState, NetworkMount};
+use chrono::Utc;
+use log::warn;
+use nix::errno::Errno;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const PROBE_INTERVAL_SECS: u64 = 15;
+const PROBE_TIMEOUT_MS: u64 = 2000;
+
+/// Filesystem types that talk to a remote server and may hang on `statvfs`.
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "ncpfs",
+    "ceph",
+    "glusterfs",
+    "9p",
+    "afs",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.s3fs",
+    "fuse.glusterfs",
+    "fuse.davfs2",
+];
+
+pub fn is_network_fs(fs_type: &str) -> bool {
+    NETWORK_FS_TYPES.contains(&fs_type)
+}
+
+/// Capacity figures from a successful `statvfs`.
+struct Usage {
+    total: u64,
+    available: u64,
+    free: u64,
+}
+
+/// `statvfs` outcome and how long the call took.
+type ProbeResult = (Result<Usage, Errno>, Duration);
+
+/// Mount entry parsed from `/proc/self/mountinfo`.
+struct MountEntry {
+    mount_point: String,
+    source: String,
+    fs_type: String,
+}
+
+/// Probes network filesystems from a dedicated thread. Each `statvfs` runs on
+/// its own short-lived thread with a deadline; a probe that never returns is
+/// left parked and reported as unreachable instead of being retried, so a hard
+/// NFS mount can neither block collection nor pile up threads.
+pub struct MountMonitor {
+    mounts: Arc<RwLock<Vec<NetworkMount>>>,
+}
+
+impl MountMonitor {
+    pub fn start() -> Self {
+        let mounts = Arc::new(RwLock::new(Vec::new()));
+        let shared = mounts.clone();
+        std::thread::Builder::new()
+            .name("mount-probe".to_string())
+            .spawn(move || {
+                let mut hung: HashMap<String, Receiver<ProbeResult>> = HashMap::new();
+                loop {
+                    let previous = shared.read().clone();
+                    let probed = probe_all(&mut hung, &previous);
+                    *shared.write() = probed;
+                    std::thread::sleep(Duration::from_secs(PROBE_INTERVAL_SECS));
+                }
+            })
+            .expect("failed to spawn mount probe thread");
+
+        MountMonitor { mounts }
+    }
+
+    /// Latest probe results. Never blocks on the filesystems themselves.
+    pub fn mounts(&self) -> Vec<NetworkMount> {
+        self.mounts.read().clone()
+    }
+}
+
+fn probe_all(
+    hung: &mut HashMap<String, Receiver<ProbeResult>>,
+    previous: &[NetworkMount],
+) -> Vec<NetworkMount> {
+    let entries = read_mountinfo();
+    hung.retain(|mount_point, _| entries.iter().any(|e| &e.mount_point == mount_point));
+
+    // Start every probe first so they all share one timeout window
+    let started = Instant::now();
+    let mut pending = Vec::new();
+    for entry in entries {
+        let rx = match hung.remove(&entry.mount_point) {
+            Some(rx) => rx,
+            None => {
+                let (tx, rx) = mpsc::channel();
+                let path = entry.mount_point.clone();
+                let spawned = std::thread::Builder::new()
+                    .name("statvfs".to_string())
+                    .spawn(move || {
+                        let t = Instant::now();
+                        let result = statvfs(&path);
+                        let _ = tx.send((result, t.elapsed()));
+                    });
+                if let Err(e) = spawned {
+                    warn!("Failed to spawn statvfs probe: {}", e);
+                    continue;
+                }
+                rx
+            }
+        };
+        pending.push((entry, rx));
+    }
+
+    let deadline = started + Duration::from_millis(PROBE_TIMEOUT_MS);
+    let mut mounts = Vec::new();
+    for (entry, rx) in pending {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let mut mount = NetworkMount {
+            mount_point: entry.mount_point.clone(),
+            source: entry.source,
+            fs_type: entry.fs_type,
+            state: MountState::Pending,
+            usage_percent: None,
+            used_bytes: None,
+            available_bytes: None,
+            total_bytes: None,
+            response_ms: None,
+            error: None,
+            checked_at: Some(Utc::now()),
+        };
+
+        match rx.recv_timeout(timeout) {
+            Ok((Ok(usage), took)) => {
+                let used = usage.total.saturating_sub(usage.free);
+                mount.state = MountState::Healthy;
+                mount.total_bytes = Some(usage.total);
+                mount.available_bytes = Some(usage.available);
+                mount.used_bytes = Some(used);
+                mount.usage_percent = if usage.total > 0 {
+                    Some(round1((used as f32 / usage.total as f32) * 100.0))
+                } else {
+                    Some(0.0)
+                };
+                mount.response_ms = Some(took.as_secs_f64() * 1000.0);
+            }
+            Ok((Err(errno), _)) => {
+                mount.state = MountState::Stale;
+                mount.error = Some(errno.desc().to_string());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                mount.state = MountState::Unreachable;
+                mount.error = Some(format!(
+                    "statvfs did not return within {}ms",
+                    PROBE_TIMEOUT_MS
+                ));
+                hung.insert(entry.mount_point, rx);
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                mount.state = MountState::Unreachable;
+                mount.error = Some("probe thread exited".to_string());
+            }
+        }
+
+        let was = previous
+            .iter()
+            .find(|m| m.mount_point == mount.mount_point)
+            .map(|m| m.state);
+        if mount.state != MountState::Healthy && was != Some(mount.state) {
+            warn!(
+                "Network mount {} is {:?}: {}",
+                mount.mount_point,
+                mount.state,
+                mount.error.as_deref().unwrap_or_default()
+            );
+        }
+        mounts.push(mount);
+    }
+
+    mounts
+}
+
+fn statvfs(path: &str) -> Result<Usage, Errno> {
+    let st = nix::sys::statvfs::statvfs(path)?;
+    let frsize = st.fragment_size() as u64;
+    Ok(Usage {
+        total: st.blocks() as u64 * frsize,
+        available: st.blocks_available() as u64 * frsize,
+        free: st.blocks_free() as u64 * frsize,
+    })
+}
+
+/// List network mounts from `/proc/self/mountinfo`:
+///
+/// ```text
+/// 36 35 0:42 / /mnt/nas rw,relatime shared:1 - nfs4 nas:/export rw,vers=4.2
+/// ```
+fn read_mountinfo() -> Vec<MountEntry> {
+    let Ok(content) = fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        // Optional fields end at the " - " separator
+        let Some((left, right)) = line.split_once(" - ") else {
+            continue;
+        };
+        let Some(mount_point) = left.split_whitespace().nth(4) else {
+            continue;
+        };
+        let mut right = right.split_whitespace();
+        let fs_type = right.next().unwrap_or_default();
+        let source = right.next().unwrap_or_default();
+        if !is_network_fs(fs_type) {
+            continue;
+        }
+        entries.push(MountEntry {
+            mount_point: unescape_mount_field(mount_point),
+            source: unescape_mount_field(source),
+            fs_type: fs_type.to_string(),
+        });
+    }
+    entries
+}
+
+/// Undo the octal escaping (`\040` for space etc.) used in mountinfo fields.
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(digits) = bytes.get(i + 1..i + 4)
+            && let Some(code) = std::str::from_utf8(digits)
+                .ok()
+                .and_then(|d| u8::from_str_radix(d, 8).ok())
+        {
+            out.push(code);
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,361 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::history::HistoryStore;
+use crate::metrics::{self, Sample, SeriesKey};
+use crate::models::{MetricKind, PathSample, PathState, PathStatus};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const SAMPLE_RETENTION_SECS: i64 = 604800; // 7 days of scans per monitor
+const PATHS_FILE: &str = "paths.json.zst";
+const PATH_SIZE_METRIC: &str = "path_size_bytes";
+const PATH_AGE_METRIC: &str = "path_age_secs";
+const PATH_FILES_METRIC: &str = "path_file_count";
+
+fn default_interval_secs() -> u64 {
+    300
+}
+
+fn default_max_depth() -> usize {
+    16
+}
+
+/// One entry of the `paths` array in the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PathMonitorConfig {
+    pub name: String,
+    pub path: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Flag the path as stale when it was not modified within this many seconds.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// Flag the path as oversize when it grows beyond this many bytes.
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// How deep to descend when sizing a directory.
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+}
+
+impl PathMonitorConfig {
+    fn is_stale(&self, sample: &PathSample) -> bool {
+        match (self.max_age_secs, sample.age_secs) {
+            (Some(max), Some(age)) => age > max as i64,
+            _ => false,
+        }
+    }
+
+    fn is_oversize(&self, sample: &PathSample) -> bool {
+        match (self.max_size_bytes, sample.size_bytes) {
+            (Some(max), Some(size)) => size > max,
+            _ => false,
+        }
+    }
+}
+
+struct PathMonitorStoreInner {
+    configs: Vec<PathMonitorConfig>,
+    samples: HashMap<String, VecDeque<PathSample>>,
+}
+
+/// Periodically scans configured files and directories for age and size.
+#[derive(Clone)]
+pub struct PathMonitorStore {
+    inner: Arc<RwLock<PathMonitorStoreInner>>,
+    history: HistoryStore,
+}
+
+impl PathMonitorStore {
+    pub fn new(configs: Vec<PathMonitorConfig>, history: HistoryStore) -> Self {
+        let mut samples = Self::load_from_disk().unwrap_or_default();
+        samples.retain(|name, _| configs.iter().any(|c| &c.name == name));
+
+        let registry = history.registry();
+        for (name, unit, help) in [
+            (PATH_SIZE_METRIC, "bytes", "Size of the file or directory tree"),
+            (PATH_AGE_METRIC, "seconds", "Time since the last modification"),
+            (PATH_FILES_METRIC, "files", "Number of files in the directory tree"),
+        ] {
+            let _ = registry.register(metrics::desc(name, unit, MetricKind::Gauge, &["path"], help));
+        }
+
+        PathMonitorStore {
+            inner: Arc::new(RwLock::new(PathMonitorStoreInner { configs, samples })),
+            history,
+        }
+    }
+
+    fn load_from_disk() -> Option<HashMap<String, VecDeque<PathSample>>> {
+        let path = config::data_path(PATHS_FILE);
+        let content = std::fs::read(&path).ok()?;
+        let decompressed = zstd::decode_all(content.as_slice()).ok()?;
+        let samples = serde_json::from_slice(&decompressed).ok()?;
+        println!("Loaded path monitor samples from {}", path.display());
+        Some(samples)
+    }
+
+    pub fn save_to_disk(&self) {
+        let inner = self.inner.read();
+        if inner.configs.is_empty() {
+            return;
+        }
+        let path = config::data_path(PATHS_FILE);
+        match serde_json::to_vec(&inner.samples) {
+            Ok(json) => match zstd::encode_all(json.as_slice(), 3) {
+                Ok(compressed) => {
+                    if let Err(e) = writes::replace(&path, &compressed) {
+                        eprintln!("Failed to write path monitor samples to {}: {}", path.display(), e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to compress path monitor samples: {}", e),
+            },
+            Err(e) => eprintln!("Failed to serialize path monitor samples: {}", e),
+        }
+    }
+
+    /// Spawn one scanning task per monitor. Scans run on the blocking pool so a
+    /// large directory tree never holds up the async runtime.
+    pub fn spawn(&self) {
+        for monitor in self.inner.read().configs.clone() {
+            let store = self.clone();
+            tokio::spawn(async move {
+                let period = Duration::from_secs(monitor.interval_secs.max(1));
+                let mut interval = tokio::time::interval(period);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let m = monitor.clone();
+                    let sample = match tokio::task::spawn_blocking(move || scan(&m)).await {
+                        Ok(sample) => sample,
+                        Err(e) => {
+                            warn!("Path monitor '{}' scan panicked: {}", monitor.name, e);
+                            continue;
+                        }
+                    };
+                    if monitor.is_stale(&sample) {
+                        warn!("Path monitor '{}' is stale", monitor.name);
+                    }
+                    if monitor.is_oversize(&sample) {
+                        warn!("Path monitor '{}' is oversize", monitor.name);
+                    }
+                    store.record(&monitor.name, sample);
+                }
+            });
+        }
+    }
+
+    fn record(&self, name: &str, sample: PathSample) {
+        let key = |metric: &str| SeriesKey::new(metric).with_label("path", name);
+        let values = [
+            (PATH_SIZE_METRIC, sample.size_bytes.map(|v| v as f64)),
+            (PATH_AGE_METRIC, sample.age_secs.map(|v| v as f64)),
+            (PATH_FILES_METRIC, sample.file_count.map(|v| v as f64)),
+        ];
+        let samples: Vec<Sample> = values
+            .into_iter()
+            .filter_map(|(metric, value)| Some(Sample::new(key(metric), value?)))
+            .collect();
+        self.history.record(sample.timestamp, samples);
+
+        let mut inner = self.inner.write();
+        let cutoff = sample.timestamp - TimeDelta::seconds(SAMPLE_RETENTION_SECS);
+        let samples = inner.samples.entry(name.to_string()).or_default();
+        samples.push_back(sample);
+        while let Some(front) = samples.front() {
+            if front.timestamp < cutoff {
+                samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<PathStatus> {
+        let inner = self.inner.read();
+        inner
+            .configs
+            .iter()
+            .map(|c| Self::status(c, inner.samples.get(&c.name), false))
+            .collect()
+    }
+
+    pub fn status_with_samples(&self, name: &str) -> Option<PathStatus> {
+        let inner = self.inner.read();
+        let config = inner.configs.iter().find(|c| c.name == name)?;
+        Some(Self::status(config, inner.samples.get(name), true))
+    }
+
+    fn status(
+        config: &PathMonitorConfig,
+        samples: Option<&VecDeque<PathSample>>,
+        with_samples: bool,
+    ) -> PathStatus {
+        let last = samples.and_then(|s| s.back()).cloned();
+        let stale = last.as_ref().is_some_and(|s| config.is_stale(s));
+        let oversize = last.as_ref().is_some_and(|s| config.is_oversize(s));
+
+        let state = match &last {
+            None => PathState::Pending,
+            Some(s) if s.error.is_some() => PathState::Error,
+            Some(s) if !s.exists => PathState::Missing,
+            Some(_) if stale => PathState::Stale,
+            Some(_) if oversize => PathState::Oversize,
+            Some(_) => PathState::Ok,
+        };
+
+        PathStatus {
+            name: config.name.clone(),
+            path: config.path.clone(),
+            interval_secs: config.interval_secs,
+            max_age_secs: config.max_age_secs,
+            max_size_bytes: config.max_size_bytes,
+            state,
+            stale,
+            oversize,
+            last,
+            samples: with_samples.then(|| samples.into_iter().flatten().cloned().collect()),
+        }
+    }
+}
+
+/// Totals gathered while walking a directory tree.
+#[derive(Default)]
+struct DirTotals {
+    size: u64,
+    files: u64,
+    newest: Option<SystemTime>,
+}
+
+fn scan(config: &PathMonitorConfig) -> PathSample {
+    let timestamp = Utc::now();
+    let path = Path::new(&config.path);
+
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return PathSample {
+                timestamp,
+                exists: false,
+                size_bytes: None,
+                age_secs: None,
+                file_count: None,
+                error: None,
+            };
+        }
+        Err(e) => {
+            return PathSample {
+                timestamp,
+                exists: true,
+                size_bytes: None,
+                age_secs: None,
+                file_count: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let (size, files, newest) = if meta.is_dir() {
+        let mut totals = DirTotals::default();
+        walk_dir(path, meta.dev(), config.max_depth, &mut totals);
+        // An empty directory is as fresh as its own mtime
+        let newest = totals.newest.or_else(|| meta.modified().ok());
+        (totals.size, Some(totals.files), newest)
+    } else {
+        (meta.len(), None, meta.modified().ok())
+    };
+
+    let age_secs = newest.map(|t| {
+        let modified: DateTime<Utc> = t.into();
+        (timestamp - modified).num_seconds()
+    });
+
+    PathSample {
+        timestamp,
+        exists: true,
+        size_bytes: Some(size),
+        age_secs,
+        file_count: files,
+        error: None,
+    }
+}
+
+/// Sum file sizes below `dir`, like `du -x`: symlinks are not followed and
+/// other filesystems are not entered. Unreadable entries are skipped.
+fn walk_dir(dir: &Path, dev: u64, depth_left: usize, totals: &mut DirTotals) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.dev() != dev {
+            continue;
+        }
+        if let Ok(modified) = meta.modified()
+            && totals.newest.is_none_or(|n| modified > n)
+        {
+            totals.newest = Some(modified);
+        }
+        if meta.is_dir() {
+            if depth_left > 0 {
+                walk_dir(&entry.path(), dev, depth_left - 1, totals);
+            }
+        } else if meta.is_file() {
+            totals.size += meta.len();
+            totals.files += 1;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+pub async fn api_paths(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    paths: web::Data<PathMonitorStore>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    encoding::respond(&req, &paths.statuses())
+}
+
+pub async fn api_path(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    paths: web::Data<PathMonitorStore>,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    match paths.status_with_samples(&name) {
+        Some(status) => encoding::respond(&req, &status),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error": "Unknown path monitor"})),
+    }
+}
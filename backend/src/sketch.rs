@@ -0,0 +1,179 @@
+use crate::columns::HeapSize;
+use serde::{Deserialize, Serialize};
+
+const RELATIVE_ACCURACY: f64 = 0.02; // quantile estimates within ±2% of the true value
+const MAX_BINS: usize = 64; // lowest bins are collapsed beyond this
+const MIN_POSITIVE: f64 = 1e-6; // values at or below this share the zero bin
+
+/// Mergeable quantile sketch in the style of DDSketch. Values are counted in
+/// logarithmically sized bins, so any quantile is answered with a bounded
+/// relative error and two sketches merge by adding their counts. When the bin
+/// limit is reached the lowest bins are folded together, which keeps the upper
+/// quantiles (p95, p99) accurate.
+///
+/// Values at or below zero are not distinguished from each other; the exact
+/// minimum is tracked separately by the caller.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sketch {
+    zero: u32,
+    bins: Vec<(i32, u32)>,
+}
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+impl Sketch {
+    pub fn insert(&mut self, value: f64) {
+        self.insert_n(value, 1);
+    }
+
+    /// Record `value` `n` times.
+    pub fn insert_n(&mut self, value: f64, n: u32) {
+        if n == 0 || value.is_nan() {
+            return;
+        }
+        if value <= MIN_POSITIVE {
+            self.zero += n;
+            return;
+        }
+        let index = (value.ln() / gamma().ln()).ceil() as i32;
+        self.add_to_bin(index, n);
+        self.collapse();
+    }
+
+    pub fn merge(&mut self, other: &Sketch) {
+        self.zero += other.zero;
+        for &(index, n) in &other.bins {
+            self.add_to_bin(index, n);
+        }
+        self.collapse();
+    }
+
+    fn add_to_bin(&mut self, index: i32, n: u32) {
+        match self.bins.binary_search_by_key(&index, |&(i, _)| i) {
+            Ok(pos) => self.bins[pos].1 += n,
+            Err(pos) => self.bins.insert(pos, (index, n)),
+        }
+    }
+
+    fn collapse(&mut self) {
+        while self.bins.len() > MAX_BINS {
+            let (_, n) = self.bins.remove(0);
+            self.bins[0].1 += n;
+        }
+    }
+
+    /// Estimate the `q` quantile (0.0–1.0). `None` when nothing was recorded.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let total = self.zero as u64 + self.bins.iter().map(|&(_, n)| n as u64).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (total - 1) as f64) as u64;
+        let mut seen = self.zero as u64;
+        if rank < seen {
+            return Some(0.0);
+        }
+        let g = gamma();
+        for &(index, n) in &self.bins {
+            seen += n as u64;
+            if rank < seen {
+                return Some(2.0 * g.powi(index) / (g + 1.0));
+            }
+        }
+        None
+    }
+}
+
+impl HeapSize for Sketch {
+    fn heap_bytes(&self) -> usize {
+        self.bins.capacity() * size_of::<(i32, u32)>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value at the rank `quantile` looks up, from the sorted values.
+    fn exact(sorted: &[f64], q: f64) -> f64 {
+        sorted[(q * (sorted.len() - 1) as f64) as usize]
+    }
+
+    fn assert_close(estimate: Option<f64>, exact: f64) {
+        let estimate = estimate.unwrap();
+        let error = (estimate - exact).abs() / exact;
+        assert!(
+            error <= RELATIVE_ACCURACY + 1e-9,
+            "estimate {} for {} is off by {:.3}",
+            estimate,
+            exact,
+            error
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Sketch::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn quantiles_within_accuracy() {
+        // One decade fits in the bins without collapsing
+        let values: Vec<f64> = (100..=1000).map(|i| i as f64 * 0.37).collect();
+        let mut sketch = Sketch::default();
+        for &v in &values {
+            sketch.insert(v);
+        }
+        for q in [0.0, 0.25, 0.5, 0.9, 0.95, 0.99, 1.0] {
+            assert_close(sketch.quantile(q), exact(&values, q));
+        }
+    }
+
+    #[test]
+    fn zero_and_nan() {
+        let mut sketch = Sketch::default();
+        sketch.insert(f64::NAN);
+        assert_eq!(sketch.quantile(0.5), None);
+        sketch.insert_n(0.0, 3);
+        sketch.insert(-5.0);
+        sketch.insert(100.0);
+        assert_eq!(sketch.quantile(0.5), Some(0.0));
+        assert_close(sketch.quantile(1.0), 100.0);
+    }
+
+    #[test]
+    fn merge_matches_single_sketch() {
+        let (mut a, mut b, mut all) = (Sketch::default(), Sketch::default(), Sketch::default());
+        for i in 1..=500 {
+            let v = i as f64;
+            if i % 3 == 0 {
+                a.insert(v);
+            } else {
+                b.insert(v);
+            }
+            all.insert(v);
+        }
+        a.merge(&b);
+        for q in [0.1, 0.5, 0.99] {
+            assert_eq!(a.quantile(q), all.quantile(q));
+        }
+    }
+
+    #[test]
+    fn collapse_keeps_upper_quantiles() {
+        // Spans far more bins than are kept
+        let values: Vec<f64> = (0..2000).map(|i| 1e-3 * 1.01f64.powi(i)).collect();
+        let mut sketch = Sketch::default();
+        for &v in &values {
+            sketch.insert(v);
+        }
+        assert!(sketch.bins.len() <= MAX_BINS);
+        for q in [0.95, 0.99, 1.0] {
+            assert_close(sketch.quantile(q), exact(&values, q));
+        }
+        // The folded low end is only a lower bound
+        assert!(sketch.quantile(0.0).unwrap() > values[0]);
+    }
+}
@@ -0,0 +1,790 @@
+use crate::models::{ArrayDevice, ArrayHealth, ArrayOperation, StorageArray};
+use log::warn;
+use parking_lot::RwLock;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Arrays change slowly and `zpool` is an external process, so they are
+/// probed on their own schedule rather than on every collection.
+const REFRESH_INTERVAL_SECS: u64 = 10;
+const ZPOOL_TIMEOUT_SECS: u64 = 5;
+
+/// Probes software RAID (mdraid), btrfs and ZFS pools from a dedicated
+/// thread, so a wedged pool can never stall collection.
+pub struct StorageMonitor {
+    arrays: Arc<RwLock<Vec<StorageArray>>>,
+}
+
+impl StorageMonitor {
+    pub fn start() -> Self {
+        let arrays = Arc::new(RwLock::new(Vec::new()));
+        let shared = arrays.clone();
+        std::thread::Builder::new()
+            .name("storage-probe".to_string())
+            .spawn(move || {
+                let mut zfs = ZfsProbe::new();
+                loop {
+                    let mut probed = Vec::new();
+                    if let Ok(mdstat) = fs::read_to_string("/proc/mdstat") {
+                        probed.extend(parse_mdstat(&mdstat));
+                    }
+                    probed.extend(scan_btrfs(Path::new("/sys/fs/btrfs")));
+                    probed.extend(zfs.collect());
+                    *shared.write() = probed;
+                    std::thread::sleep(Duration::from_secs(REFRESH_INTERVAL_SECS));
+                }
+            })
+            .expect("failed to spawn storage probe thread");
+
+        StorageMonitor { arrays }
+    }
+
+    /// Latest probe results. Never blocks on the arrays themselves.
+    pub fn arrays(&self) -> Vec<StorageArray> {
+        self.arrays.read().clone()
+    }
+}
+
+struct ZfsProbe {
+    /// Cleared once `zpool` turns out not to be installed.
+    available: bool,
+    /// Reaper of a `zpool` that did not finish in time. No new one is
+    /// started until it has exited, so a hung pool cannot pile up processes.
+    stuck: Option<JoinHandle<()>>,
+    /// Pools from the last listing, reported while `zpool` is stuck.
+    last: Vec<StorageArray>,
+}
+
+impl ZfsProbe {
+    fn new() -> Self {
+        ZfsProbe {
+            available: true,
+            stuck: None,
+            last: Vec::new(),
+        }
+    }
+
+    fn collect(&mut self) -> Vec<StorageArray> {
+        if !self.available {
+            return Vec::new();
+        }
+        if self.stuck.as_ref().is_some_and(|h| !h.is_finished()) {
+            return self.unresponsive();
+        }
+        self.stuck = None;
+
+        let list = match run_zpool(&["list", "-Hp", "-o", "name,size,alloc,health"]) {
+            Ok(out) => out,
+            Err(ZpoolError::Io(e)) => {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    self.available = false;
+                }
+                return Vec::new();
+            }
+            Err(ZpoolError::TimedOut(reaper)) => {
+                self.stuck = Some(reaper);
+                return self.unresponsive();
+            }
+        };
+        let status = match run_zpool(&["status", "-p"]) {
+            Ok(out) => out,
+            Err(ZpoolError::TimedOut(reaper)) => {
+                self.stuck = Some(reaper);
+                String::new()
+            }
+            Err(ZpoolError::Io(_)) => String::new(),
+        };
+        self.last = parse_zpool(&list, &status);
+        self.last.clone()
+    }
+
+    fn unresponsive(&self) -> Vec<StorageArray> {
+        self.last
+            .iter()
+            .cloned()
+            .map(|mut pool| {
+                pool.health = ArrayHealth::Unknown;
+                pool.state = "zpool not responding".to_string();
+                pool.operation = None;
+                pool
+            })
+            .collect()
+    }
+}
+
+enum ZpoolError {
+    Io(std::io::Error),
+    /// The process was killed; the handle finishes once it has been reaped.
+    TimedOut(JoinHandle<()>),
+}
+
+/// Run `zpool` with a hard timeout. Output is read on its own thread while
+/// the process runs, and the process is reaped on another, so neither a
+/// large output nor a process stuck in uninterruptible sleep (which ignores
+/// SIGKILL) can block the caller.
+fn run_zpool(args: &[&str]) -> Result<String, ZpoolError> {
+    let mut child = Command::new("zpool")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(ZpoolError::Io)?;
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (tx, rx) = mpsc::channel();
+    let reader = std::thread::Builder::new()
+        .name("zpool-read".to_string())
+        .spawn(move || {
+            let mut out = String::new();
+            let _ = tx.send(stdout.read_to_string(&mut out).map(|_| out));
+        });
+    if let Err(e) = reader {
+        let _ = child.kill();
+        reap(child);
+        return Err(ZpoolError::Io(e));
+    }
+
+    match rx.recv_timeout(Duration::from_secs(ZPOOL_TIMEOUT_SECS)) {
+        Ok(out) => {
+            // Output is complete, so zpool is exiting; don't wait for it here
+            reap(child);
+            out.map_err(ZpoolError::Io)
+        }
+        Err(_) => {
+            warn!("zpool {} did not finish within {}s", args.join(" "), ZPOOL_TIMEOUT_SECS);
+            let _ = child.kill();
+            match reap(child) {
+                Some(reaper) => Err(ZpoolError::TimedOut(reaper)),
+                None => Err(ZpoolError::Io(std::io::ErrorKind::TimedOut.into())),
+            }
+        }
+    }
+}
+
+/// Wait for `child` on a thread of its own.
+fn reap(mut child: Child) -> Option<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("zpool-reap".to_string())
+        .spawn(move || {
+            let _ = child.wait();
+        })
+        .inspect_err(|e| warn!("Failed to spawn zpool reaper: {}", e))
+        .ok()
+}
+
+// ---------------------------------------------------------------------------
+// mdraid
+// ---------------------------------------------------------------------------
+
+/// Parse `/proc/mdstat`:
+///
+/// ```text
+/// md0 : active raid1 sdb1[1] sda1[0](F)
+///       976630464 blocks super 1.2 [2/1] [U_]
+///       [==>..................]  recovery = 12.6% (123456/976630464) finish=120.3min speed=10000K/sec
+/// ```
+fn parse_mdstat(content: &str) -> Vec<StorageArray> {
+    let mut arrays = Vec::new();
+    let mut lines = content.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some((name, rest)) = line.split_once(" : ") else {
+            continue;
+        };
+        let name = name.trim();
+        if !name.starts_with("md") {
+            continue;
+        }
+
+        let mut tokens = rest.split_whitespace().peekable();
+        let state = tokens.next().unwrap_or("unknown").to_string();
+        // Optional "(read-only)" / "(auto-read-only)"
+        while tokens.peek().is_some_and(|t| t.starts_with('(')) {
+            tokens.next();
+        }
+        let level = tokens
+            .peek()
+            .filter(|t| !t.contains('['))
+            .map(|t| t.to_string());
+        if level.is_some() {
+            tokens.next();
+        }
+
+        let mut devices = Vec::new();
+        for token in tokens {
+            let Some((dev, flags)) = token.split_once('[') else {
+                continue;
+            };
+            let member_state = if flags.contains("(F)") {
+                "faulty"
+            } else if flags.contains("(S)") {
+                "spare"
+            } else if flags.contains("(W)") {
+                "write-mostly"
+            } else if flags.contains("(R)") {
+                "replacement"
+            } else {
+                "active"
+            };
+            devices.push(ArrayDevice {
+                name: dev.to_string(),
+                state: member_state.to_string(),
+                read_errors: None,
+                write_errors: None,
+                checksum_errors: None,
+            });
+        }
+
+        // Detail lines are indented and run until the next blank line
+        let mut size_bytes = None;
+        let mut members: Option<(u32, u32)> = None;
+        let mut operation = None;
+        while let Some(detail) = lines.next_if(|l| l.starts_with(' ') || l.starts_with('\t')) {
+            let detail = detail.trim();
+            if let Some((blocks, _)) = detail.split_once(" blocks") {
+                size_bytes = blocks.trim().parse::<u64>().ok().map(|b| b * 1024);
+            }
+            if let Some(counts) = detail
+                .split_whitespace()
+                .find(|t| t.starts_with('[') && t.contains('/'))
+            {
+                let counts = counts.trim_matches(|c| c == '[' || c == ']');
+                if let Some((total, active)) = counts.split_once('/') {
+                    members = total.parse().ok().zip(active.parse().ok());
+                }
+            }
+            if operation.is_none() {
+                operation = parse_md_operation(detail);
+            }
+        }
+
+        let has_faulty = devices.iter().any(|d| d.state == "faulty");
+        let health = if state == "inactive" || members.is_some_and(|(_, active)| active == 0) {
+            ArrayHealth::Failed
+        } else if has_faulty || members.is_some_and(|(total, active)| active < total) {
+            ArrayHealth::Degraded
+        } else {
+            ArrayHealth::Healthy
+        };
+
+        arrays.push(StorageArray {
+            kind: "mdraid".to_string(),
+            name: name.to_string(),
+            level,
+            health,
+            state,
+            size_bytes,
+            used_bytes: None,
+            devices,
+            operation,
+        });
+    }
+
+    arrays
+}
+
+fn parse_md_operation(line: &str) -> Option<ArrayOperation> {
+    for kind in ["recovery", "resync", "reshape", "check", "repair"] {
+        // "resync=DELAYED" / "resync=PENDING" carry no progress
+        if line.starts_with(&format!("{}=", kind)) {
+            return Some(ArrayOperation {
+                kind: kind.to_string(),
+                progress_percent: None,
+                eta_secs: None,
+            });
+        }
+        let Some(idx) = line.find(&format!("{} =", kind)) else {
+            continue;
+        };
+        let after = &line[idx + kind.len() + 2..];
+        let progress_percent = after
+            .split_whitespace()
+            .next()
+            .and_then(|p| p.trim_end_matches('%').parse::<f32>().ok());
+        let eta_secs = after
+            .split_whitespace()
+            .find_map(|t| t.strip_prefix("finish="))
+            .and_then(|t| t.trim_end_matches("min").parse::<f64>().ok())
+            .map(|mins| (mins * 60.0) as u64);
+        return Some(ArrayOperation {
+            kind: kind.to_string(),
+            progress_percent,
+            eta_secs,
+        });
+    }
+    None
+}
+
+// ---------------------------------------------------------------------------
+// btrfs
+// ---------------------------------------------------------------------------
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// Walk `/sys/fs/btrfs/<uuid>/` for each mounted filesystem, reading the
+/// per-device `error_stats` counters and `missing` flags.
+fn scan_btrfs(root: &Path) -> Vec<StorageArray> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut arrays = Vec::new();
+    for entry in entries.flatten() {
+        let fs_path = entry.path();
+        // Skip "features" and anything else that is not a filesystem UUID
+        if !fs_path.join("devinfo").is_dir() {
+            continue;
+        }
+        let uuid = entry.file_name().to_string_lossy().to_string();
+        let name = read_trimmed(&fs_path.join("label"))
+            .filter(|l| !l.is_empty())
+            .unwrap_or_else(|| uuid.clone());
+
+        // Data profile, e.g. allocation/data/raid1
+        let level = fs::read_dir(fs_path.join("allocation/data"))
+            .ok()
+            .and_then(|dirs| {
+                dirs.flatten()
+                    .find(|d| d.path().is_dir())
+                    .map(|d| d.file_name().to_string_lossy().to_string())
+            });
+
+        let mut devices = Vec::new();
+        if let Ok(devinfo) = fs::read_dir(fs_path.join("devinfo")) {
+            for dev in devinfo.flatten() {
+                let dev_path = dev.path();
+                let devid = dev.file_name().to_string_lossy().to_string();
+                let missing = read_trimmed(&dev_path.join("missing")).as_deref() == Some("1");
+
+                let mut counters = std::collections::HashMap::new();
+                if let Ok(stats) = fs::read_to_string(dev_path.join("error_stats")) {
+                    for line in stats.lines() {
+                        if let Some((key, value)) = line.split_once(' ')
+                            && let Ok(v) = value.trim().parse::<u64>()
+                        {
+                            counters.insert(key.to_string(), v);
+                        }
+                    }
+                }
+
+                devices.push(ArrayDevice {
+                    name: format!("devid {}", devid),
+                    state: if missing { "missing" } else { "present" }.to_string(),
+                    read_errors: counters.get("read_errs").copied(),
+                    write_errors: counters
+                        .get("write_errs")
+                        .map(|w| w + counters.get("flush_errs").copied().unwrap_or(0)),
+                    checksum_errors: counters
+                        .get("corruption_errs")
+                        .map(|c| c + counters.get("generation_errs").copied().unwrap_or(0)),
+                });
+            }
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let any_missing = devices.iter().any(|d| d.state == "missing");
+        let health = if any_missing {
+            ArrayHealth::Degraded
+        } else if devices.iter().any(device_has_errors) {
+            ArrayHealth::Errors
+        } else {
+            ArrayHealth::Healthy
+        };
+
+        arrays.push(StorageArray {
+            kind: "btrfs".to_string(),
+            name,
+            level,
+            health,
+            state: if any_missing { "degraded" } else { "ok" }.to_string(),
+            size_bytes: None,
+            used_bytes: None,
+            devices,
+            operation: None,
+        });
+    }
+
+    arrays
+}
+
+fn device_has_errors(d: &ArrayDevice) -> bool {
+    [d.read_errors, d.write_errors, d.checksum_errors]
+        .iter()
+        .any(|e| e.unwrap_or(0) > 0)
+}
+
+// ---------------------------------------------------------------------------
+// ZFS
+// ---------------------------------------------------------------------------
+
+/// Combine `zpool list -Hp -o name,size,alloc,health` with the per-pool
+/// sections of `zpool status -p`.
+fn parse_zpool(list: &str, status: &str) -> Vec<StorageArray> {
+    let mut arrays = Vec::new();
+
+    for line in list.lines() {
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 4 {
+            continue;
+        }
+        let name = cols[0].to_string();
+        let state = cols[3].to_string();
+
+        let section = zpool_status_section(status, &name);
+        let (level, devices) = section
+            .as_deref()
+            .map(|s| parse_zpool_config(s, &name))
+            .unwrap_or_default();
+        let operation = section.as_deref().and_then(parse_zpool_scan);
+
+        let health = match state.as_str() {
+            "ONLINE" if devices.iter().any(device_has_errors) => ArrayHealth::Errors,
+            "ONLINE" => ArrayHealth::Healthy,
+            "DEGRADED" | "OFFLINE" => ArrayHealth::Degraded,
+            "FAULTED" | "UNAVAIL" | "SUSPENDED" | "REMOVED" => ArrayHealth::Failed,
+            _ => ArrayHealth::Unknown,
+        };
+
+        arrays.push(StorageArray {
+            kind: "zfs".to_string(),
+            name,
+            level,
+            health,
+            state,
+            size_bytes: cols[1].parse().ok(),
+            used_bytes: cols[2].parse().ok(),
+            devices,
+            operation,
+        });
+    }
+
+    arrays
+}
+
+/// Extract the lines belonging to one pool from `zpool status` output.
+fn zpool_status_section(status: &str, pool: &str) -> Option<String> {
+    let mut section: Option<Vec<&str>> = None;
+    for line in status.lines() {
+        if let Some(name) = line.trim().strip_prefix("pool:") {
+            if section.is_some() {
+                break;
+            }
+            if name.trim() == pool {
+                section = Some(Vec::new());
+            }
+            continue;
+        }
+        if let Some(lines) = section.as_mut() {
+            lines.push(line);
+        }
+    }
+    section.map(|lines| lines.join("\n"))
+}
+
+/// Parse the `config:` table into the top-level vdev layout and leaf devices.
+fn parse_zpool_config(section: &str, pool: &str) -> (Option<String>, Vec<ArrayDevice>) {
+    // (indent, name, state, read, write, cksum)
+    let mut rows = Vec::new();
+    let mut in_config = false;
+    for line in section.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("config:") {
+            in_config = true;
+            continue;
+        }
+        if !in_config || trimmed.is_empty() || trimmed.starts_with("NAME") {
+            continue;
+        }
+        if trimmed.starts_with("errors:") {
+            break;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let cols: Vec<&str> = trimmed.split_whitespace().collect();
+        rows.push((
+            indent,
+            cols[0].to_string(),
+            cols.get(1).map(|s| s.to_string()),
+            cols.get(2).and_then(|s| s.parse::<u64>().ok()),
+            cols.get(3).and_then(|s| s.parse::<u64>().ok()),
+            cols.get(4).and_then(|s| s.parse::<u64>().ok()),
+        ));
+    }
+
+    let mut level = None;
+    let mut devices = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let (indent, name, state, read, write, cksum) = row;
+        if name == pool {
+            continue;
+        }
+        let is_leaf = rows.get(i + 1).is_none_or(|next| next.0 <= *indent);
+        if !is_leaf {
+            // mirror-0, raidz2-1, ...
+            if level.is_none() && state.is_some() {
+                level = Some(
+                    name.rsplit_once('-')
+                        .map_or(name.as_str(), |(kind, _)| kind)
+                        .to_string(),
+                );
+            }
+            continue;
+        }
+        // "logs", "cache" and "spares" headers carry no state column
+        let Some(state) = state else {
+            continue;
+        };
+        devices.push(ArrayDevice {
+            name: name.clone(),
+            state: state.clone(),
+            read_errors: *read,
+            write_errors: *write,
+            checksum_errors: *cksum,
+        });
+    }
+
+    // A pool of plain disks has no grouping vdev
+    if level.is_none() && !devices.is_empty() {
+        level = Some("stripe".to_string());
+    }
+    (level, devices)
+}
+
+/// Parse the `scan:` block for a scrub or resilver in progress.
+fn parse_zpool_scan(section: &str) -> Option<ArrayOperation> {
+    let mut lines = section.lines().map(str::trim);
+    let scan = lines.find_map(|l| l.strip_prefix("scan:"))?.trim();
+    let kind = if scan.starts_with("scrub in progress") {
+        "scrub"
+    } else if scan.starts_with("resilver in progress") {
+        "resilver"
+    } else {
+        return None;
+    };
+
+    let mut progress_percent = None;
+    let mut eta_secs = None;
+    for line in lines.take_while(|l| !l.starts_with("config:")) {
+        // "200G resilvered, 20.00% done, 01:10:00 to go"
+        for part in line.split(',') {
+            let part = part.trim();
+            if let Some(pct) = part.strip_suffix("% done") {
+                progress_percent = pct.trim().parse().ok();
+            } else if let Some(eta) = part.strip_suffix(" to go") {
+                eta_secs = parse_zfs_eta(eta);
+            }
+        }
+    }
+
+    Some(ArrayOperation {
+        kind: kind.to_string(),
+        progress_percent,
+        eta_secs,
+    })
+}
+
+/// Parse "01:10:00" or "2 days 01:10:00".
+fn parse_zfs_eta(eta: &str) -> Option<u64> {
+    let mut days = 0;
+    let mut clock = eta;
+    if let Some((d, rest)) = eta.split_once(" days ") {
+        days = d.trim().parse().ok()?;
+        clock = rest;
+    }
+    let parts: Vec<u64> = clock
+        .trim()
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let [h, m, s] = parts[..] else {
+        return None;
+    };
+    Some(days * 86400 + h * 3600 + m * 60 + s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MDSTAT: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md1 : active raid5 sdd1[3] sdc1[1] sdb1[0]
+      1953260544 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/3] [UUU]
+      bitmap: 0/8 pages [0KB], 65536KB chunk
+
+md0 : active raid1 sdb2[1](F) sda2[0]
+      976630464 blocks super 1.2 [2/1] [U_]
+      [==>..................]  recovery = 12.6% (123456/976630464) finish=120.3min speed=10000K/sec
+
+md2 : inactive sde1[0](S)
+      976630464 blocks super 1.2
+
+unused devices: <none>
+";
+
+    #[test]
+    fn mdstat_arrays() {
+        let arrays = parse_mdstat(MDSTAT);
+        assert_eq!(arrays.len(), 3);
+
+        let md1 = &arrays[0];
+        assert_eq!(md1.name, "md1");
+        assert_eq!(md1.level.as_deref(), Some("raid5"));
+        assert_eq!(md1.health, ArrayHealth::Healthy);
+        assert_eq!(md1.size_bytes, Some(1953260544 * 1024));
+        assert_eq!(md1.devices.len(), 3);
+        assert!(md1.operation.is_none());
+
+        let md0 = &arrays[1];
+        assert_eq!(md0.health, ArrayHealth::Degraded);
+        assert_eq!(md0.devices[0].name, "sdb2");
+        assert_eq!(md0.devices[0].state, "faulty");
+        let op = md0.operation.as_ref().unwrap();
+        assert_eq!(op.kind, "recovery");
+        assert_eq!(op.progress_percent, Some(12.6));
+        assert_eq!(op.eta_secs, Some(7218));
+
+        let md2 = &arrays[2];
+        assert_eq!(md2.state, "inactive");
+        assert_eq!(md2.level, None);
+        assert_eq!(md2.health, ArrayHealth::Failed);
+        assert_eq!(md2.devices[0].state, "spare");
+    }
+
+    #[test]
+    fn mdstat_delayed_resync() {
+        assert_eq!(parse_md_operation("resync=DELAYED").unwrap().kind, "resync");
+        assert!(parse_md_operation("bitmap: 0/8 pages [0KB], 65536KB chunk").is_none());
+    }
+
+    const ZPOOL_LIST: &str = "tank\t3985729650688\t1099511627776\tDEGRADED\nbackup\t1000204886016\t0\tONLINE\n";
+
+    const ZPOOL_STATUS: &str = "\
+  pool: backup
+ state: ONLINE
+config:
+
+\tNAME        STATE     READ WRITE CKSUM
+\tbackup      ONLINE       0     0     0
+\t  sdc       ONLINE       0     0     3
+
+errors: No known data errors
+
+  pool: tank
+ state: DEGRADED
+  scan: resilver in progress since Sun Oct 18 10:00:00 2026
+\t1.00T scanned at 100M/s, 500G issued at 50M/s, 2T total
+\t200G resilvered, 20.00% done, 1 days 01:10:00 to go
+config:
+
+\tNAME        STATE     READ WRITE CKSUM
+\ttank        DEGRADED     0     0     0
+\t  mirror-0  DEGRADED     0     0     0
+\t    sda     ONLINE       0     0     0
+\t    sdb     FAULTED      5    12     0
+\tlogs
+\t  nvme0n1   ONLINE       0     0     0
+
+errors: No known data errors
+";
+
+    #[test]
+    fn zpool_pools() {
+        let pools = parse_zpool(ZPOOL_LIST, ZPOOL_STATUS);
+        assert_eq!(pools.len(), 2);
+
+        let tank = &pools[0];
+        assert_eq!(tank.name, "tank");
+        assert_eq!(tank.health, ArrayHealth::Degraded);
+        assert_eq!(tank.level.as_deref(), Some("mirror"));
+        assert_eq!(tank.size_bytes, Some(3985729650688));
+        assert_eq!(tank.used_bytes, Some(1099511627776));
+        let names: Vec<&str> = tank.devices.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["sda", "sdb", "nvme0n1"]);
+        assert_eq!(tank.devices[1].state, "FAULTED");
+        assert_eq!(tank.devices[1].write_errors, Some(12));
+        let op = tank.operation.as_ref().unwrap();
+        assert_eq!(op.kind, "resilver");
+        assert_eq!(op.progress_percent, Some(20.0));
+        assert_eq!(op.eta_secs, Some(86400 + 3600 + 600));
+
+        let backup = &pools[1];
+        assert_eq!(backup.level.as_deref(), Some("stripe"));
+        assert_eq!(backup.health, ArrayHealth::Errors);
+        assert!(backup.operation.is_none());
+    }
+
+    #[test]
+    fn zpool_without_status() {
+        let pools = parse_zpool(ZPOOL_LIST, "");
+        assert_eq!(pools[1].health, ArrayHealth::Healthy);
+        assert!(pools[1].devices.is_empty());
+        assert_eq!(pools[1].level, None);
+    }
+
+    #[test]
+    fn zfs_eta() {
+        assert_eq!(parse_zfs_eta("01:10:00"), Some(4200));
+        assert_eq!(parse_zfs_eta("2 days 00:00:01"), Some(172801));
+        assert_eq!(parse_zfs_eta("no estimate"), None);
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn btrfs_sysfs() {
+        let root = std::env::temp_dir().join(format!("pi-dash-btrfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let pool = root.join("0b6d9b6e-aaaa-4bbb-8ccc-123456789abc");
+        write(&pool.join("label"), "data\n");
+        fs::create_dir_all(pool.join("allocation/data/raid1")).unwrap();
+        write(
+            &pool.join("devinfo/1/error_stats"),
+            "write_errs 0\nread_errs 0\nflush_errs 0\ncorruption_errs 0\ngeneration_errs 0\n",
+        );
+        write(&pool.join("devinfo/1/missing"), "0\n");
+        write(
+            &pool.join("devinfo/2/error_stats"),
+            "write_errs 1\nread_errs 2\nflush_errs 1\ncorruption_errs 3\ngeneration_errs 0\n",
+        );
+        write(&pool.join("devinfo/2/missing"), "0\n");
+
+        let single = root.join("11111111-2222-3333-4444-555555555555");
+        write(&single.join("label"), "\n");
+        write(&single.join("devinfo/1/missing"), "1\n");
+
+        fs::create_dir_all(root.join("features")).unwrap();
+
+        let mut arrays = scan_btrfs(&root);
+        arrays.sort_by(|a, b| a.name.cmp(&b.name));
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(arrays.len(), 2);
+        let single = &arrays[0];
+        assert_eq!(single.name, "11111111-2222-3333-4444-555555555555");
+        assert_eq!(single.health, ArrayHealth::Degraded);
+        assert_eq!(single.devices[0].state, "missing");
+
+        let data = &arrays[1];
+        assert_eq!(data.name, "data");
+        assert_eq!(data.level.as_deref(), Some("raid1"));
+        assert_eq!(data.health, ArrayHealth::Errors);
+        let dev2 = &data.devices[1];
+        assert_eq!(dev2.name, "devid 2");
+        assert_eq!(dev2.read_errors, Some(2));
+        assert_eq!(dev2.write_errors, Some(2));
+        assert_eq!(dev2.checksum_errors, Some(3));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::annotations::AnnotationStore;
+use crate::auth::{self, AuthConfig};
+use crate::clock;
+use crate::config;
+use crate::encoding;
+use crate::history::HistoryStore;
+use crate::models::{Reboot, ShutdownKind, UptimeQuery, UptimeResponse};
+use crate::writes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+const BOOTS_FILE: &str = "boots.json";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+const MAX_BOOTS: usize = 1000;
+const CLOCK_WAIT_SECS: u64 = 10; // how often to check whether history trusts the clock yet
+const LAST_SEEN_INTERVAL_SECS: u64 = 60;
+const LOW_WEAR_LAST_SEEN_INTERVAL_SECS: u64 = 600;
+
+/// One boot of the host, as far as pi-dash saw it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Boot {
+    boot_id: String,
+    booted: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    /// How the boot ended; `None` for the current one.
+    shutdown: Option<ShutdownKind>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct UptimeStoreInner {
+    /// Oldest first.
+    boots: VecDeque<Boot>,
+    /// Whether the last run of pi-dash stopped normally.
+    clean_stop: bool,
+    /// Whether the last boot is this one. Until then the clock is not
+    /// trusted and nothing is recorded.
+    #[serde(skip)]
+    current: bool,
+    /// Id of the running boot, if the kernel exposes one.
+    #[serde(skip)]
+    boot_id: Option<String>,
+}
+
+/// Boots of the host across restarts of pi-dash, kept in `/data/boots.json`.
+/// The one record of reboots: history gaps and annotations are told about
+/// them from here.
+#[derive(Clone)]
+pub struct UptimeStore {
+    inner: Arc<RwLock<UptimeStoreInner>>,
+}
+
+impl UptimeStore {
+    pub fn new() -> Self {
+        let mut inner = Self::load_from_disk().unwrap_or_default();
+        inner.boot_id = std::fs::read_to_string(BOOT_ID_PATH)
+            .ok()
+            .map(|id| id.trim().to_string());
+        if inner.boot_id.is_none() {
+            warn!("Cannot read {}; reboots will not be tracked", BOOT_ID_PATH);
+        }
+        UptimeStore {
+            inner: Arc::new(RwLock::new(inner)),
+        }
+    }
+
+    /// Whether the host rebooted since pi-dash last ran. Known from the boot
+    /// id alone, so it can be asked before the clock is trusted.
+    pub fn rebooted(&self) -> bool {
+        let inner = self.inner.read();
+        match (&inner.boot_id, inner.boots.back()) {
+            (Some(id), Some(last)) => &last.boot_id != id,
+            _ => false,
+        }
+    }
+
+    fn load_from_disk() -> Option<UptimeStoreInner> {
+        let path = config::data_path(BOOTS_FILE);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(inner) => {
+                println!("Loaded boot log from {}", path.display());
+                Some(inner)
+            }
+            Err(e) => {
+                eprintln!("Failed to parse boot log {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn write(inner: &UptimeStoreInner) {
+        let path = config::data_path(BOOTS_FILE);
+        match serde_json::to_vec(inner) {
+            Ok(json) => {
+                if let Err(e) = writes::replace(&path, &json) {
+                    eprintln!("Failed to write boot log to {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize boot log: {}", e),
+        }
+    }
+
+    /// Note that the current boot is still up at `now`.
+    fn touch(inner: &mut UptimeStoreInner, now: DateTime<Utc>) -> bool {
+        if !inner.current {
+            return false;
+        }
+        match inner.boots.back_mut() {
+            Some(boot) => {
+                boot.last_seen = boot.last_seen.max(now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record the last-seen time and mark the stop as clean, so a reboot
+    /// after it is not taken for a crash.
+    pub fn shutdown(&self) {
+        let mut inner = self.inner.write();
+        if Self::touch(&mut inner, Utc::now()) {
+            inner.clean_stop = true;
+            Self::write(&inner);
+        }
+    }
+
+    /// Once history trusts the clock, record this boot (closing the previous
+    /// one when the boot id changed) and annotate the start. Then keep the
+    /// last-seen time fresh, so a crash is placed to within the interval.
+    pub fn spawn(&self, history: HistoryStore, annotations: AnnotationStore, started: Instant) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(CLOCK_WAIT_SECS));
+            loop {
+                interval.tick().await;
+                if !history.holding() {
+                    break;
+                }
+            }
+            let now = Utc::now();
+            let reboot = store.record_boot(now);
+            annotations.started(now - started.elapsed(), reboot.as_ref());
+
+            let secs = if history.low_wear() {
+                LOW_WEAR_LAST_SEEN_INTERVAL_SECS
+            } else {
+                LAST_SEEN_INTERVAL_SECS
+            };
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let mut inner = store.inner.write();
+                if Self::touch(&mut inner, Utc::now()) {
+                    Self::write(&inner);
+                }
+            }
+        });
+    }
+
+    /// Returns the reboot since the last run, if there was one.
+    fn record_boot(&self, now: DateTime<Utc>) -> Option<Reboot> {
+        let mut inner = self.inner.write();
+        let boot_id = inner.boot_id.clone()?;
+        let booted = now - clock::uptime();
+
+        let clean_stop = std::mem::take(&mut inner.clean_stop);
+        let mut reboot = None;
+        match inner.boots.back_mut() {
+            Some(last) if last.boot_id == boot_id => {}
+            previous => {
+                if let Some(last) = previous {
+                    let shutdown = if clean_stop {
+                        ShutdownKind::Clean
+                    } else {
+                        ShutdownKind::Crash
+                    };
+                    last.shutdown = Some(shutdown);
+                    let last_seen = last.last_seen.min(booted);
+                    info!(
+                        "Host rebooted at {} (last seen {}, {:?} shutdown)",
+                        booted, last_seen, shutdown
+                    );
+                    reboot = Some(Reboot {
+                        last_seen,
+                        booted,
+                        downtime_secs: (booted - last_seen).num_seconds(),
+                        shutdown,
+                        boot_id: boot_id.clone(),
+                    });
+                }
+                inner.boots.push_back(Boot {
+                    boot_id,
+                    booted,
+                    last_seen: now,
+                    shutdown: None,
+                });
+                if inner.boots.len() > MAX_BOOTS {
+                    inner.boots.pop_front();
+                }
+            }
+        }
+        inner.current = true;
+        Self::touch(&mut inner, now);
+        Self::write(&inner);
+        reboot
+    }
+
+    /// Uptime over the last `days` days and the reboots within them. `None`
+    /// until the current boot has been recorded.
+    pub fn summary(&self, days: u32) -> Option<UptimeResponse> {
+        let inner = self.inner.read();
+        if !inner.current {
+            return None;
+        }
+        let to = Utc::now();
+        let from = to - TimeDelta::days(days as i64);
+        let current = inner.boots.back()?;
+        let tracked_since = inner.boots.front()?.booted.max(from);
+
+        let mut up = TimeDelta::zero();
+        for boot in &inner.boots {
+            let end = if boot.shutdown.is_none() { to } else { boot.last_seen };
+            let overlap = end.min(to) - boot.booted.max(tracked_since);
+            if overlap > TimeDelta::zero() {
+                up += overlap;
+            }
+        }
+        let tracked = (to - tracked_since).num_milliseconds();
+        let uptime_percent = if tracked > 0 {
+            (up.num_milliseconds() as f64 / tracked as f64 * 100.0).min(100.0)
+        } else {
+            100.0
+        };
+
+        let reboots = inner
+            .boots
+            .iter()
+            .zip(inner.boots.iter().skip(1))
+            .rev()
+            .take_while(|(_, next)| next.booted >= from)
+            .filter_map(|(prev, next)| {
+                let last_seen = prev.last_seen.min(next.booted);
+                Some(Reboot {
+                    last_seen,
+                    booted: next.booted,
+                    downtime_secs: (next.booted - last_seen).num_seconds(),
+                    shutdown: prev.shutdown?,
+                    boot_id: next.boot_id.clone(),
+                })
+            })
+            .collect();
+
+        Some(UptimeResponse {
+            boot_id: current.boot_id.clone(),
+            booted: current.booted,
+            uptime_secs: (to - current.booted).num_seconds(),
+            from,
+            to,
+            tracked_since,
+            uptime_percent,
+            reboots,
+        })
+    }
+}
+
+pub async fn api_uptime(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    uptime: web::Data<UptimeStore>,
+    query: web::Query<UptimeQuery>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 730);
+    match uptime.summary(days) {
+        Some(summary) => encoding::respond(&req, &summary),
+        None => HttpResponse::ServiceUnavailable()
+            .json(serde_json::json!({"error": "Current boot not recorded yet"})),
+    }
+}
@@ -0,0 +1,421 @@
+use log::warn;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"PDW1";
+const HEADER_LEN: usize = 8; // u32 payload length + u32 CRC32 of the payload
+const MAX_RECORD_BYTES: usize = 256 * 1024 * 1024; // larger lengths can only come from a torn header
+const COMPRESSION_LEVEL: i32 = 3;
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Append-only log of zstd-compressed records, split into numbered segment
+/// files (`00000001.seg`, `00000002.seg`, ...). Each record is framed as
+///
+/// ```text
+/// [len: u32 LE][crc32: u32 LE][zstd payload: len bytes]
+/// ```
+///
+/// and fsynced when appended, so a crash can lose at most the record being
+/// written. Reading stops at the first record that is short or fails its
+/// checksum, and the active segment is truncated back to that point.
+///
+/// New segments are written to a temporary file and renamed into place, so a
+/// segment is either complete or absent; older segments are then deleted.
+pub struct Wal {
+    dir: PathBuf,
+    seq: u64,
+    file: File,
+    len: u64,
+}
+
+impl Wal {
+    /// Open or create the log in `dir` and read back every intact record,
+    /// oldest first.
+    pub fn open(dir: &Path) -> io::Result<(Wal, Vec<Vec<u8>>)> {
+        fs::create_dir_all(dir)?;
+        // Leftovers of a rotation that was cut short
+        for entry in fs::read_dir(dir)?.flatten() {
+            if entry.file_name().to_string_lossy().ends_with(".seg.tmp") {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        let seqs = list_segments(dir)?;
+        let (records, mut active_len) = read_segments(dir, &seqs)?;
+
+        let wal = match seqs.last() {
+            Some(&seq) => {
+                let path = segment_path(dir, seq);
+                let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+                if active_len < MAGIC.len() {
+                    // Not even a header survived; start the segment over
+                    file.set_len(0)?;
+                    file.write_all(MAGIC)?;
+                    active_len = MAGIC.len();
+                } else {
+                    file.set_len(active_len as u64)?;
+                }
+                file.sync_all()?;
+                let file = OpenOptions::new().append(true).open(&path)?;
+                Wal {
+                    dir: dir.to_path_buf(),
+                    seq,
+                    file,
+                    len: active_len as u64,
+                }
+            }
+            None => {
+                let path = segment_path(dir, 1);
+                write_new_segment(dir, &path, None)?;
+                Wal {
+                    dir: dir.to_path_buf(),
+                    seq: 1,
+                    file: OpenOptions::new().append(true).open(&path)?,
+                    len: MAGIC.len() as u64,
+                }
+            }
+        };
+
+        Ok((wal, records))
+    }
+
+    /// Read every intact record in `dir` without changing anything on disk,
+    /// for looking at a log another process may be writing.
+    pub fn read(dir: &Path) -> io::Result<Vec<Vec<u8>>> {
+        let seqs = list_segments(dir)?;
+        read_segments(dir, &seqs).map(|(records, _)| records)
+    }
+
+    /// Size of the segment currently being appended to.
+    pub fn active_bytes(&self) -> u64 {
+        self.len
+    }
+
+    /// Compress, frame and durably append one record to the active segment.
+    /// Returns the number of bytes written.
+    pub fn append(&mut self, payload: &[u8]) -> io::Result<u64> {
+        let frame = encode_record(payload)?;
+        let written = self
+            .file
+            .write_all(&frame)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            // Cut off whatever part of the frame made it to disk so later
+            // records do not end up behind garbage
+            let _ = self.file.set_len(self.len);
+            return Err(e);
+        }
+        self.len += frame.len() as u64;
+        Ok(frame.len() as u64)
+    }
+
+    /// Start a new segment whose first record is `payload` (typically a full
+    /// snapshot) and delete every older segment. Returns the size of the new
+    /// segment.
+    pub fn rotate(&mut self, payload: &[u8]) -> io::Result<u64> {
+        let seq = self.seq + 1;
+        let path = segment_path(&self.dir, seq);
+        let len = write_new_segment(&self.dir, &path, Some(payload))?;
+
+        self.file = OpenOptions::new().append(true).open(&path)?;
+        self.seq = seq;
+        self.len = len;
+
+        for old in list_segments(&self.dir)?.into_iter().filter(|&s| s < seq) {
+            let old_path = segment_path(&self.dir, old);
+            if let Err(e) = fs::remove_file(&old_path) {
+                warn!("Failed to remove old segment {}: {}", old_path.display(), e);
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// Frame `records` as one segment image, for moving a log around as a
+/// single file.
+pub fn pack(records: &[Vec<u8>]) -> io::Result<Vec<u8>> {
+    let mut data = MAGIC.to_vec();
+    for payload in records {
+        data.extend(encode_record(payload)?);
+    }
+    Ok(data)
+}
+
+/// Records of a segment image; `None` if `data` is not one. Records past
+/// `limit` decompressed bytes in total are left out.
+pub fn unpack(data: &[u8], limit: u64) -> Option<Vec<Vec<u8>>> {
+    data.starts_with(MAGIC).then(|| read_records(data, limit).0)
+}
+
+/// Reader that fails once more than its limit has been read, so a small
+/// compressed input cannot expand without bound.
+pub struct Limited<R> {
+    inner: R,
+    left: u64,
+}
+
+impl<R: Read> Limited<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        Limited { inner, left: limit }
+    }
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Ask for one byte past the limit so reaching it is not mistaken for
+        // running over it
+        let max = buf.len().min(self.left.saturating_add(1).try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..max])?;
+        if n as u64 > self.left {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "decompressed data is larger than allowed",
+            ));
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Decompress zstd `data`, failing if it expands past `limit` bytes.
+pub fn decompress(data: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    Limited::new(zstd::Decoder::new(data)?, limit).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Open `path` for reading, decompressing on the fly if it is zstd. At most
+/// `limit` bytes can be read from it.
+pub fn open_decoded(path: &Path, limit: u64) -> io::Result<Limited<Box<dyn Read>>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 4];
+    let compressed = file.read_exact(&mut magic).is_ok() && &magic == ZSTD_MAGIC;
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = if compressed {
+        Box::new(zstd::Decoder::new(file)?)
+    } else {
+        Box::new(io::BufReader::new(file))
+    };
+    Ok(Limited::new(reader, limit))
+}
+
+/// Whether the file at `path` is a segment image.
+pub fn is_image(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; 4];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:08}.seg", seq))
+}
+
+fn list_segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut seqs: Vec<u64> = fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name();
+            name.to_str()?.strip_suffix(".seg")?.parse().ok()
+        })
+        .collect();
+    seqs.sort_unstable();
+    Ok(seqs)
+}
+
+/// Read the records of every segment in `seqs`. Also returns the intact
+/// length of the last one.
+fn read_segments(dir: &Path, seqs: &[u64]) -> io::Result<(Vec<Vec<u8>>, usize)> {
+    let mut records = Vec::new();
+    let mut active_len = 0;
+    for &seq in seqs {
+        let path = segment_path(dir, seq);
+        let data = fs::read(&path)?;
+        let (segment_records, good_len) = read_records(&data, u64::MAX);
+        if good_len < data.len() {
+            warn!(
+                "Discarding {} bytes of torn or corrupt data at the end of {}",
+                data.len() - good_len,
+                path.display()
+            );
+        }
+        active_len = good_len;
+        records.extend(segment_records);
+    }
+    Ok((records, active_len))
+}
+
+/// Write a segment via a temporary file so it appears complete or not at all.
+fn write_new_segment(dir: &Path, path: &Path, first: Option<&[u8]>) -> io::Result<u64> {
+    let mut data = MAGIC.to_vec();
+    if let Some(payload) = first {
+        data.extend(encode_record(payload)?);
+    }
+
+    let tmp = path.with_extension("seg.tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    // Persist the rename itself
+    File::open(dir)?.sync_all()?;
+    Ok(data.len() as u64)
+}
+
+fn encode_record(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let compressed = zstd::encode_all(payload, COMPRESSION_LEVEL)?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + compressed.len());
+    frame.extend((compressed.len() as u32).to_le_bytes());
+    frame.extend(crc32fast::hash(&compressed).to_le_bytes());
+    frame.extend(compressed);
+    Ok(frame)
+}
+
+/// Decode records up to the first damaged one, or until `limit` bytes have
+/// been decompressed. Returns the payloads and the length of the prefix read,
+/// header included.
+fn read_records(data: &[u8], limit: u64) -> (Vec<Vec<u8>>, usize) {
+    if data.get(..MAGIC.len()) != Some(MAGIC) {
+        return (Vec::new(), 0);
+    }
+
+    let mut records = Vec::new();
+    let mut left = limit;
+    let mut offset = MAGIC.len();
+    while let Some(header) = data.get(offset..offset + HEADER_LEN) {
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if len > MAX_RECORD_BYTES {
+            break;
+        }
+        let start = offset + HEADER_LEN;
+        let Some(compressed) = data.get(start..start + len) else {
+            break;
+        };
+        if crc32fast::hash(compressed) != crc {
+            break;
+        }
+        let Ok(payload) = decompress(compressed, (MAX_RECORD_BYTES as u64).min(left)) else {
+            break;
+        };
+        left -= payload.len() as u64;
+        records.push(payload);
+        offset = start + len;
+    }
+    (records, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payloads(names: &[&str]) -> Vec<Vec<u8>> {
+        names.iter().map(|n| n.repeat(50).into_bytes()).collect()
+    }
+
+    /// A log in a fresh directory holding `names`, closed again.
+    fn written(names: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut wal, records) = Wal::open(dir.path()).unwrap();
+        assert!(records.is_empty());
+        for payload in payloads(names) {
+            wal.append(&payload).unwrap();
+        }
+        dir
+    }
+
+    /// Reopen the log, check it holds `before`, append `added` and check
+    /// that a further reopen sees it right after them.
+    fn assert_recovers(dir: &Path, before: &[&str], added: &str) {
+        let (mut wal, records) = Wal::open(dir).unwrap();
+        assert_eq!(records, payloads(before));
+        let on_disk = fs::metadata(segment_path(dir, wal.seq)).unwrap().len();
+        assert_eq!(on_disk, wal.active_bytes());
+        wal.append(&payloads(&[added])[0]).unwrap();
+        drop(wal);
+
+        let (_, records) = Wal::open(dir).unwrap();
+        let mut expected = before.to_vec();
+        expected.push(added);
+        assert_eq!(records, payloads(&expected));
+    }
+
+    #[test]
+    fn reopen_reads_every_record() {
+        let dir = written(&["a", "b"]);
+        assert_recovers(dir.path(), &["a", "b"], "c");
+        assert_eq!(Wal::read(dir.path()).unwrap(), payloads(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn torn_payload_is_cut_off() {
+        let dir = written(&["a", "b", "c"]);
+        let path = segment_path(dir.path(), 1);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+        assert_recovers(dir.path(), &["a", "b"], "d");
+    }
+
+    #[test]
+    fn torn_header_is_cut_off() {
+        let dir = written(&["a"]);
+        let path = segment_path(dir.path(), 1);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[7, 0, 0]).unwrap();
+        assert_recovers(dir.path(), &["a"], "b");
+    }
+
+    #[test]
+    fn corrupt_checksum_ends_the_log() {
+        let dir = written(&["a", "b", "c"]);
+        let path = segment_path(dir.path(), 1);
+        let mut data = fs::read(&path).unwrap();
+        let c_frame = encode_record(&payloads(&["c"])[0]).unwrap().len();
+        let crc = data.len() - c_frame + 4;
+        data[crc] ^= 0xFF;
+        fs::write(&path, &data).unwrap();
+        assert_recovers(dir.path(), &["a", "b"], "d");
+    }
+
+    #[test]
+    fn leftover_rotation_is_removed() {
+        let dir = written(&["a"]);
+        let tmp = segment_path(dir.path(), 2).with_extension("seg.tmp");
+        fs::write(&tmp, b"PDW1 half a segment").unwrap();
+        assert_recovers(dir.path(), &["a"], "b");
+        assert!(!tmp.exists());
+        assert_eq!(list_segments(dir.path()).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn rotate_then_reopen() {
+        let dir = written(&["a", "b"]);
+        {
+            let (mut wal, _) = Wal::open(dir.path()).unwrap();
+            let len = wal.rotate(&payloads(&["snapshot"])[0]).unwrap();
+            assert_eq!(len, wal.active_bytes());
+            wal.append(&payloads(&["c"])[0]).unwrap();
+        }
+        assert_eq!(list_segments(dir.path()).unwrap(), vec![2]);
+        assert_recovers(dir.path(), &["snapshot", "c"], "d");
+    }
+
+    #[test]
+    fn empty_segment_is_restarted() {
+        let dir = written(&[]);
+        fs::write(segment_path(dir.path(), 1), b"PD").unwrap();
+        assert_recovers(dir.path(), &[], "a");
+    }
+
+    #[test]
+    fn images_round_trip() {
+        let records = payloads(&["a", "b"]);
+        let image = pack(&records).unwrap();
+        assert_eq!(unpack(&image, u64::MAX), Some(records));
+        assert_eq!(unpack(b"not an image", u64::MAX), None);
+        // Records past the limit are left out
+        assert_eq!(unpack(&image, 60), Some(payloads(&["a"])));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::encoding;
+use crate::history::HistoryStore;
+use crate::models::{DailyWrites, DataWrites};
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const WRITES_FILE: &str = "writes.json";
+const RETENTION_DAYS: i64 = 90;
+const PROC_IO_PATH: &str = "/proc/self/io";
+
+/// Bytes written to the data directory per UTC day. Global so every module
+/// that persists state can report into it without threading a handle around.
+///
+/// This is the size of what pi-dash hands to the filesystem: file contents,
+/// log records with their framing, and database pages. Filesystem metadata,
+/// journaling and rounding up to whole blocks are not included, so the card
+/// sees somewhat more; [`process_write_bytes`] has the kernel's figure.
+static DAILY: Mutex<BTreeMap<NaiveDate, u64>> = Mutex::new(BTreeMap::new());
+
+/// Count `bytes` written to the data directory.
+pub fn record(bytes: u64) {
+    let today = Utc::now().date_naive();
+    let mut daily = DAILY.lock();
+    *daily.entry(today).or_default() += bytes;
+    let cutoff = today - TimeDelta::days(RETENTION_DAYS);
+    daily.retain(|date, _| *date >= cutoff);
+}
+
+/// Replace the file at `path` with `data` and count the bytes. The data goes
+/// to a temporary file that is synced before it is renamed over `path`, and
+/// the directory is synced after, so a crash or power cut leaves either the
+/// old contents or the new ones, never a torn or empty file.
+pub fn replace(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    File::open(dir)?.sync_all()?;
+    record(data.len() as u64);
+    Ok(())
+}
+
+pub fn load_from_disk() {
+    let path = config::data_path(WRITES_FILE);
+    let Ok(content) = std::fs::read(&path) else {
+        return;
+    };
+    match serde_json::from_slice::<BTreeMap<NaiveDate, u64>>(&content) {
+        Ok(saved) => {
+            let mut daily = DAILY.lock();
+            for (date, bytes) in saved {
+                *daily.entry(date).or_default() += bytes;
+            }
+        }
+        Err(e) => eprintln!("Failed to parse {}: {}", path.display(), e),
+    }
+}
+
+/// Bytes this process has caused to be sent to storage since it started,
+/// as counted by the kernel. Covers everything pi-dash writes, including
+/// filesystem overhead, but not what other processes write.
+pub fn process_write_bytes() -> Option<u64> {
+    let content = std::fs::read_to_string(PROC_IO_PATH).ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("write_bytes:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Saved with [`replace`], so a power cut keeps the previous counters.
+pub fn save_to_disk() {
+    let path = config::data_path(WRITES_FILE);
+    let json = match serde_json::to_vec(&*DAILY.lock()) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Failed to serialize write counters: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = replace(&path, &json) {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+pub async fn api_writes(
+    req: HttpRequest,
+    auth: web::Data<AuthConfig>,
+    history: web::Data<HistoryStore>,
+) -> HttpResponse {
+    if let Some(token) = auth::extract_token(&req) {
+        if !auth.validate_access_token(&token) {
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+        }
+    } else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+
+    let days: Vec<DailyWrites> = DAILY
+        .lock()
+        .iter()
+        .map(|(&date, &bytes)| DailyWrites { date, bytes })
+        .collect();
+    let today = Utc::now().date_naive();
+
+    encoding::respond(&req, &DataWrites {
+        low_wear: history.low_wear(),
+        flush_interval_secs: history.flush_interval_secs(),
+        today_bytes: days.iter().find(|d| d.date == today).map_or(0, |d| d.bytes),
+        days,
+        process_write_bytes: process_write_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_swaps_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("checks.json.zst");
+        replace(&path, b"first").unwrap();
+        replace(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["checks.json.zst"]);
+    }
+}
@@ -1,11 +1,83 @@
 use crate::auth::{AuthConfig, extract_token};
+use crate::encoding::{Dictionary, Encoding, Packed};
+use crate::models::SystemStats;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::web::Bytes;
 use actix_web::{HttpRequest, HttpResponse, web};
 use actix_ws::Message;
 use futures_util::StreamExt;
 use log::{info, warn};
+use parking_lot::Mutex;
+use std::sync::{Arc, OnceLock};
 use tokio::sync::broadcast;
 
-pub type WsBroadcast = broadcast::Sender<String>;
+pub type WsBroadcast = broadcast::Sender<Arc<Frame>>;
+
+/// One stats update. Each encoding is built the first time a client that
+/// negotiated it takes the frame, then shared with every other such client;
+/// nothing is encoded for formats no client speaks.
+pub struct Frame {
+    stats: SystemStats,
+    dictionary: Arc<Mutex<Dictionary>>,
+    json: OnceLock<Option<String>>,
+    /// Stats with keys packed, and the dictionary names as of packing.
+    packed: OnceLock<Option<(Packed, Arc<Vec<String>>)>>,
+    msgpack: OnceLock<Option<Bytes>>,
+    cbor: OnceLock<Option<Bytes>>,
+}
+
+impl Frame {
+    pub fn new(stats: SystemStats, dictionary: Arc<Mutex<Dictionary>>) -> Self {
+        Frame {
+            stats,
+            dictionary,
+            json: OnceLock::new(),
+            packed: OnceLock::new(),
+            msgpack: OnceLock::new(),
+            cbor: OnceLock::new(),
+        }
+    }
+
+    fn json(&self) -> Option<String> {
+        self.json
+            .get_or_init(|| serde_json::to_string(&self.stats).ok())
+            .clone()
+    }
+
+    fn packed(&self) -> Option<&(Packed, Arc<Vec<String>>)> {
+        self.packed
+            .get_or_init(|| {
+                let value = serde_json::to_value(&self.stats).ok()?;
+                let mut dictionary = self.dictionary.lock();
+                Some((dictionary.pack(value), dictionary.names()))
+            })
+            .as_ref()
+    }
+
+    /// The encoded stats and the dictionary names they may refer to.
+    fn binary(&self, encoding: Encoding) -> Option<(Bytes, &Arc<Vec<String>>)> {
+        let cell = match encoding {
+            Encoding::Json => return None,
+            Encoding::MsgPack => &self.msgpack,
+            Encoding::Cbor => &self.cbor,
+        };
+        let (packed, names) = self.packed()?;
+        let bytes = cell
+            .get_or_init(|| encoding.encode(&("stats", packed)).ok().map(Bytes::from))
+            .clone()?;
+        Some((bytes, names))
+    }
+}
+
+/// The subprotocol to answer with: the first one the client offers that we speak.
+fn negotiate(req: &HttpRequest) -> Option<(&str, Encoding)> {
+    req.headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .find_map(|p| Encoding::from_subprotocol(p).map(|e| (p, e)))
+}
 
 pub async fn ws_handler(
     req: HttpRequest,
@@ -22,17 +94,41 @@ pub async fn ws_handler(
         return Ok(HttpResponse::Unauthorized().finish());
     }
 
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let protocol = negotiate(&req);
+    let encoding = protocol.map_or(Encoding::Json, |(_, e)| e);
+    let (mut response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    if let Some((name, _)) = protocol
+        && let Ok(value) = HeaderValue::from_str(name)
+    {
+        response.headers_mut().insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+    }
     let mut rx = tx.subscribe();
 
     // Spawn task to forward broadcast messages to this client
     actix_rt::spawn(async move {
+        // Dictionary names this client has been sent
+        let mut known = 0;
         loop {
             tokio::select! {
                 msg = rx.recv() => {
                     match msg {
-                        Ok(text) => {
-                            if session.text(text).await.is_err() {
+                        Ok(frame) => {
+                            let sent = if encoding == Encoding::Json {
+                                let Some(json) = frame.json() else { continue };
+                                session.text(json).await
+                            } else {
+                                let Some((bytes, names)) = frame.binary(encoding) else { continue };
+                                if names.len() > known {
+                                    let update = ("keys", known, &names[known..]);
+                                    let Ok(keys) = encoding.encode(&update) else { continue };
+                                    if session.binary(keys).await.is_err() {
+                                        break;
+                                    }
+                                    known = names.len();
+                                }
+                                session.binary(bytes).await
+                            };
+                            if sent.is_err() {
                                 break;
                             }
                         }
@@ -20,6 +20,12 @@ export const HistoryChart: React.FC<HistoryChartProps> = ({ data, range }) => {
 
     const timestamps = data.map((p) => {
       const d = new Date(p.timestamp);
+      if (range === "month" || range === "year") {
+        return d.toLocaleDateString(undefined, {
+          month: "short",
+          day: "numeric",
+        });
+      }
       return range === "week"
         ? d.toLocaleDateString(undefined, {
             weekday: "short",
@@ -55,7 +61,7 @@ export const HistoryChart: React.FC<HistoryChartProps> = ({ data, range }) => {
           axisLabel: {
             color: "#888",
             fontSize: 10,
-            rotate: range === "week" ? 30 : 0,
+            rotate: range === "day" ? 0 : 30,
           },
           axisLine: { lineStyle: { color: "#333" } },
         },
@@ -96,6 +96,12 @@ export const MetricChart: React.FC<MetricChartProps> = ({
 
     const timestamps = data.map((p) => {
       const d = new Date(p.timestamp);
+      if (range === "month" || range === "year") {
+        return d.toLocaleDateString(undefined, {
+          month: "short",
+          day: "numeric",
+        });
+      }
       return range === "week"
         ? d.toLocaleDateString(undefined, {
             weekday: "short",
@@ -1,18 +1,64 @@
-import { useState, useCallback } from "react";
-import { HistoryResponse } from "../types";
+import { useState, useCallback, useRef } from "react";
+import { HistoryRange, HistoryResponse } from "../types";
 import { apiFetch } from "../api";
 
+// Roughly one point per pixel of chart width is all a chart can show
+function maxPoints(): number {
+  return Math.round(Math.min(1000, Math.max(200, window.innerWidth)));
+}
+
+// Replace the buckets a `since` poll returned again and append the new ones,
+// dropping old points so the chart keeps covering the same span.
+function merge(prev: HistoryResponse, update: HistoryResponse): HistoryResponse {
+  const since = Date.parse(prev.cursor ?? "");
+  const points = [
+    ...prev.points.filter((p) => Date.parse(p.timestamp) < since),
+    ...update.points,
+  ];
+  if (prev.points.length > 1 && points.length > 0) {
+    const span =
+      Date.parse(prev.points[prev.points.length - 1].timestamp) -
+      Date.parse(prev.points[0].timestamp);
+    const start = Date.parse(points[points.length - 1].timestamp) - span;
+    while (points.length > 1 && Date.parse(points[0].timestamp) < start) {
+      points.shift();
+    }
+  }
+  return {
+    ...update,
+    points,
+    gaps: [
+      ...prev.gaps.filter((g) => !update.gaps.some((u) => u.start === g.start)),
+      ...update.gaps,
+    ],
+    clock_flags: [
+      ...prev.clock_flags.filter((f) => !update.clock_flags.some((u) => u.start === f.start)),
+      ...update.clock_flags,
+    ],
+    annotations: [
+      ...prev.annotations.filter((a) => !update.annotations.some((u) => u.id === a.id)),
+      ...update.annotations,
+    ],
+  };
+}
+
 export function useHistory() {
   const [data, setData] = useState<HistoryResponse | null>(null);
   const [loading, setLoading] = useState(false);
+  const latest = useRef<{ range: HistoryRange; data: HistoryResponse } | null>(null);
 
-  const fetchHistory = useCallback(async (range: "day" | "week") => {
+  const fetchHistory = useCallback(async (range: HistoryRange) => {
     setLoading(true);
     try {
-      const res = await apiFetch(`/api/history?range=${range}`);
+      // Refreshes of the same range only fetch what changed
+      const prev = latest.current?.range === range ? latest.current.data : null;
+      const since = prev?.cursor ? `&since=${encodeURIComponent(prev.cursor)}` : "";
+      const res = await apiFetch(`/api/history?range=${range}&max_points=${maxPoints()}${since}`);
       if (res.ok) {
         const json: HistoryResponse = await res.json();
-        setData(json);
+        const next = prev && since ? merge(prev, json) : json;
+        latest.current = { range, data: next };
+        setData(next);
       }
     } catch (e) {
       console.error("Failed to fetch history:", e);
@@ -1,6 +1,7 @@
 import { useState, useEffect, useRef, useCallback } from "react";
 import { SystemStats } from "../types";
 import { getAccessToken, refreshAccessToken } from "../api";
+import { Packed, decode, expand } from "../lib/msgpack";
 
 export function useWebSocket() {
   const [stats, setStats] = useState<SystemStats | null>(null);
@@ -22,9 +23,13 @@ export function useWebSocket() {
     if (!token) return;
 
     const proto = window.location.protocol === "https:" ? "wss:" : "ws:";
+    // Binary frames name fields by index; the server sends new names first
     const ws = new WebSocket(
       `${proto}//${window.location.host}/ws?token=${encodeURIComponent(token)}`,
+      ["pi-dash.msgpack"],
     );
+    ws.binaryType = "arraybuffer";
+    const keys: string[] = [];
 
     ws.onopen = () => {
       if (isMounted.current) setConnected(true);
@@ -33,7 +38,17 @@ export function useWebSocket() {
     ws.onmessage = (event) => {
       if (!isMounted.current) return;
       try {
-        const data: SystemStats = JSON.parse(event.data);
+        let data: SystemStats;
+        if (typeof event.data === "string") {
+          data = JSON.parse(event.data);
+        } else {
+          const frame = decode(event.data) as Packed[];
+          if (frame[0] === "keys") {
+            keys.splice(frame[1] as number, Infinity, ...(frame[2] as string[]));
+            return;
+          }
+          data = expand(frame[1], keys) as SystemStats;
+        }
         setStats(data);
         setRecentStats((prev) => {
           const next = [...prev, data];
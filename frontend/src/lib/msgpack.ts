@@ -0,0 +1,79 @@
+// Minimal MessagePack decoder for the `pi-dash.msgpack` WebSocket protocol.
+// Covers the types the server sends: nil, bools, numbers, strings, arrays
+// and maps (map keys may be integers, so maps decode to Map).
+
+export type Packed = null | boolean | number | string | Packed[] | Map<Packed, Packed>;
+
+const utf8 = new TextDecoder();
+
+export function decode(buf: ArrayBuffer): Packed {
+  const view = new DataView(buf);
+  const bytes = new Uint8Array(buf);
+  let pos = 0;
+
+  const str = (len: number) => {
+    const s = utf8.decode(bytes.subarray(pos, pos + len));
+    pos += len;
+    return s;
+  };
+  const array = (len: number): Packed[] => {
+    const out: Packed[] = [];
+    for (let i = 0; i < len; i++) out.push(value());
+    return out;
+  };
+  const map = (len: number) => {
+    const out = new Map<Packed, Packed>();
+    for (let i = 0; i < len; i++) out.set(value(), value());
+    return out;
+  };
+  const read = <T>(size: number, get: (at: number) => T) => {
+    const v = get(pos);
+    pos += size;
+    return v;
+  };
+
+  function value(): Packed {
+    const b = bytes[pos++];
+    if (b <= 0x7f) return b;
+    if (b >= 0xe0) return b - 0x100;
+    if ((b & 0xf0) === 0x80) return map(b & 0x0f);
+    if ((b & 0xf0) === 0x90) return array(b & 0x0f);
+    if ((b & 0xe0) === 0xa0) return str(b & 0x1f);
+    switch (b) {
+      case 0xc0: return null;
+      case 0xc2: return false;
+      case 0xc3: return true;
+      case 0xca: return read(4, (at) => view.getFloat32(at));
+      case 0xcb: return read(8, (at) => view.getFloat64(at));
+      case 0xcc: return read(1, (at) => view.getUint8(at));
+      case 0xcd: return read(2, (at) => view.getUint16(at));
+      case 0xce: return read(4, (at) => view.getUint32(at));
+      case 0xcf: return read(8, (at) => Number(view.getBigUint64(at)));
+      case 0xd0: return read(1, (at) => view.getInt8(at));
+      case 0xd1: return read(2, (at) => view.getInt16(at));
+      case 0xd2: return read(4, (at) => view.getInt32(at));
+      case 0xd3: return read(8, (at) => Number(view.getBigInt64(at)));
+      case 0xd9: return str(read(1, (at) => view.getUint8(at)));
+      case 0xda: return str(read(2, (at) => view.getUint16(at)));
+      case 0xdb: return str(read(4, (at) => view.getUint32(at)));
+      case 0xdc: return array(read(2, (at) => view.getUint16(at)));
+      case 0xdd: return array(read(4, (at) => view.getUint32(at)));
+      case 0xde: return map(read(2, (at) => view.getUint16(at)));
+      case 0xdf: return map(read(4, (at) => view.getUint32(at)));
+    }
+    throw new Error(`Unsupported MessagePack type 0x${b.toString(16)}`);
+  }
+
+  return value();
+}
+
+// Turn a value whose map keys are dictionary indexes back into plain objects.
+export function expand(value: Packed, keys: string[]): unknown {
+  if (value instanceof Map) {
+    const out: Record<string, unknown> = {};
+    for (const [k, v] of value) out[typeof k === "number" ? keys[k] : String(k)] = expand(v, keys);
+    return out;
+  }
+  if (Array.isArray(value)) return value.map((v) => expand(v, keys));
+  return value;
+}
@@ -4,6 +4,7 @@ import { useHistory } from "../hooks/useHistory";
 import { StatCard } from "../components/StatCard";
 import { TempCard } from "../components/TempCard";
 import { HistoryCharts } from "../components/HistoryCharts";
+import { HistoryRange } from "../types";
 
 function formatBytes(bytes: number): string {
   if (bytes === 0) return "0 B";
@@ -24,7 +25,7 @@ export const Dashboard: React.FC<DashboardProps> = ({ onLogout }) => {
     loading: historyLoading,
     fetchHistory,
   } = useHistory();
-  const [historyRange, setHistoryRange] = useState<"day" | "week">("day");
+  const [historyRange, setHistoryRange] = useState<HistoryRange>("day");
   useEffect(() => {
     fetchHistory(historyRange);
     const interval = setInterval(() => fetchHistory(historyRange), 60_000);
@@ -141,6 +142,18 @@ export const Dashboard: React.FC<DashboardProps> = ({ onLogout }) => {
             >
               7 Days
             </button>
+            <button
+              className={`history-tab ${historyRange === "month" ? "active" : ""}`}
+              onClick={() => setHistoryRange("month")}
+            >
+              90 Days
+            </button>
+            <button
+              className={`history-tab ${historyRange === "year" ? "active" : ""}`}
+              onClick={() => setHistoryRange("year")}
+            >
+              2 Years
+            </button>
           </div>
         </div>
         {historyData && historyData.points.length > 0 && (
@@ -15,6 +15,7 @@ export interface MemoryStats {
   total_bytes: number;
   free_bytes: number;
   used_bytes: number;
+  swap_percent: number | null;
 }
 
 export interface DiskStats {
@@ -34,6 +35,52 @@ export interface DiskIoStats {
   write_bytes_per_sec: number;
 }
 
+export type ArrayHealth = "healthy" | "errors" | "degraded" | "failed" | "unknown";
+
+export interface ArrayOperation {
+  kind: string;
+  progress_percent: number | null;
+  eta_secs: number | null;
+}
+
+export interface ArrayDevice {
+  name: string;
+  state: string;
+  read_errors: number | null;
+  write_errors: number | null;
+  checksum_errors: number | null;
+}
+
+/// Software RAID (mdraid), btrfs or ZFS pool.
+export interface StorageArray {
+  kind: "mdraid" | "btrfs" | "zfs";
+  name: string;
+  level: string | null;
+  health: ArrayHealth;
+  state: string;
+  size_bytes: number | null;
+  used_bytes: number | null;
+  devices: ArrayDevice[];
+  operation: ArrayOperation | null;
+}
+
+export type MountState = "pending" | "healthy" | "stale" | "unreachable";
+
+/// NFS, SMB, sshfs or other network filesystem mount.
+export interface NetworkMount {
+  mount_point: string;
+  source: string;
+  fs_type: string;
+  state: MountState;
+  usage_percent: number | null;
+  used_bytes: number | null;
+  available_bytes: number | null;
+  total_bytes: number | null;
+  response_ms: number | null;
+  error: string | null;
+  checked_at: string | null;
+}
+
 export interface SystemStats {
   timestamp: string;
   cpu: CpuStats;
@@ -42,6 +89,8 @@ export interface SystemStats {
   network: NetworkStats;
   disk_io: DiskIoStats;
   temperatures: TempGroup[];
+  storage_arrays: StorageArray[];
+  network_mounts: NetworkMount[];
 }
 
 export interface HistoryPoint {
@@ -56,9 +105,43 @@ export interface HistoryPoint {
   disk_read_bytes_sec: number;
   disk_write_bytes_sec: number;
   temperatures: TempGroup[];
+  coverage?: number;
 }
 
+export type HistoryRange = "day" | "week" | "month" | "year";
+
+export type HistoryAggregate = "avg" | "min" | "max" | "last" | "p95" | "p99";
+
 export interface HistoryResponse {
   range: string;
+  agg: HistoryAggregate;
+  from?: string;
+  to?: string;
+  step_secs?: number;
   points: HistoryPoint[];
+  gaps: HistoryGap[];
+  clock_flags: ClockFlag[];
+  annotations: Annotation[];
+  cursor?: string;
+}
+
+export interface HistoryGap {
+  start: string;
+  end: string;
+  reason: "stopped" | "reboot" | "stalled";
+}
+
+export interface ClockFlag {
+  start: string;
+  end: string;
+  kind: "stepped_forward" | "stepped_back" | "restamped";
+}
+
+export interface Annotation {
+  id: number;
+  start: string;
+  end?: string;
+  text: string;
+  tags: string[];
+  source: "user" | "auto";
 }